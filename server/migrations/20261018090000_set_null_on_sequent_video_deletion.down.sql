@@ -0,0 +1,3 @@
+ALTER TABLE video DROP CONSTRAINT video_sequent_id_fkey;
+ALTER TABLE video ADD CONSTRAINT video_sequent_id_fkey 
+    FOREIGN KEY (sequent_id) REFERENCES video;
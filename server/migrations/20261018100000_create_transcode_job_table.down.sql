@@ -0,0 +1,3 @@
+DROP TABLE transcode_job;
+DROP TYPE transcode_job_status;
+DROP TYPE transcode_job_kind;
@@ -0,0 +1,26 @@
+CREATE TYPE transcode_job_kind AS ENUM ('hls_rendition', 'remux');
+
+CREATE TYPE transcode_job_status AS ENUM ('queued', 'running', 'completed', 'failed', 'cancelled');
+
+CREATE TABLE transcode_job (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    video_id BIGINT REFERENCES video ON DELETE CASCADE NOT NULL,
+    kind transcode_job_kind NOT NULL,
+    rendition TEXT,
+    status transcode_job_status NOT NULL DEFAULT 'queued',
+    progress DOUBLE PRECISION NOT NULL DEFAULT 0,
+    error TEXT,
+    request_id TEXT,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX transcode_job_queued_idx ON transcode_job (created_at, id) WHERE status = 'queued';
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON transcode_job
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,2 @@
+DROP TABLE subtitle;
+DROP TYPE subtitle_format;
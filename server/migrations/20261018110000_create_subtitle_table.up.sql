@@ -0,0 +1,26 @@
+CREATE TYPE subtitle_format AS ENUM ('srt', 'ass', 'vtt', 'mov_text');
+
+CREATE TABLE subtitle (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    video_id BIGINT REFERENCES video ON DELETE CASCADE NOT NULL,
+    path TEXT,
+    stream_index INTEGER,
+    format subtitle_format NOT NULL,
+    language TEXT,
+    title TEXT,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    is_forced BOOLEAN NOT NULL DEFAULT FALSE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- a subtitle is either a side-car file or a track embedded in the video file
+    CHECK ((path IS NULL) <> (stream_index IS NULL)),
+    UNIQUE (video_id, path),
+    UNIQUE (video_id, stream_index)
+);
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON subtitle
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
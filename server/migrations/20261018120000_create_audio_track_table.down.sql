@@ -0,0 +1,2 @@
+ALTER TABLE transcode_job DROP COLUMN audio_index;
+DROP TABLE audio_track;
@@ -0,0 +1,23 @@
+CREATE TABLE audio_track (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    video_id BIGINT REFERENCES video ON DELETE CASCADE NOT NULL,
+    stream_index INTEGER NOT NULL,
+    codec TEXT NOT NULL,
+    language TEXT,
+    title TEXT,
+    channels SMALLINT NOT NULL DEFAULT 0,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (video_id, stream_index)
+);
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON audio_track
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
+
+-- remux jobs may keep a single audio track
+ALTER TABLE transcode_job ADD COLUMN audio_index INTEGER;
@@ -0,0 +1,33 @@
+-- "user" is a reserved word in postgres, so the table name has to be quoted everywhere
+CREATE TABLE "user" (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    username TEXT UNIQUE NOT NULL,
+    password_hash TEXT NOT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON "user"
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
+
+CREATE TABLE session (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    user_id BIGINT REFERENCES "user" ON DELETE CASCADE NOT NULL,
+    -- only the hash of the token sent in the cookie is stored
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX session_user_id_idx ON session (user_id);
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON session
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
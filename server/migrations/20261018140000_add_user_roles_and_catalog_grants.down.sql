@@ -0,0 +1,7 @@
+DROP TABLE catalog_grant;
+
+ALTER TABLE "user" ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE "user" SET is_admin = role = 'admin';
+ALTER TABLE "user" DROP COLUMN role;
+
+DROP TYPE user_role;
@@ -0,0 +1,15 @@
+CREATE TYPE user_role AS ENUM ('admin', 'viewer', 'guest');
+
+ALTER TABLE "user" ADD COLUMN role user_role NOT NULL DEFAULT 'viewer';
+UPDATE "user" SET role = 'admin' WHERE is_admin;
+ALTER TABLE "user" DROP COLUMN is_admin;
+
+-- the catalogs a guest can see, other roles see every catalog
+CREATE TABLE catalog_grant (
+    user_id BIGINT REFERENCES "user" ON DELETE CASCADE NOT NULL,
+    catalog_id BIGINT REFERENCES catalog ON DELETE CASCADE NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, catalog_id)
+);
@@ -0,0 +1,22 @@
+CREATE TYPE token_scope AS ENUM ('read', 'write');
+
+CREATE TABLE api_token (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    user_id BIGINT REFERENCES "user" ON DELETE CASCADE NOT NULL,
+    name TEXT NOT NULL,
+    -- only the hash of the token is stored, the token itself is shown once when it is created
+    token_hash TEXT UNIQUE NOT NULL,
+    scope token_scope NOT NULL,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX api_token_user_id_idx ON api_token (user_id);
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON api_token
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
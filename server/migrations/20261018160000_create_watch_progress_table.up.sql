@@ -0,0 +1,19 @@
+CREATE TABLE watch_progress (
+    user_id BIGINT REFERENCES "user" ON DELETE CASCADE NOT NULL,
+    video_id BIGINT REFERENCES video ON DELETE CASCADE NOT NULL,
+    position BIGINT NOT NULL,
+    watched BOOLEAN NOT NULL DEFAULT FALSE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, video_id)
+);
+
+-- continue watching lists the most recently updated progress of a user
+CREATE INDEX watch_progress_user_id_updated_at_idx ON watch_progress (user_id, updated_at DESC);
+
+CREATE TRIGGER set_timestamp
+    BEFORE UPDATE ON watch_progress
+    FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
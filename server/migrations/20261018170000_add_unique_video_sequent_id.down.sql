@@ -0,0 +1 @@
+ALTER TABLE video DROP CONSTRAINT video_sequent_id_key;
@@ -0,0 +1,22 @@
+-- a video can be the sequent of only one other video, otherwise the previous one in a series is ambiguous,
+-- the videos already sharing a sequent are listed for an admin to unlink all but one of them
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(format('video %s follows videos %s', sequent_id, ids), '; ' ORDER BY sequent_id)
+    INTO duplicates
+    FROM (
+        SELECT sequent_id, string_agg(id::TEXT, ', ' ORDER BY id) AS ids
+        FROM video WHERE sequent_id IS NOT NULL
+        GROUP BY sequent_id HAVING COUNT(*) > 1
+    ) AS shared;
+
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'videos share their sequent: %', duplicates
+            USING HINT = 'set the sequent_id of all but one of these videos to NULL, then run the migration again';
+    END IF;
+END
+$$;
+
+ALTER TABLE video ADD CONSTRAINT video_sequent_id_key UNIQUE (sequent_id);
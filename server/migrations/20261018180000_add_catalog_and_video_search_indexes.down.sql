@@ -0,0 +1,3 @@
+DROP INDEX video_search_idx;
+DROP INDEX catalog_search_idx;
+DROP FUNCTION search_document;
@@ -0,0 +1,16 @@
+-- the names weigh more than the descriptions when ranking search results,
+-- the 'simple' configuration only lowercases the words: the library mixes languages, so no stemming or
+-- stop word list of a single language is applied, 'running' does not match 'run' and 'the' is searchable
+CREATE FUNCTION search_document(display_name TEXT, short_desc TEXT, long_desc TEXT)
+RETURNS tsvector
+LANGUAGE SQL
+IMMUTABLE
+PARALLEL SAFE
+AS $$
+    SELECT setweight(to_tsvector('simple', display_name), 'A')
+        || setweight(to_tsvector('simple', short_desc), 'B')
+        || setweight(to_tsvector('simple', long_desc), 'C')
+$$;
+
+CREATE INDEX catalog_search_idx ON catalog USING GIN (search_document(display_name, short_desc, long_desc));
+CREATE INDEX video_search_idx ON video USING GIN (search_document(display_name, short_desc, long_desc));
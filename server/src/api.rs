@@ -1,52 +1,103 @@
+pub mod artwork;
+pub mod auth;
+pub mod catalog;
+pub mod common;
+pub mod extract;
 pub mod health_check;
+pub mod hls;
+pub mod job;
+pub mod library;
+pub mod revision;
+pub mod search;
+pub mod series;
+pub mod stream;
+pub mod subtitle;
+#[cfg(test)]
+mod testing;
+pub mod token;
+pub mod user;
+pub mod validation;
+pub mod video;
+pub mod watch_progress;
 
 use std::{any::Any, sync::Arc};
 
 use axum::{
-    body::Body,
+    body::{boxed, Body, Bytes, HttpBody},
+    error_handling::HandleErrorLayer,
     extract::FromRef,
-    http::{header, Request, Response, StatusCode},
-    Router,
+    http::{Request, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    BoxError, Router,
 };
 use axum_extra::routing::RouterExt;
+use http_body::Limited;
 use sqlx::PgPool;
-use tower::ServiceBuilder;
+use tower::{timeout::TimeoutLayer, ServiceBuilder};
 use tower_http::{
     catch_panic::CatchPanicLayer,
-    compression::CompressionLayer,
+    compression::{
+        predicate::{DefaultPredicate, NotForContentType, Predicate},
+        CompressionLayer,
+    },
     cors::CorsLayer,
     limit::RequestBodyLimitLayer,
     request_id::MakeRequestUuid,
-    timeout::TimeoutLayer,
     trace::{DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer},
     LatencyUnit, ServiceBuilderExt,
 };
 use tracing::{info, instrument};
 
-use crate::{configuration::Configuration, logging::Logger, swagger::add_swagger_ui};
+use crate::{
+    configuration::Configuration, error::ApiError, jobs::JobQueue, logging::Logger,
+    scanner::Scanner, swagger::add_swagger_ui, transcoder::Transcoder,
+};
 
-use self::health_check::health_check;
+use self::{auth::CurrentUser, health_check::health_check};
 
 static REQUEST_ID_HEADER: &str = "x-request-id";
 static MISSING_REQUEST_ID: &str = "missing_request_id";
 
+/// The request body of the routes behind the body size limit.
+pub type LimitedBody = Limited<Body>;
+
 #[derive(Clone, FromRef)]
 pub struct AppState {
     config: Arc<Configuration>,
     database: PgPool,
+    scanner: Scanner,
+    transcoder: Transcoder,
+    jobs: JobQueue,
 }
 
 impl AppState {
-    pub fn new(config: Configuration, database: PgPool) -> Self {
+    pub fn new(
+        config: Configuration,
+        database: PgPool,
+        scanner: Scanner,
+        transcoder: Transcoder,
+        jobs: JobQueue,
+    ) -> Self {
         Self {
             config: Arc::new(config),
             database,
+            scanner,
+            transcoder,
+            jobs,
         }
     }
 }
 
 #[instrument(skip_all)]
-pub fn init(config: Configuration, database: PgPool, _: &Logger) -> anyhow::Result<Router> {
+pub fn init(
+    config: Configuration,
+    database: PgPool,
+    scanner: Scanner,
+    transcoder: Transcoder,
+    jobs: JobQueue,
+    _: &Logger,
+) -> anyhow::Result<Router> {
     info!("initializing router...");
     let latency_unit = LatencyUnit::Micros;
     let http_tracing = TraceLayer::new_for_http()
@@ -59,20 +110,31 @@ pub fn init(config: Configuration, database: PgPool, _: &Logger) -> anyhow::Resu
                 .map_or(MISSING_REQUEST_ID, |value| {
                     value.to_str().unwrap_or(MISSING_REQUEST_ID)
                 });
-            tracing::info_span!("http", path, method, request_id)
+            // the authenticated user and the API token are recorded once they are known
+            tracing::info_span!(
+                "http",
+                path,
+                method,
+                request_id,
+                user_id = tracing::field::Empty,
+                token_id = tracing::field::Empty
+            )
         })
         .on_request(DefaultOnRequest::new())
         .on_response(DefaultOnResponse::new().latency_unit(latency_unit))
         .on_failure(DefaultOnFailure::new().latency_unit(latency_unit));
 
-    let body_limit = RequestBodyLimitLayer::new(config.middlewares().body_size_limit());
-
     let allowed_origins = config.middlewares().allowed_origins()?;
     let cors = CorsLayer::permissive().allow_origin(allowed_origins);
 
-    let timeout = TimeoutLayer::new(config.middlewares().request_timeout());
+    // the timeout is answered like every other error of the API
+    let timeout = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(ApiError::from_middleware))
+        .layer(TimeoutLayer::new(config.middlewares().request_timeout()));
 
-    let compression = CompressionLayer::new();
+    // video responses are already compressed and must keep their byte ranges intact
+    let compression = CompressionLayer::new()
+        .compress_when(DefaultPredicate::new().and(NotForContentType::const_new("video/")));
 
     let panic_handling = CatchPanicLayer::custom(handle_panic);
 
@@ -80,21 +142,15 @@ pub fn init(config: Configuration, database: PgPool, _: &Logger) -> anyhow::Resu
         .set_x_request_id(MakeRequestUuid)
         .layer(http_tracing)
         .propagate_x_request_id()
-        .layer(body_limit)
         .layer(cors)
         .layer(timeout)
         .layer(compression)
         .layer(panic_handling);
 
     let enable_swagger_ui = config.swagger_ui();
-    let state = AppState::new(config, database);
-
-    let api = Router::new().typed_get(health_check);
+    let state = AppState::new(config, database, scanner, transcoder, jobs);
 
-    let router = Router::new()
-        .nest("/api", api)
-        .layer(middlewares)
-        .with_state(state);
+    let router = Router::new().nest("/api", routes(state)).layer(middlewares);
 
     let router = if enable_swagger_ui {
         add_swagger_ui(router)
@@ -106,8 +162,60 @@ pub fn init(config: Configuration, database: PgPool, _: &Logger) -> anyhow::Resu
     Ok(router)
 }
 
+/// The routes of the API with their authentication and body size limits.
+fn routes(state: AppState) -> Router {
+    let body_limit = RequestBodyLimitLayer::new(state.config.middlewares().body_size_limit());
+
+    // every route besides the health check and the authentication ones requires a session
+    let authenticated = Router::new()
+        .merge(catalog::router())
+        .merge(video::router())
+        .merge(stream::router())
+        .merge(hls::router())
+        .merge(artwork::router())
+        .merge(subtitle::router())
+        .merge(job::router())
+        .merge(library::router())
+        .merge(user::router())
+        .merge(token::router())
+        .merge(search::router())
+        .merge(series::router())
+        .merge(watch_progress::router())
+        .route_layer(middleware::from_extractor_with_state::<CurrentUser, _>(
+            state.clone(),
+        ));
+
+    let uploads = artwork::upload_router()
+        .route_layer(middleware::from_extractor_with_state::<CurrentUser, _>(
+            state.clone(),
+        ));
+
+    let api = Router::new()
+        .typed_get(health_check)
+        .merge(auth::router())
+        .merge(authenticated)
+        .layer(body_limit)
+        .layer(middleware::map_response(reject_large_body))
+        // posters are limited to their own, larger size while they are written
+        .merge(uploads);
+
+    api.with_state(state)
+}
+
+/// Answers the requests declaring a body above the size limit like every other error of the API.
+async fn reject_large_body<B>(response: Response<B>) -> Response
+where
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return ApiError::PayloadTooLarge.into_response();
+    }
+    response.map(boxed)
+}
+
 #[instrument(skip_all)]
-fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
+fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response {
     let details = if let Some(s) = err.downcast_ref::<String>() {
         s
     } else if let Some(s) = err.downcast_ref::<&str>() {
@@ -118,19 +226,5 @@ fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
 
     tracing::error!("Service panicked: {details}");
 
-    // TODO Change to error struct when ready
-    let body = serde_json::json!({
-        "error": {
-            "kind": "panic",
-            "message": details,
-        }
-    });
-    let body = serde_json::to_string(&body)
-        .expect("error in building response, handle_panic is probably misconfigured");
-
-    Response::builder()
-        .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Body::from(body))
-        .expect("error in building response, handle_panic is probably misconfigured")
+    ApiError::Panic.into_response()
 }
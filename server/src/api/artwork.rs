@@ -0,0 +1,443 @@
+use std::{path::Path, sync::Arc};
+
+use axum::{
+    body::{Body, HttpBody, StreamBody},
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt,
+};
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, instrument};
+use utoipa::IntoParams;
+
+use crate::{
+    artwork::{self, MAX_WIDTH},
+    configuration::Configuration,
+    crud::Entity,
+    error::ApiError,
+    model::{Catalog, EntityId, Video},
+    previews::{self, SpriteLayout},
+    transcoder::{temporary_path, Transcoder},
+};
+
+use super::{
+    auth::{AdminUser, CurrentUser},
+    extract::QueryParams,
+    stream::FileValidators,
+    AppState, LimitedBody,
+};
+
+const JPEG_MIME_TYPE: &str = "image/jpeg";
+const WEBVTT_MIME_TYPE: &str = "text/vtt";
+
+/// Thumbnails and sprite sheets only change with the video file, which gives them a new cache key anyway.
+const THUMBNAIL_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Posters can be replaced any time, so clients revalidate them with their entity tag.
+const POSTER_CACHE_CONTROL: &str = "public, no-cache";
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/thumbnail", rejection(ApiError))]
+pub struct ThumbnailEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/previews.vtt", rejection(ApiError))]
+pub struct PreviewTrackEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/previews/:sheet", rejection(ApiError))]
+pub struct PreviewSheetEndpoint {
+    id: EntityId,
+    sheet: String,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/catalogs/:id/poster", rejection(ApiError))]
+pub struct PosterEndpoint {
+    id: EntityId,
+}
+
+/// Query parameters of the image endpoints.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImageParams {
+    /// The width to resize the image to, keeping its aspect ratio, at most 1920. Images are never upscaled.
+    w: Option<u32>,
+}
+
+impl ImageParams {
+    fn width(&self) -> Result<Option<u32>, ApiError> {
+        match self.w {
+            Some(width) if width == 0 || width > MAX_WIDTH => Err(ApiError::BadRequest(format!(
+                "width must be between 1 and {MAX_WIDTH}"
+            ))),
+            width => Ok(width),
+        }
+    }
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(video_thumbnail)
+        .typed_get(preview_track)
+        .typed_get(preview_sheet)
+        .typed_get(catalog_poster)
+        .typed_delete(delete_catalog_poster)
+}
+
+/// The poster uploads, limited while streamed by the maximum poster size rather than the body size limit.
+pub fn upload_router() -> Router<AppState> {
+    Router::new().typed_put(upload_catalog_poster)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/thumbnail",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ImageParams,
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags of cached thumbnails")
+    ),
+    responses(
+        (status = 200, description = "Serves a frame of the video, extracting it first if needed", content_type = "image/jpeg"),
+        (status = 304, description = "The cached thumbnail is still valid"),
+        (status = 400, description = "Invalid width"),
+        (status = 404, description = "Video or its file not found")
+    )
+)]
+#[instrument(skip(pool, config, transcoder))]
+pub async fn video_thumbnail(
+    ThumbnailEndpoint { id }: ThumbnailEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    State(transcoder): State<Transcoder>,
+    QueryParams(params): QueryParams<ImageParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let width = params.width()?;
+    let video = user.find_video(&pool, id).await?;
+    if !fs::try_exists(video.path())
+        .await
+        .map_err(ApiError::from_io)?
+    {
+        return Err(ApiError::NotFound);
+    }
+    let path = transcoder
+        .thumbnail(&video, config.media().thumbnail_position(), width)
+        .await
+        .map_err(|e| {
+            error!("could not extract thumbnail: {e:#}");
+            ApiError::Internal
+        })?;
+    serve_image(&path, THUMBNAIL_CACHE_CONTROL, &headers).await
+}
+
+/// Finds a video with the layout of its previews, which requires a known duration.
+async fn find_previewable_video(
+    user: &CurrentUser,
+    pool: &PgPool,
+    config: &Configuration,
+    id: EntityId,
+) -> Result<(Video, SpriteLayout), ApiError> {
+    let video = user.find_video(pool, id).await?;
+    let layout = SpriteLayout::new(&video, config.media().preview_interval()).ok_or_else(|| {
+        ApiError::Conflict("the duration of the video is unknown, it has no previews".to_owned())
+    })?;
+    Ok((video, layout))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/previews.vtt",
+    tag = "video",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Maps the time ranges of the video to preview frames on sprite sheets", content_type = "text/vtt", body = String),
+        (status = 404, description = "Video not found"),
+        (status = 409, description = "The duration of the video is unknown")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn preview_track(
+    PreviewTrackEndpoint { id }: PreviewTrackEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (_, layout) = find_previewable_video(&user, &pool, &config, id).await?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(WEBVTT_MIME_TYPE),
+        )],
+        previews::webvtt(&layout),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/previews/{sheet}",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ("sheet" = String, Path, description = "Sprite sheet file name, e.g. `0.jpg`"),
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags of cached sprite sheets")
+    ),
+    responses(
+        (status = 200, description = "Serves a sprite sheet of preview frames, generating it first if needed", content_type = "image/jpeg"),
+        (status = 304, description = "The cached sprite sheet is still valid"),
+        (status = 404, description = "Video, its file or the sprite sheet not found"),
+        (status = 409, description = "The duration of the video is unknown")
+    )
+)]
+#[instrument(skip(pool, config, transcoder))]
+pub async fn preview_sheet(
+    PreviewSheetEndpoint { id, sheet }: PreviewSheetEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    State(transcoder): State<Transcoder>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let sheet = sheet
+        .strip_suffix(".jpg")
+        .and_then(|sheet| sheet.parse::<i64>().ok())
+        .ok_or(ApiError::NotFound)?;
+    let (video, layout) = find_previewable_video(&user, &pool, &config, id).await?;
+    if layout.sheet_bounds(sheet).is_none()
+        || !fs::try_exists(video.path())
+            .await
+            .map_err(ApiError::from_io)?
+    {
+        return Err(ApiError::NotFound);
+    }
+    let path = transcoder
+        .preview_sheet(&video, &layout, sheet)
+        .await
+        .map_err(|e| {
+            error!("could not generate sprite sheet: {e:#}");
+            ApiError::Internal
+        })?;
+    serve_image(&path, THUMBNAIL_CACHE_CONTROL, &headers).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/catalogs/{id}/poster",
+    tag = "catalog",
+    params(
+        ("id" = i64, Path, description = "Catalog id"),
+        ImageParams,
+        ("If-None-Match" = Option<String>, Header, description = "Entity tags of cached posters")
+    ),
+    responses(
+        (status = 200, description = "Serves the uploaded poster of the catalog, or the poster.jpg in its directory", content_type = "image/jpeg"),
+        (status = 304, description = "The cached poster is still valid"),
+        (status = 400, description = "Invalid width"),
+        (status = 404, description = "Catalog or its poster not found")
+    )
+)]
+#[instrument(skip(pool, config, transcoder))]
+pub async fn catalog_poster(
+    PosterEndpoint { id }: PosterEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    State(transcoder): State<Transcoder>,
+    QueryParams(params): QueryParams<ImageParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let width = params.width()?;
+    let catalog = Catalog::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    user.ensure_catalog_access(&pool, catalog.id).await?;
+    let source = artwork::find_poster(&config.media().data_dir(), &catalog)
+        .await
+        .map_err(ApiError::from_io)?
+        .ok_or(ApiError::NotFound)?;
+    let path = transcoder
+        .poster(catalog.id, &source, width)
+        .await
+        .map_err(|e| {
+            error!("could not resize poster: {e:#}");
+            ApiError::Internal
+        })?;
+    serve_image(&path, POSTER_CACHE_CONTROL, &headers).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/catalogs/{id}/poster",
+    tag = "catalog",
+    params(("id" = i64, Path, description = "Catalog id")),
+    request_body(content = String, description = "The JPEG image", content_type = "image/jpeg"),
+    responses(
+        (status = 204, description = "Stores the poster of the catalog, it replaces the poster.jpg in its directory"),
+        (status = 400, description = "The image is not a JPEG or it is too large"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Catalog not found")
+    )
+)]
+#[instrument(skip(pool, config, request))]
+pub async fn upload_catalog_poster(
+    PosterEndpoint { id }: PosterEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    request: Request<Body>,
+) -> Result<impl IntoResponse, ApiError> {
+    let catalog = Catalog::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    let output = artwork::uploaded_poster_path(&config.media().data_dir(), catalog.id);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).await.map_err(ApiError::Io)?;
+    }
+
+    let temporary = temporary_path(&output);
+    let result = write_poster(
+        &temporary,
+        request.into_body(),
+        config.media().max_poster_size(),
+    )
+    .await;
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temporary).await;
+        return Err(e);
+    }
+    fs::rename(&temporary, &output)
+        .await
+        .map_err(ApiError::Io)?;
+    info!("stored poster of catalog {id}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/catalogs/{id}/poster",
+    tag = "catalog",
+    params(("id" = i64, Path, description = "Catalog id")),
+    responses(
+        (status = 204, description = "Deletes the uploaded poster of the catalog, the poster.jpg in its directory is served again"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Catalog or uploaded poster not found")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn delete_catalog_poster(
+    PosterEndpoint { id }: PosterEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let catalog = Catalog::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    fs::remove_file(artwork::uploaded_poster_path(
+        &config.media().data_dir(),
+        catalog.id,
+    ))
+    .await
+    .map_err(ApiError::from_io)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Writes an uploaded poster to a file chunk by chunk, rejecting it once it exceeds the size limit or turns out not to be a JPEG.
+async fn write_poster(path: &Path, mut body: Body, max_size: u64) -> Result<(), ApiError> {
+    let mut file = File::create(path).await.map_err(ApiError::Io)?;
+    let mut written = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk =
+            chunk.map_err(|e| ApiError::BadRequest(format!("could not read the poster: {e}")))?;
+        if written == 0 && !chunk.is_empty() && !artwork::is_jpeg(&chunk) {
+            return Err(ApiError::BadRequest(
+                "the poster must be a JPEG image".to_owned(),
+            ));
+        }
+        written += chunk.len() as u64;
+        if written > max_size {
+            return Err(ApiError::BadRequest(format!(
+                "the poster must not be larger than {max_size} bytes"
+            )));
+        }
+        file.write_all(&chunk).await.map_err(ApiError::Io)?;
+    }
+    if written == 0 {
+        return Err(ApiError::BadRequest("the poster is empty".to_owned()));
+    }
+    file.flush().await.map_err(ApiError::Io)?;
+    Ok(())
+}
+
+/// Serves an image with caching headers, or an empty response if the client has it cached already.
+async fn serve_image(
+    path: &Path,
+    cache_control: &'static str,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let file = File::open(path).await.map_err(ApiError::from_io)?;
+    let metadata = file.metadata().await.map_err(ApiError::from_io)?;
+    let validators = FileValidators::new(metadata.len(), metadata.modified().ok());
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+    if let Ok(etag) = HeaderValue::from_str(&validators.etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    if let Some(Ok(last_modified)) = validators
+        .last_modified
+        .as_deref()
+        .map(HeaderValue::from_str)
+    {
+        response_headers.insert(header::LAST_MODIFIED, last_modified);
+    }
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|if_none_match| validators.matches_any(if_none_match));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(JPEG_MIME_TYPE),
+    );
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(metadata.len()));
+    Ok((response_headers, StreamBody::new(ReaderStream::new(file))).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn image_params_width_bounds() {
+        assert_eq!(ImageParams { w: None }.width().unwrap(), None);
+        assert_eq!(ImageParams { w: Some(320) }.width().unwrap(), Some(320));
+        assert_eq!(
+            ImageParams { w: Some(MAX_WIDTH) }.width().unwrap(),
+            Some(MAX_WIDTH)
+        );
+        assert!(ImageParams { w: Some(0) }.width().is_err());
+        assert!(ImageParams {
+            w: Some(MAX_WIDTH + 1)
+        }
+        .width()
+        .is_err());
+    }
+}
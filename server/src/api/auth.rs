@@ -0,0 +1,581 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json, Router,
+};
+use axum_extra::{
+    extract::cookie::{Cookie, CookieJar, SameSite},
+    routing::{RouterExt, TypedPath},
+};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::{error, instrument, Span};
+use utoipa::ToSchema;
+
+use crate::{
+    auth,
+    configuration::Configuration,
+    crud::Entity,
+    error::ApiError,
+    model::{ApiToken, CatalogGrant, EntityId, Session, TokenScope, User, UserRole, Video},
+};
+
+use super::{extract::JsonBody, AppState, LimitedBody};
+
+/// The name of the cookie holding the session token.
+pub const SESSION_COOKIE: &str = "binge_session";
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/auth/login")]
+pub struct LoginEndpoint;
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/auth/logout")]
+pub struct LogoutEndpoint;
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/me")]
+pub struct MeEndpoint;
+
+/// The user of the session or the API token the request was sent with, requests without either are rejected with `401`.
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub id: EntityId,
+    pub username: String,
+    pub role: UserRole,
+}
+
+impl From<User> for CurrentUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            role: user.role,
+        }
+    }
+}
+
+impl CurrentUser {
+    /// Rejects the request with `403` if the catalog is not granted to a restricted user.
+    pub async fn ensure_catalog_access(
+        &self,
+        pool: &PgPool,
+        catalog_id: EntityId,
+    ) -> Result<(), ApiError> {
+        if self.role.is_restricted() && !CatalogGrant::exists(pool, self.id, catalog_id).await? {
+            return Err(ApiError::Forbidden(format!(
+                "you have no access to catalog {catalog_id}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Finds a video, rejecting the request with `403` if its catalog is not granted to a restricted user.
+    pub async fn find_video(&self, pool: &PgPool, id: EntityId) -> Result<Video, ApiError> {
+        let video = Video::find(pool, id).await?.ok_or(ApiError::NotFound)?;
+        self.ensure_catalog_access(pool, video.catalog_id).await?;
+        Ok(video)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // already extracted by the authentication middleware
+        if let Some(user) = parts.extensions.get::<Self>() {
+            return Ok(user.clone());
+        }
+        let pool = PgPool::from_ref(state);
+        let user = if let Some(token) = bearer_token(&parts.headers) {
+            let token = ApiToken::use_token(&pool, &auth::hash_token(token))
+                .await?
+                .ok_or_else(|| {
+                    ApiError::Unauthorized("the API token is invalid or expired".to_owned())
+                })?;
+            if token.scope == TokenScope::Read && !parts.method.is_safe() {
+                return Err(ApiError::Forbidden("the API token is read-only".to_owned()));
+            }
+            Span::current().record("token_id", token.id);
+            User::find(&pool, token.user_id)
+                .await?
+                .ok_or_else(unauthenticated)?
+        } else {
+            let jar = CookieJar::from_headers(&parts.headers);
+            let Some(cookie) = jar.get(SESSION_COOKIE) else {
+                return Err(unauthenticated());
+            };
+            User::find_by_session(&pool, &auth::hash_token(cookie.value()))
+                .await?
+                .ok_or_else(unauthenticated)?
+        };
+        Span::current().record("user_id", user.id);
+        let user = Self::from(user);
+        parts.extensions.insert(user.clone());
+        Ok(user)
+    }
+}
+
+/// The token of an `Authorization: Bearer` header.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// A logged in admin, the requests of other users are rejected with `403`.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub CurrentUser);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = CurrentUser::from_request_parts(parts, state).await?;
+        if user.role != UserRole::Admin {
+            return Err(ApiError::Forbidden(
+                "only admins are allowed to do this".to_owned(),
+            ));
+        }
+        Ok(Self(user))
+    }
+}
+
+fn unauthenticated() -> ApiError {
+    ApiError::Unauthorized("a valid session or API token is required, log in first".to_owned())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserDto {
+    id: EntityId,
+    username: String,
+    role: UserRole,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+impl From<User> for UserDto {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            role: user.role,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginDto {
+    username: String,
+    #[schema(value_type = String, format = Password)]
+    password: Secret<String>,
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_post(login)
+        .typed_post(logout)
+        .typed_get(me)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginDto,
+    responses(
+        (status = 200, description = "Starts a session and sets its cookie", body = UserDto),
+        (status = 401, description = "Invalid username or password")
+    )
+)]
+#[instrument(skip(pool, config, jar))]
+pub async fn login(
+    _: LoginEndpoint,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    jar: CookieJar,
+    JsonBody(dto): JsonBody<LoginDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let invalid_credentials = || ApiError::Unauthorized("invalid username or password".to_owned());
+
+    let user = User::find_by_username(&pool, &dto.username).await?;
+    // unknown users are verified too, so the response time does not tell which users exist
+    let password_hash = user.as_ref().map_or_else(
+        || auth::DUMMY_PASSWORD_HASH.to_owned(),
+        |user| user.password_hash.clone(),
+    );
+    let verified = auth::verify_password(dto.password, password_hash)
+        .await
+        .map_err(|e| {
+            error!(
+                "could not verify the password of user '{}': {e:#}",
+                dto.username
+            );
+            ApiError::Internal
+        })?;
+    let Some(user) = user.filter(|_| verified) else {
+        return Err(invalid_credentials());
+    };
+
+    Session::delete_expired(&pool).await?;
+    let ttl = config.auth().session_ttl();
+    let token = auth::generate_token();
+    Session::create(
+        &pool,
+        user.id,
+        &auth::hash_token(&token),
+        OffsetDateTime::now_utc() + ttl,
+    )
+    .await?;
+
+    let cookie = Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(config.auth().secure_cookie())
+        .max_age(time::Duration::try_from(ttl).unwrap_or(time::Duration::MAX))
+        .finish();
+    Ok((jar.add(cookie), Json(UserDto::from(user))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    responses(
+        (status = 204, description = "Ends the current session and removes its cookie")
+    )
+)]
+#[instrument(skip(pool, jar))]
+pub async fn logout(
+    _: LogoutEndpoint,
+    State(pool): State<PgPool>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        Session::delete_by_token_hash(&pool, &auth::hash_token(cookie.value())).await?;
+    }
+    let removal = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    Ok((jar.remove(removal), StatusCode::NO_CONTENT))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Finds the logged in user", body = UserDto),
+        (status = 401, description = "Not logged in")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn me(
+    _: MeEndpoint,
+    current_user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = User::find(&pool, current_user.id)
+        .await?
+        .ok_or_else(unauthenticated)?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::testing::{Credentials, TestApp},
+        crud::{
+            api_token::CreateApiTokenRequest, catalog::CreateCatalogRequest,
+            video::CreateVideoRequest,
+        },
+        model::Catalog,
+    };
+    use axum::http::{HeaderValue, Method};
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    /// Creates two catalogs with a video each, returns the ids of the catalogs and of their videos.
+    async fn library(pool: &PgPool) -> [(EntityId, EntityId); 2] {
+        let mut library = [(0, 0); 2];
+        for entry in &mut library {
+            let catalog = Catalog::create(pool, Faker.fake::<CreateCatalogRequest>())
+                .await
+                .unwrap();
+            let video = Video::create(
+                pool,
+                CreateVideoRequest {
+                    catalog_id: catalog.id,
+                    sequent_id: None,
+                    ..Faker.fake()
+                },
+            )
+            .await
+            .unwrap();
+            *entry = (catalog.id, video.id);
+        }
+        library
+    }
+
+    fn ids(body: &Value) -> Vec<i64> {
+        body.as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["id"].as_i64().unwrap())
+            .collect()
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn bearer_token_found() {
+        let token = auth::generate_token();
+        assert_eq!(
+            bearer_token(&headers(&format!("Bearer {token}"))),
+            Some(token.as_str())
+        );
+        assert_eq!(
+            bearer_token(&headers(&format!("bearer {token}"))),
+            Some(token.as_str())
+        );
+    }
+
+    #[test]
+    fn bearer_token_missing() {
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+        let credentials: String = Faker.fake::<u64>().to_string();
+        assert_eq!(
+            bearer_token(&headers(&format!("Basic {credentials}"))),
+            None
+        );
+        assert_eq!(bearer_token(&headers("Bearer")), None);
+    }
+
+    #[sqlx::test]
+    async fn guest_sees_only_granted_catalogs(pool: PgPool) {
+        let [(granted, granted_video), (other, other_video)] = library(&pool).await;
+        let (guest, credentials) = TestApp::log_in(&pool, UserRole::Guest).await;
+        CatalogGrant::replace(&pool, guest.id, &[granted])
+            .await
+            .unwrap();
+        let app = TestApp::new(pool.clone());
+
+        let (status, catalogs) = app.send(Method::GET, "/catalogs", &credentials, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&catalogs), vec![granted]);
+        let (_, videos) = app.send(Method::GET, "/videos", &credentials, None).await;
+        assert_eq!(ids(&videos), vec![granted_video]);
+
+        for uri in [
+            format!("/catalogs/{other}"),
+            format!("/catalogs/{other}/videos"),
+            format!("/videos/{other_video}"),
+        ] {
+            let (status, body) = app.send(Method::GET, &uri, &credentials, None).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{uri}");
+            assert_eq!(body["error"]["code"], "forbidden", "{uri}");
+        }
+
+        // viewers are not restricted to grants
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let (_, catalogs) = app.send(Method::GET, "/catalogs", &credentials, None).await;
+        assert_eq!(ids(&catalogs), vec![granted, other]);
+    }
+
+    #[sqlx::test]
+    async fn guest_cannot_stream_ungranted_videos(pool: PgPool) {
+        let [(granted, granted_video), (_, other_video)] = library(&pool).await;
+        let (guest, credentials) = TestApp::log_in(&pool, UserRole::Guest).await;
+        CatalogGrant::replace(&pool, guest.id, &[granted])
+            .await
+            .unwrap();
+        let app = TestApp::new(pool);
+
+        for path in [
+            "stream",
+            "remux",
+            "thumbnail",
+            "subtitles",
+            "hls/master.m3u8",
+        ] {
+            let uri = format!("/videos/{other_video}/{path}");
+            let (status, _) = app.send(Method::GET, &uri, &credentials, None).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{uri}");
+        }
+        // the granted video is found, only its file is missing
+        let uri = format!("/videos/{granted_video}/stream");
+        let (status, _) = app.send(Method::GET, &uri, &credentials, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn admin_only_requests_forbidden_for_others(pool: PgPool) {
+        let [(catalog, video), _] = library(&pool).await;
+        let (user, _) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let app = TestApp::new(pool.clone());
+        let requests = [
+            (Method::POST, "/catalogs".to_owned()),
+            (Method::POST, "/catalogs/bulk".to_owned()),
+            (Method::DELETE, "/catalogs/bulk".to_owned()),
+            (Method::PUT, format!("/catalogs/{catalog}")),
+            (Method::DELETE, format!("/catalogs/{catalog}")),
+            (Method::DELETE, format!("/catalogs/{catalog}/poster")),
+            (Method::POST, "/videos".to_owned()),
+            (Method::POST, "/videos/bulk".to_owned()),
+            (Method::DELETE, "/videos/bulk".to_owned()),
+            (Method::PUT, format!("/videos/{video}")),
+            (Method::DELETE, format!("/videos/{video}")),
+            (Method::GET, "/users".to_owned()),
+            (Method::POST, "/users".to_owned()),
+            (Method::PUT, format!("/users/{}", user.id)),
+            (Method::DELETE, format!("/users/{}", user.id)),
+            (Method::PUT, format!("/users/{}/grants", user.id)),
+            (Method::POST, "/library/scan".to_owned()),
+            (Method::GET, "/jobs".to_owned()),
+            (Method::POST, "/jobs".to_owned()),
+        ];
+
+        for role in [UserRole::Guest, UserRole::Viewer] {
+            let (_, credentials) = TestApp::log_in(&pool, role).await;
+            for (method, uri) in &requests {
+                let (status, body) = app
+                    .send(method.clone(), uri, &credentials, Some(json!({})))
+                    .await;
+                assert_eq!(status, StatusCode::FORBIDDEN, "{role:?} {method} {uri}");
+                assert_eq!(
+                    body["error"]["code"], "forbidden",
+                    "{role:?} {method} {uri}"
+                );
+            }
+        }
+        assert_eq!(Catalog::count_all(&pool).await.unwrap(), 2);
+        assert_eq!(Video::count_all(&pool).await.unwrap(), 2);
+
+        let credentials = Credentials::Anonymous;
+        let (status, _) = app.send(Method::GET, "/users", &credentials, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    /// Creates an API token of a user, returns its id and the token.
+    async fn api_token(
+        pool: &PgPool,
+        user: &User,
+        scope: TokenScope,
+        expires_at: Option<OffsetDateTime>,
+    ) -> (EntityId, Credentials) {
+        let token = auth::generate_token();
+        let api_token = ApiToken::create(
+            pool,
+            CreateApiTokenRequest {
+                user_id: user.id,
+                name: Faker.fake(),
+                token_hash: auth::hash_token(&token),
+                scope,
+                expires_at,
+            },
+        )
+        .await
+        .unwrap();
+        (api_token.id, Credentials::Token(token))
+    }
+
+    #[sqlx::test]
+    async fn api_token_authenticates_its_user(pool: PgPool) {
+        let (user, _) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+        let (id, credentials) = api_token(&pool, &user, TokenScope::Read, Some(expires_at)).await;
+        let app = TestApp::new(pool.clone());
+
+        let (status, me) = app.send(Method::GET, "/me", &credentials, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(me["username"], user.username);
+        let tokens = ApiToken::find_all_by_user(&pool, user.id).await.unwrap();
+        assert_eq!(tokens[0].id, id);
+        assert!(tokens[0].last_used_at.is_some());
+    }
+
+    #[sqlx::test]
+    async fn expired_and_revoked_api_tokens_rejected(pool: PgPool) {
+        let (user, session) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let expired_at = OffsetDateTime::now_utc() - time::Duration::seconds(1);
+        let (_, expired) = api_token(&pool, &user, TokenScope::Write, Some(expired_at)).await;
+        let (id, revoked) = api_token(&pool, &user, TokenScope::Write, None).await;
+        let app = TestApp::new(pool);
+
+        let (status, _) = app.send(Method::GET, "/me", &revoked, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let uri = format!("/me/tokens/{id}");
+        let (status, _) = app.send(Method::DELETE, &uri, &session, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        for credentials in [expired, revoked, Credentials::Token(auth::generate_token())] {
+            let (status, body) = app.send(Method::GET, "/me", &credentials, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["error"]["code"], "unauthorized");
+        }
+    }
+
+    #[sqlx::test]
+    async fn read_api_token_rejected_on_unsafe_methods(pool: PgPool) {
+        let (user, _) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let (id, read) = api_token(&pool, &user, TokenScope::Read, None).await;
+        let (_, write) = api_token(&pool, &user, TokenScope::Write, None).await;
+        let app = TestApp::new(pool.clone());
+        let dto = json!({ "name": "script", "scope": "read" });
+
+        let (status, _) = app.send(Method::GET, "/me/tokens", &read, None).await;
+        assert_eq!(status, StatusCode::OK);
+        for (method, uri) in [
+            (Method::POST, "/me/tokens".to_owned()),
+            (Method::DELETE, format!("/me/tokens/{id}")),
+            (Method::POST, "/library/scan".to_owned()),
+        ] {
+            let (status, body) = app
+                .send(method.clone(), &uri, &read, Some(dto.clone()))
+                .await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{method} {uri}");
+            assert_eq!(body["error"]["code"], "forbidden", "{method} {uri}");
+        }
+        assert_eq!(
+            ApiToken::find_all_by_user(&pool, user.id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let (status, _) = app
+            .send(Method::POST, "/me/tokens", &write, Some(dto))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    configuration::Configuration,
+    crud::{
+        catalog::{CatalogFiltering, CatalogOrdering, CreateCatalogRequest, UpdateCatalogRequest},
+        Counted, Entity,
+    },
+    error::ApiError,
+    model::{Catalog, EntityId},
+};
+
+use super::{
+    auth::{AdminUser, CurrentUser},
+    common::{BulkDeleteDto, BulkDeleteResponse, ListParams},
+    extract::{JsonBody, QueryParams},
+    revision::{etag_header, IfMatch},
+    validation::{validate_descriptions, ValidJson, Validate, Validator, MAX_PATH_LENGTH},
+    AppState, LimitedBody,
+};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/catalogs")]
+pub struct CatalogsEndpoint;
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/catalogs/bulk")]
+pub struct CatalogsBulkEndpoint;
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/catalogs/:id", rejection(ApiError))]
+pub struct CatalogEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CatalogDto {
+    id: EntityId,
+    path: String,
+    display_name: String,
+    short_desc: String,
+    long_desc: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+impl From<Catalog> for CatalogDto {
+    fn from(catalog: Catalog) -> Self {
+        Self {
+            id: catalog.id,
+            path: catalog.path,
+            display_name: catalog.display_name,
+            short_desc: catalog.short_desc,
+            long_desc: catalog.long_desc,
+            created_at: catalog.created_at,
+            updated_at: catalog.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateCatalogDto {
+    path: String,
+    display_name: String,
+    short_desc: String,
+    long_desc: String,
+}
+
+impl From<CreateCatalogDto> for CreateCatalogRequest {
+    fn from(dto: CreateCatalogDto) -> Self {
+        Self {
+            path: dto.path,
+            display_name: dto.display_name,
+            short_desc: dto.short_desc,
+            long_desc: dto.long_desc,
+        }
+    }
+}
+
+#[async_trait]
+impl Validate for CreateCatalogDto {
+    async fn validate(&self, validator: &mut Validator, state: &AppState) -> Result<(), ApiError> {
+        let roots = state.config.library().roots();
+        validator
+            .length("path", &self.path, 1..=MAX_PATH_LENGTH)
+            .path_within("path", &self.path, &roots);
+        validate_descriptions(
+            validator,
+            &self.display_name,
+            &self.short_desc,
+            &self.long_desc,
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateCatalogDto {
+    display_name: String,
+    short_desc: String,
+    long_desc: String,
+}
+
+impl UpdateCatalogDto {
+    fn into_request(
+        self,
+        id: EntityId,
+        expected_updated_at: Option<Vec<OffsetDateTime>>,
+    ) -> UpdateCatalogRequest {
+        UpdateCatalogRequest {
+            id,
+            display_name: self.display_name,
+            short_desc: self.short_desc,
+            long_desc: self.long_desc,
+            expected_updated_at,
+        }
+    }
+}
+
+#[async_trait]
+impl Validate for UpdateCatalogDto {
+    async fn validate(&self, validator: &mut Validator, _: &AppState) -> Result<(), ApiError> {
+        validate_descriptions(
+            validator,
+            &self.display_name,
+            &self.short_desc,
+            &self.long_desc,
+        );
+        Ok(())
+    }
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(list_catalogs)
+        .typed_post(create_catalog)
+        .typed_post(create_catalogs)
+        .typed_delete(delete_catalogs)
+        .typed_get(find_catalog)
+        .typed_put(update_catalog)
+        .typed_delete(delete_catalog)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/catalogs",
+    tag = "catalog",
+    params(ListParams),
+    responses(
+        (status = 200, description = "Lists catalogs, in a page when paged by page, size or cursor", body = CatalogListing),
+        (status = 400, description = "Invalid filtering, ordering or pagination")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn list_catalogs(
+    _: CatalogsEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    QueryParams(params): QueryParams<ListParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let filters = params.filters::<CatalogFiltering>()?;
+    let ordering = params.ordering::<CatalogOrdering>()?;
+    let pagination =
+        params.keyed_pagination::<Catalog>(&ordering, config.listing().max_page_size())?;
+    let Counted {
+        items: mut catalogs,
+        total,
+    } = if user.role.is_restricted() {
+        Catalog::find_all_granted(&pool, user.id, filters, ordering.clone(), pagination).await?
+    } else {
+        Catalog::find_all_counted(&pool, filters, ordering.clone(), pagination).await?
+    };
+    let cursors = params.cursors(&ordering, &mut catalogs);
+    let catalogs = catalogs
+        .into_iter()
+        .map(CatalogDto::from)
+        .collect::<Vec<_>>();
+    Ok(params.listing(catalogs, total, cursors))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/catalogs",
+    tag = "catalog",
+    request_body = CreateCatalogDto,
+    responses(
+        (status = 201, description = "Creates a catalog", body = CatalogDto),
+        (status = 403, description = "Not an admin"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn create_catalog(
+    _: CatalogsEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    ValidJson(dto): ValidJson<CreateCatalogDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let catalog = Catalog::create(&pool, dto.into()).await?;
+    Ok((StatusCode::CREATED, Json(CatalogDto::from(catalog))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/catalogs/bulk",
+    tag = "catalog",
+    request_body = [CreateCatalogDto],
+    responses(
+        (status = 201, description = "Creates multiple catalogs at once", body = [CatalogDto]),
+        (status = 403, description = "Not an admin"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn create_catalogs(
+    _: CatalogsBulkEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    ValidJson(dtos): ValidJson<Vec<CreateCatalogDto>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let requests = dtos.into_iter().map(CreateCatalogRequest::from).collect();
+    let catalogs = Catalog::create_many(&pool, requests).await?;
+    let catalogs = catalogs
+        .into_iter()
+        .map(CatalogDto::from)
+        .collect::<Vec<_>>();
+    Ok((StatusCode::CREATED, Json(catalogs)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/catalogs/bulk",
+    tag = "catalog",
+    request_body = BulkDeleteDto,
+    responses(
+        (status = 200, description = "Deletes multiple catalogs at once", body = BulkDeleteResponse),
+        (status = 403, description = "Not an admin")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_catalogs(
+    _: CatalogsBulkEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    JsonBody(dto): JsonBody<BulkDeleteDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = Catalog::delete_many(&pool, dto.ids).await?;
+    Ok(Json(BulkDeleteResponse { deleted }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/catalogs/{id}",
+    tag = "catalog",
+    params(("id" = i64, Path, description = "Catalog id")),
+    responses(
+        (status = 200, description = "Finds a catalog", body = CatalogDto,
+            headers(("ETag" = String, description = "The revision of the catalog"))),
+        (status = 404, description = "Catalog not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_catalog(
+    CatalogEndpoint { id }: CatalogEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let catalog = Catalog::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    user.ensure_catalog_access(&pool, catalog.id).await?;
+    Ok((
+        etag_header(catalog.updated_at),
+        Json(CatalogDto::from(catalog)),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/catalogs/{id}",
+    tag = "catalog",
+    params(
+        ("id" = i64, Path, description = "Catalog id"),
+        ("If-Match" = Option<String>, Header, description = "Only updates the catalog at the revision of one of these ETags")
+    ),
+    request_body = UpdateCatalogDto,
+    responses(
+        (status = 200, description = "Updates a catalog", body = CatalogDto,
+            headers(("ETag" = String, description = "The new revision of the catalog"))),
+        (status = 400, description = "The If-Match header is invalid"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Catalog not found"),
+        (status = 412, description = "The catalog changed since the revision of the If-Match header"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn update_catalog(
+    CatalogEndpoint { id }: CatalogEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    if_match: IfMatch,
+    ValidJson(dto): ValidJson<UpdateCatalogDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(catalog) = Catalog::update(&pool, dto.into_request(id, if_match.0.clone())).await?
+    else {
+        return Err(if_match.rejection(Catalog::find(&pool, id).await?.is_some()));
+    };
+    Ok((
+        etag_header(catalog.updated_at),
+        Json(CatalogDto::from(catalog)),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/catalogs/{id}",
+    tag = "catalog",
+    params(
+        ("id" = i64, Path, description = "Catalog id"),
+        ("If-Match" = Option<String>, Header, description = "Only deletes the catalog at the revision of one of these ETags")
+    ),
+    responses(
+        (status = 204, description = "Deletes a catalog"),
+        (status = 400, description = "The If-Match header is invalid"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Catalog not found"),
+        (status = 412, description = "The catalog changed since the revision of the If-Match header")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_catalog(
+    CatalogEndpoint { id }: CatalogEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    if_match: IfMatch,
+) -> Result<impl IntoResponse, ApiError> {
+    if Catalog::delete_unless_changed(&pool, id, if_match.0.as_deref()).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(if_match.rejection(Catalog::find(&pool, id).await?.is_some()))
+    }
+}
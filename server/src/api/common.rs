@@ -0,0 +1,715 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    crud::{
+        Column, Condition, Direction, Filter, FilterField, Keyed, Keyset, OrderBy, Pagination,
+        ValueKind,
+    },
+    error::ApiError,
+    model::EntityId,
+};
+
+use super::{auth::UserDto, catalog::CatalogDto, job::JobDto, video::VideoDto};
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+
+/// Query parameters accepted by every listing endpoint.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    /// The page to return, starting from 1.
+    page: Option<u64>,
+    /// The number of items on a page, at most the configured maximum page size.
+    size: Option<u64>,
+    /// Comma separated list of fields to order by, prefix a field with `-` for descending order.
+    #[param(example = "display_name,-created_at")]
+    sort: Option<String>,
+    /// Comma separated list of conditions as `field:operator:value`, all of them have to match.
+    /// The operators are `eq`, `ne`, `gt`, `gte`, `lt`, `lte`, `in` with values separated by `|`,
+    /// `like` with `%` matching any text, and `null` and `not_null` without a value.
+    #[param(example = "height:gte:1080,duration:lt:1800,catalog_id:in:4|7")]
+    filter: Option<String>,
+    /// Pages by cursor instead of by page number, the rows inserted meanwhile do not shift the pages.
+    /// Empty for the first page, then the `next_cursor` or `prev_cursor` of the previous response.
+    cursor: Option<String>,
+}
+
+impl ListParams {
+    pub fn pagination(&self, max_size: u64) -> Result<Option<Pagination>, ApiError> {
+        if self.page.is_none() && self.size.is_none() {
+            return Ok(None);
+        }
+        paginate(self.page, self.size, max_size).map(Some)
+    }
+
+    /// The pagination of a listing of keyed entities, by cursor if one is given, otherwise by page.
+    pub fn keyed_pagination<E: Keyed>(
+        &self,
+        ordering: &[OrderBy<E::Ordering>],
+        max_size: u64,
+    ) -> Result<Option<Pagination>, ApiError> {
+        let Some(cursor) = &self.cursor else {
+            return self.pagination(max_size);
+        };
+        if self.page.is_some() {
+            return Err(ApiError::BadRequest(
+                "cursor and page cannot be combined".to_owned(),
+            ));
+        }
+        let size = self.size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let keyset = decode_keyset(cursor, ordering, E::key_kind)?;
+        Ok(Some(Pagination::keyset(size, keyset, max_size)?))
+    }
+
+    /// Drops the extra row fetched by cursor, returns the cursors of the neighbouring pages.
+    /// Returns `None` when the listing is not paged by cursor.
+    pub fn cursors<E: Keyed>(
+        &self,
+        ordering: &[OrderBy<E::Ordering>],
+        rows: &mut Vec<E>,
+    ) -> Option<Cursors> {
+        let cursor = Cursor::decode(self.cursor.as_deref()?)?;
+        let size = self.size.unwrap_or(DEFAULT_PAGE_SIZE) as usize;
+        let has_more = rows.len() > size;
+        if has_more {
+            if cursor.backward {
+                rows.remove(0);
+            } else {
+                rows.pop();
+            }
+        }
+        // coming from another page, there is always a page to go back to
+        let has_next = if cursor.backward {
+            cursor.id.is_some()
+        } else {
+            has_more
+        };
+        let has_prev = if cursor.backward {
+            has_more
+        } else {
+            cursor.id.is_some()
+        };
+        let encode = |row: Option<&E>, backward| {
+            let row = row?;
+            let cursor = Cursor {
+                sort: sort_signature(ordering),
+                keys: ordering
+                    .iter()
+                    .map(|order_by| row.key(order_by.field()).map(|key| key.to_string()))
+                    .collect(),
+                id: Some(row.id()),
+                backward,
+            };
+            Some(cursor.encode())
+        };
+        Some(Cursors {
+            next_cursor: if has_next {
+                encode(rows.last(), false)
+            } else {
+                None
+            },
+            prev_cursor: if has_prev {
+                encode(rows.first(), true)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Responds with the items, in a page with the counts when paged by page or size,
+    /// in a page with the cursors when paged by cursor, otherwise as they are.
+    pub fn listing<T: Serialize>(
+        &self,
+        items: Vec<T>,
+        total: i64,
+        cursors: Option<Cursors>,
+    ) -> Response {
+        let listing = if let Some(cursors) = cursors {
+            Listing::CursorPage(CursorPage { items, cursors })
+        } else if self.page.is_none() && self.size.is_none() {
+            Listing::Items(items)
+        } else {
+            let size = self.size.unwrap_or(DEFAULT_PAGE_SIZE);
+            let total = u64::try_from(total).unwrap_or_default();
+            Listing::Page(Page {
+                items,
+                page: self.page.unwrap_or(1),
+                size,
+                total,
+                total_pages: total.div_ceil(size),
+            })
+        };
+        Json(listing).into_response()
+    }
+
+    pub fn ordering<T>(&self) -> Result<Vec<OrderBy<T>>, ApiError>
+    where
+        T: Column + for<'de> Deserialize<'de>,
+    {
+        let Some(sort) = &self.sort else {
+            return Ok(vec![]);
+        };
+        sort.split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let (field, direction) = field
+                    .strip_prefix('-')
+                    .map_or((field, Direction::Asc), |field| (field, Direction::Desc));
+                parse_field(field).map(|field| OrderBy::new(field, direction))
+            })
+            .collect()
+    }
+
+    pub fn filters<T>(&self) -> Result<Vec<Filter<T>>, ApiError>
+    where
+        T: FilterField + for<'de> Deserialize<'de>,
+    {
+        let Some(filter) = &self.filter else {
+            return Ok(vec![]);
+        };
+        filter
+            .split(',')
+            .map(str::trim)
+            .filter(|filter| !filter.is_empty())
+            .map(parse_filter)
+            .collect()
+    }
+}
+
+/// The response of a listing endpoint, its shape depends on the pagination of the request.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Listing<T> {
+    /// Every item, without pagination.
+    Items(Vec<T>),
+    /// Paged by page or size.
+    Page(Page<T>),
+    /// Paged by cursor.
+    CursorPage(CursorPage<T>),
+}
+
+/// Declares the schema of a [`Listing`] of `$dto`, utoipa does not substitute generic enum variants.
+macro_rules! listing_schema {
+    ($name:ident, $dto:ident, $page:ident, $cursor_page:ident) => {
+        /// Every item without pagination, or a page of them when paged by page, size or cursor.
+        #[derive(Serialize, ToSchema)]
+        #[serde(untagged)]
+        #[allow(dead_code)]
+        pub enum $name {
+            Items(Vec<$dto>),
+            Page($page),
+            CursorPage($cursor_page),
+        }
+    };
+}
+
+listing_schema!(CatalogListing, CatalogDto, CatalogPage, CatalogCursorPage);
+listing_schema!(VideoListing, VideoDto, VideoPage, VideoCursorPage);
+listing_schema!(UserListing, UserDto, UserPage, UserCursorPage);
+listing_schema!(JobListing, JobDto, JobPage, JobCursorPage);
+
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    CatalogPage = Page<CatalogDto>,
+    VideoPage = Page<VideoDto>,
+    UserPage = Page<UserDto>,
+    JobPage = Page<JobDto>
+)]
+pub struct Page<T> {
+    items: Vec<T>,
+    /// The page of the items, starting from 1.
+    page: u64,
+    /// The number of items on a page.
+    size: u64,
+    /// The number of items on all the pages.
+    total: u64,
+    total_pages: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Cursors {
+    /// Continues with the following page, missing on the last page.
+    next_cursor: Option<String>,
+    /// Goes back to the preceding page, missing on the first page.
+    prev_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    CatalogCursorPage = CursorPage<CatalogDto>,
+    VideoCursorPage = CursorPage<VideoDto>,
+    UserCursorPage = CursorPage<UserDto>,
+    JobCursorPage = CursorPage<JobDto>
+)]
+pub struct CursorPage<T> {
+    items: Vec<T>,
+    #[serde(flatten)]
+    cursors: Cursors,
+}
+
+/// The row a page continues from, opaque to the clients.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Cursor {
+    /// The ordering the keys belong to, the cursor is rejected with another ordering.
+    sort: String,
+    keys: Vec<Option<String>>,
+    /// The id of the row, the first page without it.
+    id: Option<EntityId>,
+    backward: bool,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor is serializable");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// An empty cursor starts from the first page.
+    fn decode(cursor: &str) -> Option<Self> {
+        if cursor.is_empty() {
+            return Some(Self {
+                sort: String::new(),
+                keys: vec![],
+                id: None,
+                backward: false,
+            });
+        }
+        let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+fn sort_signature<T: Column>(ordering: &[OrderBy<T>]) -> String {
+    ordering
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn decode_keyset<T: Column>(
+    cursor: &str,
+    ordering: &[OrderBy<T>],
+    key_kind: impl Fn(&T) -> ValueKind,
+) -> Result<Keyset, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("invalid cursor '{cursor}'"));
+    let cursor = Cursor::decode(cursor).ok_or_else(invalid)?;
+    let Some(id) = cursor.id else {
+        return Ok(Keyset {
+            after: None,
+            backward: cursor.backward,
+        });
+    };
+    if cursor.sort != sort_signature(ordering) {
+        return Err(ApiError::BadRequest(
+            "the cursor belongs to another ordering".to_owned(),
+        ));
+    }
+    if cursor.keys.len() != ordering.len() {
+        return Err(invalid());
+    }
+    let keys = ordering
+        .iter()
+        .zip(cursor.keys)
+        .map(|(order_by, key)| match key {
+            Some(key) => key_kind(order_by.field()).parse(&key).map(Some),
+            None => Some(None),
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)?;
+    Ok(Keyset {
+        after: Some((keys, id)),
+        backward: cursor.backward,
+    })
+}
+
+/// Validates the page and the size of a listing, defaulting to the first page.
+pub fn paginate(
+    page: Option<u64>,
+    size: Option<u64>,
+    max_size: u64,
+) -> Result<Pagination, ApiError> {
+    let page = page.unwrap_or(1);
+    let size = size.unwrap_or(DEFAULT_PAGE_SIZE);
+    Ok(Pagination::new(size, page, max_size)?)
+}
+
+fn parse_field<T: for<'de> Deserialize<'de>>(field: &str) -> Result<T, ApiError> {
+    let deserializer: serde::de::value::StrDeserializer<'_, serde::de::value::Error> =
+        field.into_deserializer();
+    T::deserialize(deserializer)
+        .map_err(|_| ApiError::BadRequest(format!("cannot order by unknown field '{field}'")))
+}
+
+fn parse_filter<T>(filter: &str) -> Result<Filter<T>, ApiError>
+where
+    T: FilterField + for<'de> Deserialize<'de>,
+{
+    let mut parts = filter.splitn(3, ':');
+    let name = parts.next().unwrap_or_default().trim();
+    let operator = parts.next().unwrap_or_default().trim();
+    let value = parts.next();
+
+    let deserializer: serde::de::value::StrDeserializer<'_, serde::de::value::Error> =
+        name.into_deserializer();
+    let field = T::deserialize(deserializer)
+        .map_err(|_| ApiError::BadRequest(format!("cannot filter by unknown field '{name}'")))?;
+    let kind = field.kind();
+    let parse = |value: &str| {
+        kind.parse(value).ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "invalid value '{value}' for field '{name}', expected {kind:?}"
+            ))
+        })
+    };
+    let condition = match (operator, value) {
+        ("null", None) => Condition::IsNull,
+        ("not_null", None) => Condition::IsNotNull,
+        ("eq", Some(value)) => Condition::Eq(parse(value)?),
+        ("ne", Some(value)) => Condition::Ne(parse(value)?),
+        ("gt", Some(value)) => Condition::Gt(parse(value)?),
+        ("gte", Some(value)) => Condition::Gte(parse(value)?),
+        ("lt", Some(value)) => Condition::Lt(parse(value)?),
+        ("lte", Some(value)) => Condition::Lte(parse(value)?),
+        ("in", Some(values)) => {
+            Condition::In(values.split('|').map(parse).collect::<Result<_, _>>()?)
+        }
+        ("like", Some(pattern)) if kind == ValueKind::Text => Condition::Like(pattern.to_owned()),
+        _ => {
+            return Err(ApiError::BadRequest(format!(
+                "invalid filter '{filter}', expected 'field:operator:value'"
+            )))
+        }
+    };
+    Ok(Filter::new(field, condition))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BulkDeleteDto {
+    pub ids: Vec<EntityId>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkDeleteResponse {
+    pub deleted: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::testing::TestApp,
+        crud::{catalog::CreateCatalogRequest, Entity, FilterValue},
+        model::{Catalog, UserRole},
+    };
+    use axum::http::{Method, StatusCode};
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum TestOrdering {
+        DisplayName,
+        CreatedAt,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum TestFiltering {
+        DisplayName,
+        Height,
+    }
+
+    impl Column for TestOrdering {
+        fn column(&self) -> &'static str {
+            match self {
+                Self::DisplayName => "display_name",
+                Self::CreatedAt => "created_at",
+            }
+        }
+    }
+
+    impl Column for TestFiltering {
+        fn column(&self) -> &'static str {
+            match self {
+                Self::DisplayName => "display_name",
+                Self::Height => "height",
+            }
+        }
+    }
+
+    impl FilterField for TestFiltering {
+        fn kind(&self) -> ValueKind {
+            match self {
+                Self::DisplayName => ValueKind::Text,
+                Self::Height => ValueKind::Integer,
+            }
+        }
+    }
+
+    const MAX_PAGE_SIZE: u64 = 100;
+
+    fn params(page: Option<u64>, size: Option<u64>, sort: Option<&str>) -> ListParams {
+        ListParams {
+            page,
+            size,
+            sort: sort.map(ToOwned::to_owned),
+            filter: None,
+            cursor: None,
+        }
+    }
+
+    fn filter_params(filter: &str) -> ListParams {
+        ListParams {
+            filter: Some(filter.to_owned()),
+            ..ListParams::default()
+        }
+    }
+
+    fn test_key_kind(field: &TestOrdering) -> ValueKind {
+        match field {
+            TestOrdering::DisplayName => ValueKind::Text,
+            TestOrdering::CreatedAt => ValueKind::Timestamp,
+        }
+    }
+
+    fn test_ordering() -> Vec<OrderBy<TestOrdering>> {
+        vec![
+            OrderBy::new(TestOrdering::DisplayName, Direction::Asc),
+            OrderBy::new(TestOrdering::CreatedAt, Direction::Desc),
+        ]
+    }
+
+    #[test]
+    fn pagination_missing_params_none() {
+        let pagination = params(None, None, None).pagination(MAX_PAGE_SIZE).unwrap();
+        assert_eq!(pagination, None);
+    }
+
+    #[test]
+    fn pagination_only_page_default_size() {
+        let page = Faker.fake::<u8>().max(1).into();
+        let pagination = params(Some(page), None, None)
+            .pagination(MAX_PAGE_SIZE)
+            .unwrap();
+        assert_eq!(
+            pagination,
+            Pagination::new(DEFAULT_PAGE_SIZE, page, MAX_PAGE_SIZE).ok()
+        );
+    }
+
+    #[test]
+    fn pagination_only_size_first_page() {
+        let size = (1..=MAX_PAGE_SIZE).fake();
+        let pagination = params(None, Some(size), None)
+            .pagination(MAX_PAGE_SIZE)
+            .unwrap();
+        assert_eq!(pagination, Pagination::new(size, 1, MAX_PAGE_SIZE).ok());
+    }
+
+    #[test]
+    fn pagination_zero_page_rejected() {
+        let result = params(Some(0), Some(10), None).pagination(MAX_PAGE_SIZE);
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn pagination_size_above_max_rejected() {
+        let result = params(None, Some(MAX_PAGE_SIZE + 1), None).pagination(MAX_PAGE_SIZE);
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn ordering_missing_sort_empty() {
+        let ordering = params(None, None, None).ordering::<TestOrdering>().unwrap();
+        assert_eq!(ordering, vec![]);
+    }
+
+    #[test]
+    fn ordering_multiple_fields_with_direction() {
+        let ordering = params(None, None, Some("display_name, -created_at,"))
+            .ordering::<TestOrdering>()
+            .unwrap();
+        assert_eq!(
+            ordering,
+            vec![
+                OrderBy::new(TestOrdering::DisplayName, Direction::Asc),
+                OrderBy::new(TestOrdering::CreatedAt, Direction::Desc)
+            ]
+        );
+    }
+
+    #[test]
+    fn ordering_unknown_field_rejected() {
+        let result = params(None, None, Some("display_name,password")).ordering::<TestOrdering>();
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn filters_missing_filter_empty() {
+        let filters = params(None, None, None).filters::<TestFiltering>().unwrap();
+        assert_eq!(filters, vec![]);
+    }
+
+    #[test]
+    fn filters_multiple_conditions() {
+        let height: i64 = Faker.fake::<u16>().into();
+        let filters = filter_params(&format!("height:gte:{height}, display_name:like:%a:b%,"))
+            .filters::<TestFiltering>()
+            .unwrap();
+        assert_eq!(
+            filters,
+            vec![
+                Filter::new(
+                    TestFiltering::Height,
+                    Condition::Gte(FilterValue::Integer(height))
+                ),
+                Filter::new(
+                    TestFiltering::DisplayName,
+                    Condition::Like("%a:b%".to_owned())
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_in_and_null_conditions() {
+        let filters = filter_params("height:in:720|1080,display_name:not_null")
+            .filters::<TestFiltering>()
+            .unwrap();
+        assert_eq!(
+            filters,
+            vec![
+                Filter::new(
+                    TestFiltering::Height,
+                    Condition::In(vec![FilterValue::Integer(720), FilterValue::Integer(1080)])
+                ),
+                Filter::new(TestFiltering::DisplayName, Condition::IsNotNull)
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_invalid_value_rejected() {
+        let result = filter_params("height:lt:tall").filters::<TestFiltering>();
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn filters_like_on_number_rejected() {
+        let result = filter_params("height:like:10%").filters::<TestFiltering>();
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn filters_unknown_field_rejected() {
+        let result = filter_params("password:eq:hunter2").filters::<TestFiltering>();
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn keyset_empty_cursor_first_page() {
+        let keyset = decode_keyset("", &test_ordering(), test_key_kind).unwrap();
+        assert_eq!(
+            keyset,
+            Keyset {
+                after: None,
+                backward: false
+            }
+        );
+    }
+
+    #[test]
+    fn keyset_cursor_round_trip() {
+        let id: i64 = Faker.fake();
+        let name: String = Faker.fake();
+        let cursor = Cursor {
+            sort: sort_signature(&test_ordering()),
+            keys: vec![Some(name.clone()), None],
+            id: Some(id),
+            backward: true,
+        };
+        let keyset = decode_keyset(&cursor.encode(), &test_ordering(), test_key_kind).unwrap();
+        assert_eq!(
+            keyset,
+            Keyset {
+                after: Some((vec![Some(FilterValue::Text(name)), None], id)),
+                backward: true
+            }
+        );
+    }
+
+    #[test]
+    fn keyset_cursor_of_other_ordering_rejected() {
+        let cursor = Cursor {
+            sort: "display_name DESC".to_owned(),
+            keys: vec![Some("a".to_owned())],
+            id: Some(1),
+            backward: false,
+        };
+        let result = decode_keyset(&cursor.encode(), &test_ordering(), test_key_kind);
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn keyset_invalid_cursor_rejected() {
+        let cursor = Cursor {
+            sort: sort_signature(&test_ordering()),
+            keys: vec![Some("a".to_owned()), Some("yesterday".to_owned())],
+            id: Some(1),
+            backward: false,
+        };
+        let result = decode_keyset(&cursor.encode(), &test_ordering(), test_key_kind);
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+        let result = decode_keyset("not a cursor", &test_ordering(), test_key_kind);
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[sqlx::test]
+    async fn listing_shape_follows_pagination(pool: PgPool) {
+        for _ in 0..3 {
+            Catalog::create(&pool, Faker.fake::<CreateCatalogRequest>())
+                .await
+                .unwrap();
+        }
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let app = TestApp::new(pool);
+
+        let (status, body) = app.send(Method::GET, "/catalogs", &credentials, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().map(Vec::len), Some(3));
+
+        let (status, body) = app
+            .send(Method::GET, "/catalogs?page=2&size=2", &credentials, None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["items"].as_array().map(Vec::len), Some(1));
+        assert_eq!(
+            (
+                &body["page"],
+                &body["size"],
+                &body["total"],
+                &body["total_pages"]
+            ),
+            (&json!(2), &json!(2), &json!(3), &json!(2))
+        );
+
+        let (status, body) = app
+            .send(Method::GET, "/catalogs?cursor=&size=2", &credentials, None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["items"].as_array().map(Vec::len), Some(2));
+        assert!(body["next_cursor"].is_string());
+        assert!(body["prev_cursor"].is_null());
+        assert!(body.get("total").is_none());
+    }
+}
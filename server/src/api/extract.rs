@@ -0,0 +1,148 @@
+use async_trait::async_trait;
+use axum::{
+    body::HttpBody,
+    extract::{FromRequest, FromRequestParts, Query},
+    http::{request::Parts, Request},
+    BoxError, Json,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::ApiError;
+
+/// A JSON payload without fields to validate, see [`super::validation::ValidJson`] otherwise.
+/// Rejects a malformed or oversized payload with an [`ApiError`], like the handlers fail.
+#[derive(Debug)]
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, ApiError> {
+        let Json(payload) = Json::<T>::from_request(request, state).await?;
+        Ok(Self(payload))
+    }
+}
+
+/// The query string parameters of a request, rejected with an [`ApiError`] when invalid.
+#[derive(Debug)]
+pub struct QueryParams<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for QueryParams<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        let Query(params) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::testing::{Credentials, TestApp},
+        model::UserRole,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Method, StatusCode},
+    };
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+
+    fn code(body: &Value) -> &str {
+        body["error"]["code"].as_str().unwrap()
+    }
+
+    #[sqlx::test]
+    async fn malformed_payloads_answered_with_error_codes(pool: PgPool) {
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let app = TestApp::new(pool);
+        let request = |body: &'static str| {
+            TestApp::request(Method::DELETE, "/catalogs/bulk", &credentials)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let (syntax_status, syntax) = app.call(request("{\"ids\": [")).await;
+        let (data_status, data) = app.call(request("{\"ids\": \"1\"}")).await;
+        let (login_status, login) = app
+            .send(
+                Method::POST,
+                "/auth/login",
+                &Credentials::Anonymous,
+                Some(json!({ "username": "admin" })),
+            )
+            .await;
+
+        assert_eq!(syntax_status, StatusCode::BAD_REQUEST);
+        assert_eq!(code(&syntax), "bad_request");
+        assert_eq!(data_status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(code(&data), "validation");
+        assert_eq!(login_status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(code(&login), "validation");
+    }
+
+    #[sqlx::test]
+    async fn invalid_queries_and_paths_answered_with_error_codes(pool: PgPool) {
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let app = TestApp::new(pool);
+
+        for uri in [
+            "/catalogs?page=first",
+            "/search",
+            "/videos/first",
+            "/jobs/first",
+            "/catalogs/1/videos?size=-1",
+        ] {
+            let (status, body) = app.send(Method::GET, uri, &credentials, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+            assert_eq!(code(&body), "bad_request", "{uri}");
+        }
+    }
+
+    #[sqlx::test]
+    async fn oversized_payloads_answered_with_error_codes(pool: PgPool) {
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let app = TestApp::new(pool);
+        let ids = vec![1; 4096];
+        let body = json!({ "ids": ids }).to_string();
+
+        let (declared_status, declared) = app
+            .call(
+                TestApp::request(Method::DELETE, "/catalogs/bulk", &credentials)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::CONTENT_LENGTH, body.len())
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await;
+        let (streamed_status, streamed) = app
+            .send(
+                Method::DELETE,
+                "/catalogs/bulk",
+                &credentials,
+                Some(json!({ "ids": ids })),
+            )
+            .await;
+
+        assert_eq!(declared_status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(code(&declared), "payload_too_large");
+        assert_eq!(streamed_status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(code(&streamed), "payload_too_large");
+    }
+}
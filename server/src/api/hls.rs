@@ -0,0 +1,188 @@
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::IntoResponse,
+    Router,
+};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+use tracing::{error, instrument};
+
+use crate::{
+    error::ApiError,
+    hls::{self, Rendition},
+    model::{EntityId, Video},
+    transcoder::Transcoder,
+};
+
+use super::{auth::CurrentUser, AppState, LimitedBody};
+
+const PLAYLIST_MIME_TYPE: &str = "application/vnd.apple.mpegurl";
+const SEGMENT_MIME_TYPE: &str = "video/mp2t";
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/hls/master.m3u8", rejection(ApiError))]
+pub struct MasterPlaylistEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/hls/:rendition/index.m3u8", rejection(ApiError))]
+pub struct RenditionPlaylistEndpoint {
+    id: EntityId,
+    rendition: String,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/hls/:rendition/:segment", rejection(ApiError))]
+pub struct SegmentEndpoint {
+    id: EntityId,
+    rendition: String,
+    segment: String,
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(master_playlist)
+        .typed_get(rendition_playlist)
+        .typed_get(segment)
+}
+
+/// Finds a video that can be segmented, which requires a known duration.
+async fn find_segmentable_video(
+    user: &CurrentUser,
+    pool: &PgPool,
+    id: EntityId,
+) -> Result<Video, ApiError> {
+    let video = user.find_video(pool, id).await?;
+    if video.duration <= 0 {
+        return Err(ApiError::Conflict(
+            "the duration of the video is unknown, it cannot be segmented".to_owned(),
+        ));
+    }
+    Ok(video)
+}
+
+fn find_rendition(video: &Video, name: &str) -> Result<Rendition, ApiError> {
+    hls::renditions(video)
+        .into_iter()
+        .find(|rendition| rendition.name == name)
+        .ok_or(ApiError::NotFound)
+}
+
+fn playlist_response(playlist: String) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PLAYLIST_MIME_TYPE),
+        )],
+        playlist,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/hls/master.m3u8",
+    tag = "video",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Lists the renditions of the adaptive stream", content_type = "application/vnd.apple.mpegurl", body = String),
+        (status = 404, description = "Video not found"),
+        (status = 409, description = "The duration of the video is unknown")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn master_playlist(
+    MasterPlaylistEndpoint { id }: MasterPlaylistEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = find_segmentable_video(&user, &pool, id).await?;
+    Ok(playlist_response(hls::master_playlist(&hls::renditions(
+        &video,
+    ))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/hls/{rendition}/index.m3u8",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ("rendition" = String, Path, description = "Rendition name, e.g. `720p`")
+    ),
+    responses(
+        (status = 200, description = "Lists the segments of a rendition", content_type = "application/vnd.apple.mpegurl", body = String),
+        (status = 404, description = "Video or rendition not found"),
+        (status = 409, description = "The duration of the video is unknown")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn rendition_playlist(
+    RenditionPlaylistEndpoint { id, rendition }: RenditionPlaylistEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = find_segmentable_video(&user, &pool, id).await?;
+    find_rendition(&video, &rendition)?;
+    Ok(playlist_response(hls::rendition_playlist(video.duration)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/hls/{rendition}/{segment}",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ("rendition" = String, Path, description = "Rendition name, e.g. `720p`"),
+        ("segment" = String, Path, description = "Segment file name, e.g. `0.ts`")
+    ),
+    responses(
+        (status = 200, description = "Streams the segment, transcoding it first if needed", content_type = "video/mp2t"),
+        (status = 404, description = "Video, rendition or segment not found"),
+        (status = 409, description = "The duration of the video is unknown")
+    )
+)]
+#[instrument(skip(pool, transcoder))]
+pub async fn segment(
+    SegmentEndpoint {
+        id,
+        rendition,
+        segment,
+    }: SegmentEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(transcoder): State<Transcoder>,
+) -> Result<impl IntoResponse, ApiError> {
+    let index = segment
+        .strip_suffix(".ts")
+        .and_then(|index| index.parse::<i64>().ok())
+        .ok_or(ApiError::NotFound)?;
+    let video = find_segmentable_video(&user, &pool, id).await?;
+    let rendition = find_rendition(&video, &rendition)?;
+    if hls::segment_bounds(video.duration, index).is_none() {
+        return Err(ApiError::NotFound);
+    }
+
+    let path = transcoder
+        .hls_segment(&video, &rendition, index)
+        .await
+        .map_err(|e| {
+            error!("could not transcode segment: {e:#}");
+            ApiError::Internal
+        })?;
+    let file = File::open(&path).await.map_err(ApiError::from_io)?;
+    let size = file.metadata().await.map_err(ApiError::from_io)?.len();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(SEGMENT_MIME_TYPE),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+    Ok((headers, StreamBody::new(ReaderStream::new(file))))
+}
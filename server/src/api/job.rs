@@ -0,0 +1,271 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json, Router,
+};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    configuration::Configuration,
+    crud::{
+        transcode_job::{CreateTranscodeJobRequest, TranscodeJobFiltering, TranscodeJobOrdering},
+        Counted, Entity,
+    },
+    error::ApiError,
+    hls,
+    jobs::JobQueue,
+    model::{EntityId, Progress, TranscodeJob, TranscodeJobKind, TranscodeJobStatus, Video},
+};
+
+use super::{
+    auth::AdminUser,
+    common::ListParams,
+    extract::QueryParams,
+    stream::find_audio_tracks,
+    validation::{ValidJson, Validate, Validator},
+    AppState, LimitedBody, REQUEST_ID_HEADER,
+};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/jobs")]
+pub struct JobsEndpoint;
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/jobs/:id", rejection(ApiError))]
+pub struct JobEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobDto {
+    id: EntityId,
+    video_id: EntityId,
+    kind: TranscodeJobKind,
+    rendition: Option<String>,
+    /// The only audio track kept by a remux job.
+    audio: Option<i32>,
+    status: TranscodeJobStatus,
+    /// Between 0 and 1.
+    progress: Progress,
+    error: Option<String>,
+    request_id: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    started_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    finished_at: Option<OffsetDateTime>,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+impl From<TranscodeJob> for JobDto {
+    fn from(job: TranscodeJob) -> Self {
+        Self {
+            id: job.id,
+            video_id: job.video_id,
+            kind: job.kind,
+            rendition: job.rendition,
+            audio: job.audio_index,
+            status: job.status,
+            progress: job.progress,
+            error: job.error,
+            request_id: job.request_id,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateJobDto {
+    video_id: EntityId,
+    kind: TranscodeJobKind,
+    /// The rendition to transcode, required by `hls_rendition` jobs, e.g. `720p`.
+    rendition: Option<String>,
+    /// The index of the only audio track to keep, for `remux` jobs, every audio track is kept without it.
+    audio: Option<i32>,
+}
+
+#[async_trait]
+impl Validate for CreateJobDto {
+    async fn validate(&self, validator: &mut Validator, _: &AppState) -> Result<(), ApiError> {
+        let hls_rendition = self.kind == TranscodeJobKind::HlsRendition;
+        validator
+            .check(
+                "rendition",
+                hls_rendition == self.rendition.is_some(),
+                "is required by hls_rendition jobs, remux jobs have none",
+            )
+            .check(
+                "audio",
+                !hls_rendition || self.audio.is_none(),
+                "hls_rendition jobs have no audio track",
+            );
+        Ok(())
+    }
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(list_jobs)
+        .typed_post(create_job)
+        .typed_get(find_job)
+        .typed_delete(delete_job)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "job",
+    params(ListParams),
+    responses(
+        (status = 200, description = "Lists transcoding jobs, in a page when paged by page, size or cursor", body = JobListing),
+        (status = 400, description = "Invalid filtering, ordering or pagination"),
+        (status = 403, description = "Not an admin")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn list_jobs(
+    _: JobsEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    QueryParams(params): QueryParams<ListParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let filters = params.filters::<TranscodeJobFiltering>()?;
+    let ordering = params.ordering::<TranscodeJobOrdering>()?;
+    let pagination =
+        params.keyed_pagination::<TranscodeJob>(&ordering, config.listing().max_page_size())?;
+    let Counted {
+        items: mut jobs,
+        total,
+    } = TranscodeJob::find_all_counted(&pool, filters, ordering.clone(), pagination).await?;
+    let cursors = params.cursors(&ordering, &mut jobs);
+    Ok(params.listing(
+        jobs.into_iter().map(JobDto::from).collect::<Vec<_>>(),
+        total,
+        cursors,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/jobs",
+    tag = "job",
+    request_body = CreateJobDto,
+    responses(
+        (status = 201, description = "Queues a transcoding job", body = JobDto),
+        (status = 400, description = "The video does not exist, or has no such rendition or audio track"),
+        (status = 403, description = "Not an admin"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool, jobs))]
+pub async fn create_job(
+    _: JobsEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    State(jobs): State<JobQueue>,
+    headers: HeaderMap,
+    ValidJson(dto): ValidJson<CreateJobDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(video) = Video::find(&pool, dto.video_id).await? else {
+        return Err(ApiError::BadRequest(format!(
+            "video {} does not exist",
+            dto.video_id
+        )));
+    };
+    if let Some(name) = dto.rendition.as_deref() {
+        if video.duration <= 0 {
+            return Err(ApiError::BadRequest(
+                "the duration of the video is unknown, it cannot be segmented".to_owned(),
+            ));
+        }
+        if !hls::renditions(&video)
+            .iter()
+            .any(|rendition| rendition.name == name)
+        {
+            return Err(ApiError::BadRequest(format!(
+                "the video has no '{name}' rendition"
+            )));
+        }
+    }
+    if let Some(audio) = dto.audio {
+        find_audio_tracks(&pool, &video, audio).await?;
+    }
+
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+    let job = jobs
+        .enqueue(CreateTranscodeJobRequest {
+            video_id: dto.video_id,
+            kind: dto.kind,
+            rendition: dto.rendition,
+            audio_index: dto.audio,
+            request_id,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(JobDto::from(job))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}",
+    tag = "job",
+    params(("id" = i64, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "Finds a transcoding job", body = JobDto),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Job not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_job(
+    JobEndpoint { id }: JobEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job = TranscodeJob::find(&pool, id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(Json(JobDto::from(job)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/jobs/{id}",
+    tag = "job",
+    params(("id" = i64, Path, description = "Job id")),
+    responses(
+        (status = 204, description = "Cancels a queued or running job, or removes a finished one"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Job not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_job(
+    JobEndpoint { id }: JobEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    if TranscodeJob::cancel(&pool, id).await?.is_some() || TranscodeJob::delete(&pool, id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
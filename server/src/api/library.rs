@@ -0,0 +1,49 @@
+use axum::{extract::State, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use tracing::{error, instrument};
+
+use crate::{error::ApiError, scanner::Scanner};
+
+use super::{auth::AdminUser, AppState, LimitedBody};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/library/scan")]
+pub struct ScanEndpoint;
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new().typed_post(scan_library)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/library/scan",
+    tag = "library",
+    responses(
+        (status = 200, description = "Scans the media library and reports the changes", body = crate::scanner::ScanSummary),
+        (status = 403, description = "Not an admin"),
+        (status = 409, description = "A scan is already running")
+    )
+)]
+#[instrument(skip(scanner))]
+pub async fn scan_library(
+    _: ScanEndpoint,
+    _: AdminUser,
+    State(scanner): State<Scanner>,
+) -> Result<impl IntoResponse, ApiError> {
+    // the scan runs in its own task, so it is not cancelled if the request times out
+    let scan = tokio::spawn(async move { scanner.try_scan().await });
+    match scan.await {
+        Ok(Some(Ok(summary))) => Ok(Json(summary)),
+        Ok(Some(Err(e))) => {
+            error!("library scan failed: {e:#}");
+            Err(ApiError::Internal)
+        }
+        Ok(None) => Err(ApiError::Conflict(
+            "a library scan is already running".to_owned(),
+        )),
+        Err(e) => {
+            error!("library scan task failed: {e}");
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderValue},
+};
+use time::OffsetDateTime;
+
+use crate::error::ApiError;
+
+/// The entity tag of a revision of an entity, changing with its `updated_at`.
+pub fn etag(updated_at: OffsetDateTime) -> String {
+    format!("\"{}\"", updated_at.unix_timestamp_nanos())
+}
+
+/// The `ETag` header of a revision of an entity.
+pub fn etag_header(updated_at: OffsetDateTime) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(etag) = HeaderValue::from_str(&etag(updated_at)) {
+        headers.insert(header::ETAG, etag);
+    }
+    headers
+}
+
+fn parse_etag(tag: &str) -> Option<OffsetDateTime> {
+    let nanos = tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok()?;
+    OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()
+}
+
+/// The revisions an entity is expected to be at by the `If-Match` header of a request,
+/// `None` without the header or with `*`.
+/// Tags not issued by [`etag`] match no revision, without any other tag the request fails
+/// as if the entity had changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfMatch(pub Option<Vec<OffsetDateTime>>);
+
+impl IfMatch {
+    /// The error of a conditional write matching no row, the entity changed if it still exists.
+    pub fn rejection(&self, exists: bool) -> ApiError {
+        if exists && self.0.is_some() {
+            ApiError::PreconditionFailed
+        } else {
+            ApiError::NotFound
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let mut revisions = None;
+        for value in parts.headers.get_all(header::IF_MATCH) {
+            let value = value
+                .to_str()
+                .map_err(|_| ApiError::BadRequest("the If-Match header is invalid".to_owned()))?
+                .trim();
+            if value == "*" {
+                return Ok(Self(None));
+            }
+            // weak tags never match, If-Match uses the strong comparison
+            revisions
+                .get_or_insert_with(Vec::new)
+                .extend(value.split(',').filter_map(|tag| parse_etag(tag.trim())));
+        }
+        Ok(Self(revisions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::testing::TestApp,
+        crud::{catalog::CreateCatalogRequest, Entity},
+        model::{Catalog, EntityId, UserRole},
+    };
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+
+    async fn if_match(value: Option<&str>) -> Result<IfMatch, ApiError> {
+        let mut request = Request::builder();
+        if let Some(value) = value {
+            request = request.header(header::IF_MATCH, value);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        IfMatch::from_request_parts(&mut parts, &()).await
+    }
+
+    #[test]
+    fn etag_round_trip() {
+        let updated_at = time::macros::datetime!(2023-08-13 17:35:51.123456 UTC);
+        assert_eq!(etag(updated_at), "\"1691948151123456000\"");
+        assert_eq!(parse_etag(&etag(updated_at)), Some(updated_at));
+    }
+
+    #[tokio::test]
+    async fn if_match_revision() {
+        let updated_at = time::macros::datetime!(2023-08-13 17:35:51.123456 UTC);
+        let result = if_match(Some(&etag(updated_at))).await.unwrap();
+        assert_eq!(result, IfMatch(Some(vec![updated_at])));
+    }
+
+    #[tokio::test]
+    async fn if_match_missing_or_any_unconditional() {
+        assert_eq!(if_match(None).await.unwrap(), IfMatch(None));
+        assert_eq!(if_match(Some("*")).await.unwrap(), IfMatch(None));
+    }
+
+    #[tokio::test]
+    async fn if_match_several_tags_keeps_known_strong_ones() {
+        let first = time::macros::datetime!(2023-08-13 17:35:51.123456 UTC);
+        let second = time::macros::datetime!(2023-08-14 09:12:03 UTC);
+        let value = format!(
+            "{}, \"other\", W/{}, {}",
+            etag(first),
+            etag(second),
+            etag(second)
+        );
+        let result = if_match(Some(&value)).await.unwrap();
+        assert_eq!(result, IfMatch(Some(vec![first, second])));
+    }
+
+    #[tokio::test]
+    async fn if_match_unknown_or_weak_tag_matches_nothing() {
+        for tag in [
+            "\"other\"",
+            "W/\"1691948151123456000\"",
+            "1691948151123456000",
+        ] {
+            let result = if_match(Some(tag)).await.unwrap();
+            assert_eq!(result, IfMatch(Some(Vec::new())), "{tag}");
+            assert!(matches!(
+                result.rejection(true),
+                ApiError::PreconditionFailed
+            ));
+            assert!(matches!(result.rejection(false), ApiError::NotFound));
+        }
+    }
+
+    #[sqlx::test]
+    async fn conditional_writes_follow_if_match(pool: PgPool) {
+        let catalog = Catalog::create(&pool, Faker.fake::<CreateCatalogRequest>())
+            .await
+            .unwrap();
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let app = TestApp::new(pool.clone());
+        let stale = etag(catalog.updated_at - time::Duration::SECOND);
+        let send = |method: Method, id: EntityId, if_match: String, body: Option<Value>| {
+            let request = TestApp::request(method, &format!("/catalogs/{id}"), &credentials)
+                .header(header::IF_MATCH, if_match);
+            let request = match body {
+                Some(body) => request
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string())),
+                None => request.body(Body::empty()),
+            };
+            app.call(request.unwrap())
+        };
+        let update = json!({"display_name": "Comets", "short_desc": "", "long_desc": ""});
+
+        let (status, _) = send(Method::DELETE, catalog.id + 1, "\"other\"".to_owned(), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(Method::DELETE, catalog.id, "\"other\"".to_owned(), None).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+        let (status, _) = send(Method::DELETE, catalog.id, stale.clone(), None).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+        let tags = format!("{stale}, {}", etag(catalog.updated_at));
+        let (status, body) = send(Method::PUT, catalog.id, tags, Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["display_name"], "Comets");
+
+        let catalog = Catalog::find(&pool, catalog.id).await.unwrap().unwrap();
+        let tags = format!("{stale}, {}", etag(catalog.updated_at));
+        let (status, _) = send(Method::DELETE, catalog.id, tags, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+}
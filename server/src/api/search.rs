@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    configuration::Configuration,
+    crud::search::{SearchHit, SearchHitKind, SearchRequest},
+    error::ApiError,
+    model::EntityId,
+};
+
+use super::{auth::CurrentUser, common::paginate, extract::QueryParams, AppState, LimitedBody};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/search")]
+pub struct SearchEndpoint;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// The words to search for in the names and the descriptions, supports `"quoted phrases"`, `or` and `-excluded` words.
+    #[param(example = "star wars -clone")]
+    q: String,
+    /// The page to return, starting from 1.
+    page: Option<u64>,
+    /// The number of results on a page.
+    size: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchHitDto {
+    kind: SearchHitKind,
+    id: EntityId,
+    /// The catalog of a video, missing for catalogs.
+    catalog_id: Option<EntityId>,
+    display_name: String,
+    /// The matching parts of the name and the descriptions, the matched words wrapped in `<mark>` tags.
+    highlight: String,
+    /// How well the result matches, higher is better.
+    rank: f32,
+    /// Link to the catalog or the video.
+    link: String,
+}
+
+impl From<SearchHit> for SearchHitDto {
+    fn from(hit: SearchHit) -> Self {
+        let link = match hit.kind {
+            SearchHitKind::Catalog => format!("/api/catalogs/{}", hit.id),
+            SearchHitKind::Video => format!("/api/videos/{}", hit.id),
+        };
+        Self {
+            kind: hit.kind,
+            id: hit.id,
+            catalog_id: hit.catalog_id,
+            display_name: hit.display_name,
+            highlight: hit.highlight,
+            rank: hit.rank,
+            link,
+        }
+    }
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new().typed_get(search)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Searches catalogs and videos by their names and descriptions, the best matches first", body = [SearchHitDto]),
+        (status = 400, description = "Empty search or invalid pagination")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn search(
+    _: SearchEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    QueryParams(params): QueryParams<SearchParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let query = params.q.trim();
+    if query.is_empty() {
+        return Err(ApiError::BadRequest(
+            "the search must not be empty".to_owned(),
+        ));
+    }
+    let hits = SearchHit::search(
+        &pool,
+        SearchRequest {
+            query: query.to_owned(),
+            granted_to: user.role.is_restricted().then_some(user.id),
+            pagination: paginate(params.page, params.size, config.listing().max_page_size())?,
+        },
+    )
+    .await?;
+    Ok(Json(
+        hits.into_iter().map(SearchHitDto::from).collect::<Vec<_>>(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::testing::TestApp,
+        crud::{catalog::CreateCatalogRequest, video::CreateVideoRequest, Entity},
+        model::{Catalog, CatalogGrant, UserRole, Video},
+    };
+    use axum::http::{Method, StatusCode};
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    /// Creates two catalogs with a video named like them, returns their ids.
+    async fn library(pool: &PgPool) -> [(EntityId, EntityId); 2] {
+        let mut library = [(0, 0); 2];
+        for (entry, name) in library.iter_mut().zip(["Comets", "Comets again"]) {
+            let catalog = Catalog::create(
+                pool,
+                CreateCatalogRequest {
+                    display_name: name.to_owned(),
+                    short_desc: String::new(),
+                    long_desc: String::new(),
+                    ..Faker.fake()
+                },
+            )
+            .await
+            .unwrap();
+            let video = Video::create(
+                pool,
+                CreateVideoRequest {
+                    display_name: name.to_owned(),
+                    short_desc: String::new(),
+                    long_desc: String::new(),
+                    catalog_id: catalog.id,
+                    sequent_id: None,
+                    ..Faker.fake()
+                },
+            )
+            .await
+            .unwrap();
+            *entry = (catalog.id, video.id);
+        }
+        library
+    }
+
+    fn hits(body: &Value) -> Vec<(String, i64)> {
+        body.as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| {
+                let kind = hit["kind"].as_str().unwrap().to_owned();
+                (kind, hit["id"].as_i64().unwrap())
+            })
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn search_lists_best_matches_first(pool: PgPool) {
+        let [(catalog, video), (again_catalog, again_video)] = library(&pool).await;
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let app = TestApp::new(pool);
+
+        let (status, first_page) = app
+            .send(
+                Method::GET,
+                "/search?q=comets+or+again&size=3",
+                &credentials,
+                None,
+            )
+            .await;
+        let (_, second_page) = app
+            .send(
+                Method::GET,
+                "/search?q=comets+or+again&size=3&page=2",
+                &credentials,
+                None,
+            )
+            .await;
+        let (_, both_words) = app
+            .send(Method::GET, "/search?q=comets+again", &credentials, None)
+            .await;
+
+        // matching both words ranks higher, equally ranked hits are listed catalogs first
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            hits(&first_page),
+            vec![
+                ("catalog".to_owned(), again_catalog),
+                ("video".to_owned(), again_video),
+                ("catalog".to_owned(), catalog),
+            ]
+        );
+        assert_eq!(hits(&second_page), vec![("video".to_owned(), video)]);
+        assert_eq!(second_page[0]["catalog_id"], json!(catalog));
+        assert_eq!(second_page[0]["link"], format!("/api/videos/{video}"));
+        assert_eq!(
+            hits(&both_words),
+            vec![
+                ("catalog".to_owned(), again_catalog),
+                ("video".to_owned(), again_video)
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn guest_searches_only_granted_catalogs(pool: PgPool) {
+        let [(granted, granted_video), _] = library(&pool).await;
+        let (guest, credentials) = TestApp::log_in(&pool, UserRole::Guest).await;
+        CatalogGrant::replace(&pool, guest.id, &[granted])
+            .await
+            .unwrap();
+        let app = TestApp::new(pool);
+
+        let (status, body) = app
+            .send(Method::GET, "/search?q=comets", &credentials, None)
+            .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            hits(&body),
+            vec![
+                ("catalog".to_owned(), granted),
+                ("video".to_owned(), granted_video)
+            ]
+        );
+    }
+
+    #[sqlx::test]
+    async fn blank_search_rejected(pool: PgPool) {
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let app = TestApp::new(pool);
+
+        let (status, body) = app
+            .send(Method::GET, "/search?q=+++", &credentials, None)
+            .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "bad_request");
+    }
+}
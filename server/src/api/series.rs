@@ -0,0 +1,183 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    crud::Entity,
+    error::ApiError,
+    model::{CatalogGrant, EntityId, Video, WatchProgress},
+};
+
+use super::{
+    auth::CurrentUser,
+    video::{to_dto, to_dtos, VideoDto},
+    watch_progress::WatchProgressDto,
+    AppState, LimitedBody,
+};
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/series", rejection(ApiError))]
+pub struct SeriesEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/neighbours", rejection(ApiError))]
+pub struct NeighboursEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/up-next", rejection(ApiError))]
+pub struct UpNextEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NeighboursDto {
+    /// The video this one is the sequent of.
+    previous: Option<VideoDto>,
+    /// The sequent of this video.
+    next: Option<VideoDto>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UpNextDto {
+    video: VideoDto,
+    /// Where to resume the video, missing if it was not started yet.
+    progress: Option<WatchProgressDto>,
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(find_series)
+        .typed_get(find_neighbours)
+        .typed_get(find_up_next)
+}
+
+/// Leaves out the videos of catalogs not granted to a restricted user.
+async fn accessible(
+    pool: &PgPool,
+    user: &CurrentUser,
+    videos: Vec<Video>,
+) -> Result<Vec<Video>, ApiError> {
+    if !user.role.is_restricted() {
+        return Ok(videos);
+    }
+    let catalog_ids = CatalogGrant::find_catalog_ids(pool, user.id).await?;
+    Ok(videos
+        .into_iter()
+        .filter(|video| catalog_ids.contains(&video.catalog_id))
+        .collect())
+}
+
+async fn accessible_dto(
+    pool: &PgPool,
+    user: &CurrentUser,
+    video: Option<Video>,
+) -> Result<Option<VideoDto>, ApiError> {
+    let videos = accessible(pool, user, video.into_iter().collect()).await?;
+    Ok(to_dtos(pool, videos).await?.pop())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/series",
+    tag = "series",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Lists the series of a video in order, following the sequents from its first video", body = [VideoDto]),
+        (status = 403, description = "The catalog of the video is not granted to the user"),
+        (status = 404, description = "Video not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_series(
+    SeriesEndpoint { id }: SeriesEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    let series = Video::find_series(&pool, video.id).await?;
+    let series = accessible(&pool, &user, series).await?;
+    Ok(Json(to_dtos(&pool, series).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/neighbours",
+    tag = "series",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Finds the previous and the next video of a video in its series", body = NeighboursDto),
+        (status = 403, description = "The catalog of the video is not granted to the user"),
+        (status = 404, description = "Video not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_neighbours(
+    NeighboursEndpoint { id }: NeighboursEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    let previous = Video::find_previous(&pool, video.id).await?;
+    let next = match video.sequent_id {
+        Some(sequent_id) => Video::find(&pool, sequent_id).await?,
+        None => None,
+    };
+    Ok(Json(NeighboursDto {
+        previous: accessible_dto(&pool, &user, previous).await?,
+        next: accessible_dto(&pool, &user, next).await?,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/up-next",
+    tag = "series",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Finds the first video after this one in its series the logged in user has not watched yet", body = UpNextDto),
+        (status = 204, description = "The user watched every video after this one"),
+        (status = 403, description = "The catalog of the video is not granted to the user"),
+        (status = 404, description = "Video not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_up_next(
+    UpNextEndpoint { id }: UpNextEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    let series = Video::find_series(&pool, video.id).await?;
+    let following = series
+        .into_iter()
+        .skip_while(|episode| episode.id != video.id)
+        .skip(1)
+        .collect();
+    let following = accessible(&pool, &user, following).await?;
+    let ids = following
+        .iter()
+        .map(|episode| episode.id)
+        .collect::<Vec<_>>();
+    let progress = WatchProgress::find_all_by_videos(&pool, user.id, &ids).await?;
+    for episode in following {
+        let progress = progress
+            .iter()
+            .find(|progress| progress.video_id == episode.id);
+        if progress.is_some_and(|progress| progress.watched) {
+            continue;
+        }
+        let dto = UpNextDto {
+            progress: progress.cloned().map(WatchProgressDto::from),
+            video: to_dto(&pool, episode).await?,
+        };
+        return Ok(Json(dto).into_response());
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
@@ -0,0 +1,550 @@
+use std::{io::SeekFrom, ops::Range, path::Path, time::SystemTime};
+
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, instrument};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    crud::transcode_job::CreateTranscodeJobRequest,
+    error::ApiError,
+    jobs::JobQueue,
+    media::video_mime_type,
+    model::{AudioTrack, EntityId, TranscodeJobKind, TranscodeJobStatus, Video},
+    transcoder::Transcoder,
+};
+
+use super::{auth::CurrentUser, extract::QueryParams, AppState, LimitedBody, REQUEST_ID_HEADER};
+
+const FALLBACK_MIME_TYPE: &str = "application/octet-stream";
+const MP4_MIME_TYPE: &str = "video/mp4";
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/stream", rejection(ApiError))]
+pub struct StreamEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/remux", rejection(ApiError))]
+pub struct RemuxEndpoint {
+    id: EntityId,
+}
+
+/// Query parameters of the streaming endpoints.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamParams {
+    /// The index of the audio track to play, as listed on the video.
+    /// A specific track of a video with more of them is served from the MP4 version produced by a remux job with that track.
+    /// The stream queues the job if needed, and answers `202` until the job completes.
+    audio: Option<i32>,
+}
+
+/// The remux job producing the requested audio track of a video.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueuedRemuxDto {
+    job_id: EntityId,
+    status: TranscodeJobStatus,
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(stream_video)
+        .typed_get(stream_remuxed_video)
+}
+
+/// The outcome of evaluating the `Range` header of a request against a file.
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    /// No range, an ignored range or more than one range was requested, the whole file is served.
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+/// Validators of a file, used for `If-Range` evaluation and to let clients cache responses.
+#[derive(Debug)]
+pub(super) struct FileValidators {
+    pub(super) etag: String,
+    pub(super) last_modified: Option<String>,
+}
+
+impl FileValidators {
+    pub(super) fn new(size: u64, modified: Option<SystemTime>) -> Self {
+        let modified_secs = modified
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        Self {
+            etag: format!("\"{size:x}-{modified_secs:x}\""),
+            last_modified: modified.map(httpdate::fmt_http_date),
+        }
+    }
+
+    /// Evaluates an `If-Range` header value, a range is only served if the validator still matches.
+    /// Only strong entity tags are considered, as required for range requests.
+    fn matches(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') {
+            return if_range == self.etag;
+        }
+        if if_range.starts_with("W/") {
+            return false;
+        }
+        self.last_modified
+            .as_deref()
+            .and_then(|last_modified| httpdate::parse_http_date(last_modified).ok())
+            .zip(httpdate::parse_http_date(if_range).ok())
+            .is_some_and(|(last_modified, if_range)| last_modified == if_range)
+    }
+
+    /// Evaluates an `If-None-Match` header value, which uses the weak comparison of entity tags.
+    pub(super) fn matches_any(&self, if_none_match: &str) -> bool {
+        let etag = self.etag.trim_start_matches("W/");
+        if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    }
+}
+
+fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let Some(ranges) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    let mut specs = ranges.split(',').map(str::trim);
+    let (Some(spec), None) = (specs.next(), specs.next()) else {
+        return RangeRequest::Full;
+    };
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(size),
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            size.saturating_sub(suffix)..size
+        }
+        _ => return RangeRequest::Full,
+    };
+
+    if range.start >= size {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(range)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/stream",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        StreamParams,
+        ("Range" = Option<String>, Header, description = "Requested byte range, e.g. `bytes=0-1023`"),
+        ("If-Range" = Option<String>, Header, description = "Serve the range only if the file is unchanged")
+    ),
+    responses(
+        (status = 200, description = "Streams the whole video file"),
+        (status = 202, description = "The requested audio track is being remuxed, the request can be repeated once the job completed", body = QueuedRemuxDto,
+            headers(("Location" = String, description = "The job remuxing the audio track"))),
+        (status = 206, description = "Streams the requested part of the video file"),
+        (status = 400, description = "The video has no such audio track"),
+        (status = 404, description = "Video or its file not found"),
+        (status = 409, description = "The remux of the requested audio track was cancelled, an admin has to queue it again"),
+        (status = 416, description = "The requested range cannot be satisfied")
+    )
+)]
+#[instrument(skip(pool, transcoder, jobs))]
+pub async fn stream_video(
+    StreamEndpoint { id }: StreamEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(transcoder): State<Transcoder>,
+    State(jobs): State<JobQueue>,
+    QueryParams(params): QueryParams<StreamParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    if let Some(audio) = params.audio {
+        // the original file carries every audio track, players pick the default one
+        if find_audio_tracks(&pool, &video, audio).await?.len() > 1 {
+            return serve_audio_track(&video, audio, &transcoder, &jobs, &headers).await;
+        }
+    }
+    let path = video.path();
+    let mime_type = video_mime_type(&path).unwrap_or(FALLBACK_MIME_TYPE);
+    serve_file(&path, mime_type, &headers).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/remux",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        StreamParams,
+        ("Range" = Option<String>, Header, description = "Requested byte range, e.g. `bytes=0-1023`"),
+        ("If-Range" = Option<String>, Header, description = "Serve the range only if the file is unchanged")
+    ),
+    responses(
+        (status = 200, description = "Streams the whole MP4 version of the video", content_type = "video/mp4"),
+        (status = 206, description = "Streams the requested part of the MP4 version of the video", content_type = "video/mp4"),
+        (status = 400, description = "The video has no such audio track"),
+        (status = 404, description = "Video not found or it was not remuxed yet by a remux job, with the requested audio track if any"),
+        (status = 416, description = "The requested range cannot be satisfied")
+    )
+)]
+#[instrument(skip(pool, transcoder))]
+pub async fn stream_remuxed_video(
+    RemuxEndpoint { id }: RemuxEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(transcoder): State<Transcoder>,
+    QueryParams(params): QueryParams<StreamParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    if let Some(audio) = params.audio {
+        find_audio_tracks(&pool, &video, audio).await?;
+    }
+    let path = transcoder.remux_path(&video, params.audio);
+    serve_file(&path, MP4_MIME_TYPE, &headers).await
+}
+
+/// Serves the MP4 version of a video with only the given audio track.
+/// Until it is remuxed, a remux job is queued unless there is one already, and the request is answered with `202` and the job.
+/// A failed job is queued again, a cancelled one is left to the admins.
+async fn serve_audio_track(
+    video: &Video,
+    audio: i32,
+    transcoder: &Transcoder,
+    jobs: &JobQueue,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = transcoder.remux_path(video, Some(audio));
+    if fs::try_exists(&path).await.map_err(ApiError::from_io)? {
+        return serve_file(&path, MP4_MIME_TYPE, headers).await;
+    }
+
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+    let job = jobs
+        .find_or_enqueue(CreateTranscodeJobRequest {
+            video_id: video.id,
+            kind: TranscodeJobKind::Remux,
+            rendition: None,
+            audio_index: Some(audio),
+            request_id,
+        })
+        .await?;
+    if job.status == TranscodeJobStatus::Cancelled {
+        return Err(ApiError::Conflict(format!(
+            "remux job {} of audio track {audio} was cancelled, an admin has to queue it again",
+            job.id
+        )));
+    }
+    let location = [(header::LOCATION, format!("/api/jobs/{}", job.id))];
+    let body = Json(QueuedRemuxDto {
+        job_id: job.id,
+        status: job.status,
+    });
+    Ok((StatusCode::ACCEPTED, location, body).into_response())
+}
+
+/// Finds the audio tracks of a video, failing if none of them has the requested index.
+pub(super) async fn find_audio_tracks(
+    pool: &PgPool,
+    video: &Video,
+    audio: i32,
+) -> Result<Vec<AudioTrack>, ApiError> {
+    let tracks = AudioTrack::find_all_by_videos(pool, &[video.id]).await?;
+    if !tracks.iter().any(|track| track.stream_index == audio) {
+        return Err(ApiError::BadRequest(format!(
+            "the video has no audio track {audio}"
+        )));
+    }
+    Ok(tracks)
+}
+
+/// Serves a file, or the byte range of it requested by the headers.
+async fn serve_file(
+    path: &Path,
+    mime_type: &'static str,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let mut file = File::open(path).await.map_err(ApiError::from_io)?;
+    let metadata = file.metadata().await.map_err(ApiError::from_io)?;
+    let size = metadata.len();
+    let validators = FileValidators::new(size, metadata.modified().ok());
+
+    let range_header = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let if_range_header = headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok());
+    let range_request = match (range_header, if_range_header) {
+        (Some(range), Some(if_range)) if validators.matches(if_range) => parse_range(range, size),
+        (Some(_), Some(_)) | (None, _) => RangeRequest::Full,
+        (Some(range), None) => parse_range(range, size),
+    };
+    debug!("serving '{}' with {range_request:?}", path.display());
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(mime_type));
+    if let Ok(etag) = HeaderValue::from_str(&validators.etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    if let Some(Ok(last_modified)) = validators
+        .last_modified
+        .as_deref()
+        .map(HeaderValue::from_str)
+    {
+        response_headers.insert(header::LAST_MODIFIED, last_modified);
+    }
+
+    let (status, range) = match range_request {
+        RangeRequest::Full => (StatusCode::OK, 0..size),
+        RangeRequest::Partial(range) => {
+            let content_range = format!("bytes {}-{}/{size}", range.start, range.end - 1);
+            if let Ok(content_range) = HeaderValue::from_str(&content_range) {
+                response_headers.insert(header::CONTENT_RANGE, content_range);
+            }
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        RangeRequest::Unsatisfiable => {
+            if let Ok(content_range) = HeaderValue::from_str(&format!("bytes */{size}")) {
+                response_headers.insert(header::CONTENT_RANGE, content_range);
+            }
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+        }
+    };
+    response_headers.insert(
+        header::CONTENT_LENGTH,
+        HeaderValue::from(range.end - range.start),
+    );
+
+    file.seek(SeekFrom::Start(range.start))
+        .await
+        .map_err(ApiError::from_io)?;
+    let body = StreamBody::new(ReaderStream::new(file.take(range.end - range.start)));
+
+    Ok((status, response_headers, body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crud::{
+            audio_track::CreateAudioTrackRequest, catalog::CreateCatalogRequest,
+            video::CreateVideoRequest, Entity,
+        },
+        model::{Catalog, TranscodeJob, UserRole},
+    };
+    use axum::body::HttpBody;
+    use fake::{Fake, Faker};
+    use pretty_assertions::{assert_eq, assert_ne};
+    use serde_json::Value;
+    use std::{path::PathBuf, time::Duration};
+
+    const SIZE: u64 = 1000;
+
+    #[test]
+    fn parse_range_start_and_end() {
+        assert_eq!(
+            parse_range("bytes=0-499", SIZE),
+            RangeRequest::Partial(0..500)
+        );
+    }
+
+    #[test]
+    fn parse_range_end_clamped_to_size() {
+        assert_eq!(
+            parse_range("bytes=500-5000", SIZE),
+            RangeRequest::Partial(500..SIZE)
+        );
+    }
+
+    #[test]
+    fn parse_range_open_ended() {
+        let start = Faker.fake::<u64>() % SIZE;
+        assert_eq!(
+            parse_range(&format!("bytes={start}-"), SIZE),
+            RangeRequest::Partial(start..SIZE)
+        );
+    }
+
+    #[test]
+    fn parse_range_suffix() {
+        assert_eq!(
+            parse_range("bytes=-100", SIZE),
+            RangeRequest::Partial(900..SIZE)
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", SIZE),
+            RangeRequest::Partial(0..SIZE)
+        );
+    }
+
+    #[test]
+    fn parse_range_unsatisfiable() {
+        assert_eq!(
+            parse_range("bytes=1000-", SIZE),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", SIZE), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_invalid_ignored() {
+        assert_eq!(parse_range("items=0-10", SIZE), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=10-0", SIZE), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", SIZE), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=-", SIZE), RangeRequest::Full);
+    }
+
+    #[test]
+    fn parse_range_multiple_ranges_ignored() {
+        assert_eq!(parse_range("bytes=0-10, 20-30", SIZE), RangeRequest::Full);
+    }
+
+    #[test]
+    fn validators_match_etag() {
+        let validators = FileValidators::new(SIZE, Some(SystemTime::UNIX_EPOCH));
+        assert!(validators.matches(&validators.etag.clone()));
+        assert!(!validators.matches("\"other\""));
+        assert!(!validators.matches(&format!("W/{}", validators.etag)));
+    }
+
+    #[test]
+    fn validators_match_any_etag() {
+        let validators = FileValidators::new(SIZE, Some(SystemTime::UNIX_EPOCH));
+        assert!(validators.matches_any(&format!("\"other\", {}", validators.etag)));
+        assert!(validators.matches_any(&format!("W/{}", validators.etag)));
+        assert!(validators.matches_any("*"));
+        assert!(!validators.matches_any("\"other\""));
+    }
+
+    #[test]
+    fn validators_match_last_modified() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_693_000_000);
+        let validators = FileValidators::new(SIZE, Some(modified));
+        assert!(validators.matches(&httpdate::fmt_http_date(modified)));
+        assert!(!validators.matches(&httpdate::fmt_http_date(modified + Duration::from_secs(1))));
+    }
+
+    /// The status, the `Location` header and the JSON body of the answer to a queued remux.
+    async fn queued(result: Result<Response, ApiError>) -> (StatusCode, String, Value) {
+        let response = result.unwrap();
+        let location = response.headers()[header::LOCATION]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = vec![];
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        (status, location, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[sqlx::test]
+    async fn stream_audio_track_queues_remux(pool: PgPool) -> Result<(), sqlx::Error> {
+        let catalog = Catalog::create(&pool, Faker.fake::<CreateCatalogRequest>()).await?;
+        let video = Video::create(
+            &pool,
+            CreateVideoRequest {
+                catalog_id: catalog.id,
+                sequent_id: None,
+                size: 1000,
+                ..Faker.fake()
+            },
+        )
+        .await?;
+        let tracks = (1..=2)
+            .map(|stream_index| CreateAudioTrackRequest {
+                video_id: video.id,
+                stream_index,
+                ..Faker.fake()
+            })
+            .collect();
+        AudioTrack::create_many(&pool, tracks).await?;
+
+        let cache_dir = std::env::temp_dir().join(format!("binge-stream-{}", std::process::id()));
+        let transcoder = Transcoder::new(PathBuf::from("ffmpeg"), cache_dir.clone(), 1);
+        let jobs = JobQueue::new(pool.clone());
+        let stream = || {
+            stream_video(
+                StreamEndpoint { id: video.id },
+                CurrentUser {
+                    id: 1,
+                    username: Faker.fake(),
+                    role: UserRole::Admin,
+                },
+                State(pool.clone()),
+                State(transcoder.clone()),
+                State(jobs.clone()),
+                QueryParams(StreamParams { audio: Some(2) }),
+                HeaderMap::new(),
+            )
+        };
+
+        // the first requests queue a single job
+        for _ in 0..2 {
+            let (status, location, body) = queued(stream().await).await;
+            assert_eq!(status, StatusCode::ACCEPTED);
+            let job_id = body["job_id"].as_i64().unwrap();
+            assert_eq!(location, format!("/api/jobs/{job_id}"));
+            assert_eq!(body["status"], "queued");
+        }
+        let queued_jobs = TranscodeJob::find_all(&pool, vec![], vec![], None).await?;
+        assert_eq!(queued_jobs.len(), 1);
+        assert_eq!(queued_jobs[0].kind, TranscodeJobKind::Remux);
+        assert_eq!(queued_jobs[0].audio_index, Some(2));
+
+        // a failed remux is retried by a new job, a cancelled one is not
+        let job = TranscodeJob::claim_next(&pool).await?.unwrap();
+        TranscodeJob::finish(&pool, job.id, TranscodeJobStatus::Failed, None).await?;
+        let (status, _, body) = queued(stream().await).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_ne!(body["job_id"], job.id);
+        let job = TranscodeJob::claim_next(&pool).await?.unwrap();
+        TranscodeJob::finish(&pool, job.id, TranscodeJobStatus::Cancelled, None).await?;
+        let result = stream().await;
+        assert!(matches!(result, Err(ApiError::Conflict(_))), "{result:?}");
+
+        let path = transcoder.remux_path(&video, Some(2));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "remuxed").unwrap();
+        let result = stream().await;
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+        assert_eq!(result.unwrap().status(), StatusCode::OK);
+
+        Ok(())
+    }
+}
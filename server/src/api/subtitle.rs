@@ -0,0 +1,173 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderValue},
+    response::IntoResponse,
+    Json, Router,
+};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::fs;
+use tracing::{error, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    crud::Entity,
+    error::ApiError,
+    model::{EntityId, Subtitle, SubtitleFormat},
+    subtitles,
+    transcoder::Transcoder,
+};
+
+use super::{auth::CurrentUser, AppState, LimitedBody};
+
+const WEBVTT_MIME_TYPE: &str = "text/vtt; charset=utf-8";
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/subtitles", rejection(ApiError))]
+pub struct VideoSubtitlesEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/subtitles/:track", rejection(ApiError))]
+pub struct SubtitleTrackEndpoint {
+    id: EntityId,
+    track: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubtitleDto {
+    id: EntityId,
+    video_id: EntityId,
+    /// The format of the source, every subtitle is served as WebVTT.
+    format: SubtitleFormat,
+    language: Option<String>,
+    title: Option<String>,
+    /// The name of the subtitle in the track selection of a player.
+    label: String,
+    is_default: bool,
+    is_forced: bool,
+    /// Whether the subtitle is embedded in the video file, or a side-car file next to it.
+    embedded: bool,
+    /// Link to the WebVTT track.
+    url: String,
+}
+
+impl From<Subtitle> for SubtitleDto {
+    fn from(subtitle: Subtitle) -> Self {
+        Self {
+            label: subtitles::label(&subtitle),
+            url: format!(
+                "/api/videos/{}/subtitles/{}.vtt",
+                subtitle.video_id, subtitle.id
+            ),
+            embedded: subtitle.stream_index.is_some(),
+            id: subtitle.id,
+            video_id: subtitle.video_id,
+            format: subtitle.format,
+            language: subtitle.language,
+            title: subtitle.title,
+            is_default: subtitle.is_default,
+            is_forced: subtitle.is_forced,
+        }
+    }
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(list_video_subtitles)
+        .typed_get(subtitle_track)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/subtitles",
+    tag = "video",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Lists the embedded and side-car subtitles of the video", body = [SubtitleDto]),
+        (status = 404, description = "Video not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn list_video_subtitles(
+    VideoSubtitlesEndpoint { id }: VideoSubtitlesEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    let subtitles = Subtitle::find_all_by_video(&pool, video.id)
+        .await?
+        .into_iter()
+        .map(SubtitleDto::from)
+        .collect::<Vec<_>>();
+    Ok(Json(subtitles))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/subtitles/{track}",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ("track" = String, Path, description = "Subtitle file name, e.g. `12.vtt`")
+    ),
+    responses(
+        (status = 200, description = "Serves the subtitle converted to WebVTT, embedded tracks are extracted first if needed", content_type = "text/vtt", body = String),
+        (status = 404, description = "Video, subtitle or its file not found")
+    )
+)]
+#[instrument(skip(pool, transcoder))]
+pub async fn subtitle_track(
+    SubtitleTrackEndpoint { id, track }: SubtitleTrackEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(transcoder): State<Transcoder>,
+) -> Result<impl IntoResponse, ApiError> {
+    let subtitle_id = track
+        .strip_suffix(".vtt")
+        .and_then(|track| track.parse::<EntityId>().ok())
+        .ok_or(ApiError::NotFound)?;
+    let video = user.find_video(&pool, id).await?;
+    let subtitle = Subtitle::find(&pool, subtitle_id)
+        .await?
+        .filter(|subtitle| subtitle.video_id == id)
+        .ok_or(ApiError::NotFound)?;
+
+    let track = match (&subtitle.path, subtitle.stream_index) {
+        (Some(path), _) => {
+            let bytes = fs::read(path).await.map_err(ApiError::from_io)?;
+            subtitles::to_webvtt(subtitle.format, &subtitles::decode(&bytes)).ok_or_else(|| {
+                error!("side-car subtitle cannot be converted: {path}");
+                ApiError::Internal
+            })?
+        }
+        (None, Some(index)) => {
+            if !fs::try_exists(video.path())
+                .await
+                .map_err(ApiError::from_io)?
+            {
+                return Err(ApiError::NotFound);
+            }
+            let path = transcoder.subtitle(&video, index).await.map_err(|e| {
+                error!("could not extract subtitle: {e:#}");
+                ApiError::Internal
+            })?;
+            let bytes = fs::read(path).await.map_err(ApiError::from_io)?;
+            subtitles::decode(&bytes)
+        }
+        (None, None) => {
+            error!("subtitle {} has neither a path nor a stream", subtitle.id);
+            return Err(ApiError::Internal);
+        }
+    };
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(WEBVTT_MIME_TYPE),
+        )],
+        track,
+    ))
+}
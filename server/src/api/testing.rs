@@ -0,0 +1,127 @@
+use axum::{
+    body::{Body, HttpBody},
+    http::{header, request, Method, Request, StatusCode},
+    Router,
+};
+use confique::Config;
+use fake::{Fake, Faker};
+use serde_json::Value;
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use tower::ServiceExt;
+
+use crate::{
+    auth,
+    configuration::Configuration,
+    crud::{user::CreateUserRequest, Entity},
+    jobs::JobQueue,
+    metadata::Prober,
+    model::{Session, User, UserRole},
+    scanner::Scanner,
+    transcoder::Transcoder,
+};
+
+use super::{auth::SESSION_COOKIE, routes, AppState};
+
+/// The API without workers and library roots, configured by the defaults and the environment.
+/// Sends requests through its routes, against the database of a `sqlx::test`.
+pub struct TestApp {
+    router: Router,
+}
+
+/// How a request is authenticated.
+pub enum Credentials {
+    Anonymous,
+    Session(String),
+    Token(String),
+}
+
+impl TestApp {
+    pub fn new(pool: PgPool) -> Self {
+        // the database url is taken from the environment, as by `sqlx::test`
+        let config = Configuration::builder().env().load().unwrap();
+        let cache_dir = std::env::temp_dir().join(format!("binge-api-{}", std::process::id()));
+        let scanner = Scanner::new(vec![], Prober::new(config.media().ffprobe()), pool.clone());
+        let transcoder = Transcoder::new(config.media().ffmpeg(), cache_dir, 1);
+        let jobs = JobQueue::new(pool.clone());
+        let state = AppState::new(config, pool, scanner, transcoder, jobs);
+        Self {
+            router: routes(state),
+        }
+    }
+
+    /// Creates a user with the role and a session for them.
+    pub async fn log_in(pool: &PgPool, role: UserRole) -> (User, Credentials) {
+        let user = User::create(
+            pool,
+            CreateUserRequest {
+                username: Faker.fake(),
+                password_hash: auth::DUMMY_PASSWORD_HASH.to_owned(),
+                role,
+            },
+        )
+        .await
+        .unwrap();
+        let token = auth::generate_token();
+        Session::create(
+            pool,
+            user.id,
+            &auth::hash_token(&token),
+            OffsetDateTime::now_utc() + Duration::hours(1),
+        )
+        .await
+        .unwrap();
+        (user, Credentials::Session(token))
+    }
+
+    /// Sends a request, returns the status and the JSON body of the response, `null` without a body.
+    pub async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        credentials: &Credentials,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Self::request(method, uri, credentials);
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        self.call(request).await
+    }
+
+    /// Starts an authenticated request, for the ones [`Self::send`] cannot build.
+    pub fn request(method: Method, uri: &str, credentials: &Credentials) -> request::Builder {
+        let mut request = Request::builder().method(method).uri(uri);
+        match credentials {
+            Credentials::Anonymous => {}
+            Credentials::Session(token) => {
+                request = request.header(header::COOKIE, format!("{SESSION_COOKIE}={token}"));
+            }
+            Credentials::Token(token) => {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+        }
+        request
+    }
+
+    /// Sends a request built by the test, returns the status and the JSON body of the response like [`Self::send`].
+    pub async fn call(&self, request: Request<Body>) -> (StatusCode, Value) {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = vec![];
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes).unwrap()
+        };
+        (status, body)
+    }
+}
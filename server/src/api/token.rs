@@ -0,0 +1,261 @@
+use async_trait::async_trait;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    auth,
+    crud::api_token::CreateApiTokenRequest,
+    error::ApiError,
+    model::{ApiToken, EntityId, TokenScope},
+};
+
+use super::{
+    auth::CurrentUser,
+    validation::{ValidJson, Validate, Validator, MAX_NAME_LENGTH},
+    AppState, LimitedBody,
+};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/me/tokens")]
+pub struct TokensEndpoint;
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/me/tokens/:id", rejection(ApiError))]
+pub struct TokenEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiTokenDto {
+    id: EntityId,
+    name: String,
+    scope: TokenScope,
+    #[serde(with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_used_at: Option<OffsetDateTime>,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+impl From<ApiToken> for ApiTokenDto {
+    fn from(token: ApiToken) -> Self {
+        Self {
+            id: token.id,
+            name: token.name,
+            scope: token.scope,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+            updated_at: token.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiTokenDto {
+    /// The token to send as `Authorization: Bearer <token>`, it cannot be retrieved again.
+    token: String,
+    #[serde(flatten)]
+    details: ApiTokenDto,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiTokenDto {
+    /// Describes where the token is used, e.g. `living room player`.
+    name: String,
+    scope: TokenScope,
+    /// The token never expires without it.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+}
+
+#[async_trait]
+impl Validate for CreateApiTokenDto {
+    async fn validate(&self, validator: &mut Validator, _: &AppState) -> Result<(), ApiError> {
+        let now = OffsetDateTime::now_utc();
+        validator
+            .length("name", &self.name, 1..=MAX_NAME_LENGTH)
+            .check(
+                "expires_at",
+                self.expires_at.is_none_or(|expires_at| expires_at > now),
+                "must be in the future",
+            );
+        Ok(())
+    }
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(list_tokens)
+        .typed_post(create_token)
+        .typed_delete(delete_token)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me/tokens",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Lists the API tokens of the logged in user", body = [ApiTokenDto])
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn list_tokens(
+    _: TokensEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tokens = ApiToken::find_all_by_user(&pool, user.id).await?;
+    Ok(Json(
+        tokens
+            .into_iter()
+            .map(ApiTokenDto::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/me/tokens",
+    tag = "auth",
+    request_body = CreateApiTokenDto,
+    responses(
+        (status = 201, description = "Creates an API token for the logged in user, the token is shown only in this response", body = CreatedApiTokenDto),
+        (status = 422, description = "The payload is malformed or has invalid fields, e.g. the expiry is in the past")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn create_token(
+    _: TokensEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    ValidJson(dto): ValidJson<CreateApiTokenDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let token = auth::generate_token();
+    let api_token = ApiToken::create(
+        &pool,
+        CreateApiTokenRequest {
+            user_id: user.id,
+            name: dto.name,
+            token_hash: auth::hash_token(&token),
+            scope: dto.scope,
+            expires_at: dto.expires_at,
+        },
+    )
+    .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiTokenDto {
+            token,
+            details: ApiTokenDto::from(api_token),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/me/tokens/{id}",
+    tag = "auth",
+    params(("id" = i64, Path, description = "API token id")),
+    responses(
+        (status = 204, description = "Revokes an API token of the logged in user"),
+        (status = 404, description = "The user has no such API token")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_token(
+    TokenEndpoint { id }: TokenEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    if ApiToken::delete_by_user(&pool, id, user.id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::testing::TestApp, model::UserRole};
+    use axum::http::Method;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[sqlx::test]
+    async fn tokens_created_listed_and_revoked(pool: PgPool) {
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let (_, other) = TestApp::log_in(&pool, UserRole::Admin).await;
+        let app = TestApp::new(pool);
+
+        let dto = json!({ "name": "living room player", "scope": "read" });
+        let (status, created) = app
+            .send(Method::POST, "/me/tokens", &credentials, Some(dto))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["token"].as_str().unwrap().len(), 64);
+        assert_eq!(created["scope"], "read");
+        let id = created["id"].as_i64().unwrap();
+
+        let (status, tokens) = app
+            .send(Method::GET, "/me/tokens", &credentials, None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            tokens,
+            json!([{
+                "id": id,
+                "name": "living room player",
+                "scope": "read",
+                "expires_at": null,
+                "last_used_at": null,
+                "created_at": created["created_at"],
+                "updated_at": created["updated_at"],
+            }])
+        );
+        let (_, tokens) = app.send(Method::GET, "/me/tokens", &other, None).await;
+        assert_eq!(tokens, json!([]));
+
+        // only the owner revokes a token
+        let uri = format!("/me/tokens/{id}");
+        let (status, _) = app.send(Method::DELETE, &uri, &other, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = app.send(Method::DELETE, &uri, &credentials, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, tokens) = app
+            .send(Method::GET, "/me/tokens", &credentials, None)
+            .await;
+        assert_eq!(tokens, json!([]));
+        let (status, _) = app.send(Method::DELETE, &uri, &credentials, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn token_expiring_in_the_past_rejected(pool: PgPool) {
+        let (_, credentials) = TestApp::log_in(&pool, UserRole::Viewer).await;
+        let app = TestApp::new(pool);
+
+        let dto = json!({ "name": "", "scope": "write", "expires_at": "2020-01-01T00:00:00Z" });
+        let (status, body) = app
+            .send(Method::POST, "/me/tokens", &credentials, Some(dto))
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let fields = body["error"]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["field"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["name", "expires_at"]);
+    }
+}
@@ -0,0 +1,337 @@
+use std::{ops::RangeInclusive, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    auth,
+    configuration::Configuration,
+    crud::{
+        user::{CreateUserRequest, UpdateUserRequest, UserFiltering, UserOrdering},
+        Counted, Entity,
+    },
+    error::ApiError,
+    model::{CatalogGrant, EntityId, User, UserRole},
+};
+
+use super::{
+    auth::{AdminUser, UserDto},
+    common::ListParams,
+    extract::{JsonBody, QueryParams},
+    validation::{ValidJson, Validate, Validator},
+    AppState, LimitedBody,
+};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/users")]
+pub struct UsersEndpoint;
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/users/:id", rejection(ApiError))]
+pub struct UserEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/users/:id/grants", rejection(ApiError))]
+pub struct UserGrantsEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateUserDto {
+    username: String,
+    #[schema(value_type = String, format = Password)]
+    password: Secret<String>,
+    role: UserRole,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateUserDto {
+    username: String,
+    role: UserRole,
+    /// The new password, the current one is kept without it.
+    #[schema(value_type = Option<String>, format = Password)]
+    password: Option<Secret<String>>,
+}
+
+const MAX_USERNAME_LENGTH: usize = 64;
+const PASSWORD_LENGTH: RangeInclusive<usize> = 8..=128;
+
+#[async_trait]
+impl Validate for CreateUserDto {
+    async fn validate(&self, validator: &mut Validator, _: &AppState) -> Result<(), ApiError> {
+        validator
+            .length("username", &self.username, 1..=MAX_USERNAME_LENGTH)
+            .length("password", self.password.expose_secret(), PASSWORD_LENGTH);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Validate for UpdateUserDto {
+    async fn validate(&self, validator: &mut Validator, _: &AppState) -> Result<(), ApiError> {
+        validator.length("username", &self.username, 1..=MAX_USERNAME_LENGTH);
+        if let Some(password) = &self.password {
+            validator.length("password", password.expose_secret(), PASSWORD_LENGTH);
+        }
+        Ok(())
+    }
+}
+
+/// The catalogs a guest can see, other roles see every catalog regardless.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CatalogGrantsDto {
+    catalog_ids: Vec<EntityId>,
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(list_users)
+        .typed_post(create_user)
+        .typed_get(find_user)
+        .typed_put(update_user)
+        .typed_delete(delete_user)
+        .typed_get(list_user_grants)
+        .typed_put(replace_user_grants)
+}
+
+async fn hash_password(password: Secret<String>) -> Result<String, ApiError> {
+    auth::hash_password(password).await.map_err(|e| {
+        error!("{e:#}");
+        ApiError::Internal
+    })
+}
+
+async fn ensure_username_free(
+    pool: &PgPool,
+    username: &str,
+    id: Option<EntityId>,
+) -> Result<(), ApiError> {
+    match User::find_by_username(pool, username).await? {
+        Some(user) if Some(user.id) != id => Err(ApiError::Conflict(format!(
+            "the username '{username}' is already taken"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users",
+    tag = "user",
+    params(ListParams),
+    responses(
+        (status = 200, description = "Lists users, in a page when paged by page, size or cursor", body = UserListing),
+        (status = 400, description = "Invalid filtering, ordering or pagination"),
+        (status = 403, description = "Not an admin")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn list_users(
+    _: UsersEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    QueryParams(params): QueryParams<ListParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let filters = params.filters::<UserFiltering>()?;
+    let ordering = params.ordering::<UserOrdering>()?;
+    let pagination =
+        params.keyed_pagination::<User>(&ordering, config.listing().max_page_size())?;
+    let Counted {
+        items: mut users,
+        total,
+    } = User::find_all_counted(&pool, filters, ordering.clone(), pagination).await?;
+    let cursors = params.cursors(&ordering, &mut users);
+    Ok(params.listing(
+        users.into_iter().map(UserDto::from).collect::<Vec<_>>(),
+        total,
+        cursors,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users",
+    tag = "user",
+    request_body = CreateUserDto,
+    responses(
+        (status = 201, description = "Creates a user", body = UserDto),
+        (status = 403, description = "Not an admin"),
+        (status = 409, description = "The username is already taken"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn create_user(
+    _: UsersEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    ValidJson(dto): ValidJson<CreateUserDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_username_free(&pool, &dto.username, None).await?;
+    let user = User::create(
+        &pool,
+        CreateUserRequest {
+            username: dto.username,
+            password_hash: hash_password(dto.password).await?,
+            role: dto.role,
+        },
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(UserDto::from(user))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{id}",
+    tag = "user",
+    params(("id" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Finds a user", body = UserDto),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "User not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_user(
+    UserEndpoint { id }: UserEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = User::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/users/{id}",
+    tag = "user",
+    params(("id" = i64, Path, description = "User id")),
+    request_body = UpdateUserDto,
+    responses(
+        (status = 200, description = "Updates a user", body = UserDto),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "The username is already taken, or admins would demote themselves"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn update_user(
+    UserEndpoint { id }: UserEndpoint,
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    ValidJson(dto): ValidJson<UpdateUserDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = User::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    // otherwise the last admin could lock everyone out of the administration
+    if user.id == admin.id && dto.role != UserRole::Admin {
+        return Err(ApiError::Conflict(
+            "admins cannot take away their own admin role".to_owned(),
+        ));
+    }
+    ensure_username_free(&pool, &dto.username, Some(user.id)).await?;
+    let password_hash = match dto.password {
+        Some(password) => hash_password(password).await?,
+        None => user.password_hash,
+    };
+    let user = User::update(
+        &pool,
+        UpdateUserRequest {
+            id,
+            username: dto.username,
+            password_hash,
+            role: dto.role,
+        },
+    )
+    .await?
+    .ok_or(ApiError::NotFound)?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/{id}",
+    tag = "user",
+    params(("id" = i64, Path, description = "User id")),
+    responses(
+        (status = 204, description = "Deletes a user with their sessions"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Admins cannot delete themselves")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_user(
+    UserEndpoint { id }: UserEndpoint,
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    if id == admin.id {
+        return Err(ApiError::Conflict(
+            "admins cannot delete themselves".to_owned(),
+        ));
+    }
+    if User::delete(&pool, id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{id}/grants",
+    tag = "user",
+    params(("id" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Lists the catalogs granted to a user", body = CatalogGrantsDto),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "User not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn list_user_grants(
+    UserGrantsEndpoint { id }: UserGrantsEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = User::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    let catalog_ids = CatalogGrant::find_catalog_ids(&pool, user.id).await?;
+    Ok(Json(CatalogGrantsDto { catalog_ids }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/users/{id}/grants",
+    tag = "user",
+    params(("id" = i64, Path, description = "User id")),
+    request_body = CatalogGrantsDto,
+    responses(
+        (status = 200, description = "Replaces the catalogs granted to a user", body = CatalogGrantsDto),
+        (status = 400, description = "One of the catalogs does not exist"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "User not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn replace_user_grants(
+    UserGrantsEndpoint { id }: UserGrantsEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    JsonBody(dto): JsonBody<CatalogGrantsDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = User::find(&pool, id).await?.ok_or(ApiError::NotFound)?;
+    let catalog_ids = CatalogGrant::replace(&pool, user.id, &dto.catalog_ids)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("one of the catalogs does not exist".to_owned()))?;
+    Ok(Json(CatalogGrantsDto { catalog_ids }))
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt::Display,
+    ops::RangeInclusive,
+    path::{Component, Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use axum::{body::HttpBody, extract::FromRequest, http::Request, BoxError, Json};
+use serde::de::DeserializeOwned;
+
+use crate::error::{ApiError, FieldError};
+
+use super::AppState;
+
+pub const MAX_NAME_LENGTH: usize = 255;
+pub const MAX_SHORT_DESC_LENGTH: usize = 500;
+pub const MAX_LONG_DESC_LENGTH: usize = 10_000;
+pub const MAX_PATH_LENGTH: usize = 4096;
+
+/// A request payload checking its fields before it is handled.
+#[async_trait]
+pub trait Validate {
+    /// Reports the invalid fields to the validator, fails only when the checks themselves fail.
+    async fn validate(&self, validator: &mut Validator, state: &AppState) -> Result<(), ApiError>;
+}
+
+/// Validates every item of a list, their fields are prefixed with their index.
+#[async_trait]
+impl<T: Validate + Sync> Validate for Vec<T> {
+    async fn validate(&self, validator: &mut Validator, state: &AppState) -> Result<(), ApiError> {
+        for (index, item) in self.iter().enumerate() {
+            validator.prefix = format!("[{index}].");
+            item.validate(validator, state).await?;
+        }
+        validator.prefix.clear();
+        Ok(())
+    }
+}
+
+/// Collects the invalid fields, so all of them are reported at once.
+#[derive(Debug, Default)]
+pub struct Validator {
+    prefix: String,
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn check(&mut self, field: &str, valid: bool, message: impl Into<String>) -> &mut Self {
+        if !valid {
+            self.errors.push(FieldError {
+                field: format!("{}{field}", self.prefix),
+                message: message.into(),
+            });
+        }
+        self
+    }
+
+    /// The number of characters of the value is in the range.
+    pub fn length(&mut self, field: &str, value: &str, range: RangeInclusive<usize>) -> &mut Self {
+        let length = value.chars().count();
+        let message = match (range.start(), range.end()) {
+            (0, max) => format!("must be at most {max} characters long"),
+            (min, max) => format!("must be between {min} and {max} characters long"),
+        };
+        self.check(field, range.contains(&length), message)
+    }
+
+    pub fn positive<T: PartialOrd + Default>(&mut self, field: &str, value: T) -> &mut Self {
+        self.check(field, value > T::default(), "must be greater than zero")
+    }
+
+    pub fn not_negative<T: PartialOrd + Default>(&mut self, field: &str, value: T) -> &mut Self {
+        self.check(field, value >= T::default(), "must not be negative")
+    }
+
+    /// The referenced entity exists.
+    pub fn exists(&mut self, field: &str, id: impl Display, exists: bool) -> &mut Self {
+        self.check(field, exists, format!("{id} does not exist"))
+    }
+
+    /// The path is absolute and inside one of the roots, without going up to their parents.
+    pub fn path_within(&mut self, field: &str, path: &str, roots: &[PathBuf]) -> &mut Self {
+        let path = Path::new(path);
+        let valid = path.is_absolute()
+            && path.components().all(|c| c != Component::ParentDir)
+            && roots.iter().any(|root| path.starts_with(root));
+        self.check(
+            field,
+            valid,
+            "must be an absolute path inside a library root",
+        )
+    }
+
+    /// Succeeds without invalid fields.
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::InvalidFields(self.errors))
+        }
+    }
+}
+
+/// Checks the texts describing a catalog or a video.
+pub fn validate_descriptions(
+    validator: &mut Validator,
+    display_name: &str,
+    short_desc: &str,
+    long_desc: &str,
+) {
+    validator
+        .length("display_name", display_name, 1..=MAX_NAME_LENGTH)
+        .length("short_desc", short_desc, 0..=MAX_SHORT_DESC_LENGTH)
+        .length("long_desc", long_desc, 0..=MAX_LONG_DESC_LENGTH);
+}
+
+/// A JSON payload whose fields are valid, a malformed or invalid payload is rejected.
+#[derive(Debug)]
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<AppState, B> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate + Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request<B>, state: &AppState) -> Result<Self, ApiError> {
+        let Json(payload) = Json::<T>::from_request(request, state).await?;
+        let mut validator = Validator::default();
+        payload.validate(&mut validator, state).await?;
+        validator.finish()?;
+        Ok(Self(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn fields(validator: Validator) -> Vec<String> {
+        match validator.finish() {
+            Err(ApiError::InvalidFields(errors)) => {
+                errors.into_iter().map(|error| error.field).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn valid_fields_pass() {
+        let mut validator = Validator::default();
+        validator
+            .length("name", "movies", 1..=10)
+            .positive("size", 1)
+            .not_negative("duration", 0)
+            .positive("framerate", 23.976);
+        assert!(validator.finish().is_ok());
+    }
+
+    #[test]
+    fn every_invalid_field_reported() {
+        let mut validator = Validator::default();
+        validator
+            .length("name", "", 1..=10)
+            .length("desc", "ok", 0..=10)
+            .positive("size", -1)
+            .not_negative("duration", -1)
+            .positive("framerate", 0.0)
+            .positive("bitrate", f64::NAN);
+        assert_eq!(
+            fields(validator),
+            vec!["name", "size", "duration", "framerate", "bitrate"]
+        );
+    }
+
+    #[test]
+    fn length_counts_characters() {
+        let mut validator = Validator::default();
+        validator.length("name", "ééé", 1..=3);
+        assert!(validator.finish().is_ok());
+    }
+
+    #[test]
+    fn path_within_roots() {
+        let roots = vec![
+            PathBuf::from("/media/movies"),
+            PathBuf::from("/media/series"),
+        ];
+        let mut validator = Validator::default();
+        validator
+            .path_within("valid", "/media/series/lost/s01e01.mp4", &roots)
+            .path_within("relative", "media/movies/a.mp4", &roots)
+            .path_within("outside", "/media/moviesx/a.mp4", &roots)
+            .path_within("escaping", "/media/movies/../../etc/passwd", &roots);
+        assert_eq!(fields(validator), vec!["relative", "outside", "escaping"]);
+    }
+}
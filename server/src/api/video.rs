@@ -0,0 +1,550 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    configuration::Configuration,
+    crud::{
+        video::{CreateVideoRequest, UpdateVideoRequest, VideoFiltering, VideoOrdering},
+        Counted, Entity,
+    },
+    error::ApiError,
+    model::{
+        AudioChannels, AudioTrack, Bytes, BytesPerSecond, Catalog, EntityId, FramesPerSecond,
+        ScreenHeight, ScreenWidth, Seconds, Video,
+    },
+};
+
+use super::{
+    auth::{AdminUser, CurrentUser},
+    common::{BulkDeleteDto, BulkDeleteResponse, ListParams},
+    extract::{JsonBody, QueryParams},
+    revision::{etag_header, IfMatch},
+    validation::{validate_descriptions, ValidJson, Validate, Validator, MAX_PATH_LENGTH},
+    AppState, LimitedBody,
+};
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/videos")]
+pub struct VideosEndpoint;
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/videos/bulk")]
+pub struct VideosBulkEndpoint;
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id", rejection(ApiError))]
+pub struct VideoEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/catalogs/:id/videos", rejection(ApiError))]
+pub struct CatalogVideosEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VideoDto {
+    id: EntityId,
+    path: String,
+    display_name: String,
+    short_desc: String,
+    long_desc: String,
+    catalog_id: EntityId,
+    sequent_id: Option<EntityId>,
+    /// Link to the next video of the series, if there is one.
+    next: Option<String>,
+
+    size: Bytes,
+    duration: Seconds,
+    bitrate: BytesPerSecond,
+    width: ScreenWidth,
+    height: ScreenHeight,
+    framerate: FramesPerSecond,
+    /// The audio tracks of the video file, the `index` selects one with the `audio` parameter of the streaming endpoints.
+    audio_tracks: Vec<AudioTrackDto>,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AudioTrackDto {
+    /// The position among the audio tracks of the video file.
+    index: i32,
+    codec: String,
+    language: Option<String>,
+    title: Option<String>,
+    /// Zero if unknown.
+    channels: AudioChannels,
+    is_default: bool,
+}
+
+impl From<AudioTrack> for AudioTrackDto {
+    fn from(track: AudioTrack) -> Self {
+        Self {
+            index: track.stream_index,
+            codec: track.codec,
+            language: track.language,
+            title: track.title,
+            channels: track.channels,
+            is_default: track.is_default,
+        }
+    }
+}
+
+impl VideoDto {
+    fn new(video: Video, audio_tracks: Vec<AudioTrack>) -> Self {
+        Self {
+            id: video.id,
+            path: video.path,
+            display_name: video.display_name,
+            short_desc: video.short_desc,
+            long_desc: video.long_desc,
+            catalog_id: video.catalog_id,
+            sequent_id: video.sequent_id,
+            next: video.sequent_id.map(|id| format!("/api/videos/{id}")),
+            size: video.size,
+            duration: video.duration,
+            bitrate: video.bitrate,
+            width: video.width,
+            height: video.height,
+            framerate: video.framerate,
+            audio_tracks: audio_tracks.into_iter().map(AudioTrackDto::from).collect(),
+            created_at: video.created_at,
+            updated_at: video.updated_at,
+        }
+    }
+}
+
+/// The fields of a video, the same to create and to update it.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VideoPayloadDto {
+    path: String,
+    display_name: String,
+    short_desc: String,
+    long_desc: String,
+    catalog_id: EntityId,
+    sequent_id: Option<EntityId>,
+
+    size: Bytes,
+    duration: Seconds,
+    bitrate: BytesPerSecond,
+    width: ScreenWidth,
+    height: ScreenHeight,
+    framerate: FramesPerSecond,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateVideoDto {
+    #[serde(flatten)]
+    video: VideoPayloadDto,
+}
+
+impl From<CreateVideoDto> for CreateVideoRequest {
+    fn from(CreateVideoDto { video }: CreateVideoDto) -> Self {
+        Self {
+            path: video.path,
+            display_name: video.display_name,
+            short_desc: video.short_desc,
+            long_desc: video.long_desc,
+            catalog_id: video.catalog_id,
+            sequent_id: video.sequent_id,
+            size: video.size,
+            duration: video.duration,
+            bitrate: video.bitrate,
+            width: video.width,
+            height: video.height,
+            framerate: video.framerate,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateVideoDto {
+    #[serde(flatten)]
+    video: VideoPayloadDto,
+}
+
+impl UpdateVideoDto {
+    fn into_request(
+        self,
+        id: EntityId,
+        expected_updated_at: Option<Vec<OffsetDateTime>>,
+    ) -> UpdateVideoRequest {
+        let video = self.video;
+        UpdateVideoRequest {
+            id,
+            path: video.path,
+            display_name: video.display_name,
+            short_desc: video.short_desc,
+            long_desc: video.long_desc,
+            catalog_id: video.catalog_id,
+            sequent_id: video.sequent_id,
+            size: video.size,
+            duration: video.duration,
+            bitrate: video.bitrate,
+            width: video.width,
+            height: video.height,
+            framerate: video.framerate,
+            expected_updated_at,
+        }
+    }
+}
+
+#[async_trait]
+impl Validate for VideoPayloadDto {
+    async fn validate(&self, validator: &mut Validator, state: &AppState) -> Result<(), ApiError> {
+        validate_path(validator, state, &self.path);
+        validate_descriptions(
+            validator,
+            &self.display_name,
+            &self.short_desc,
+            &self.long_desc,
+        );
+        validate_media(
+            validator,
+            self.size,
+            self.duration,
+            self.bitrate,
+            self.width,
+            self.height,
+            self.framerate,
+        );
+        validate_links(validator, state, self.catalog_id, self.sequent_id).await
+    }
+}
+
+#[async_trait]
+impl Validate for CreateVideoDto {
+    async fn validate(&self, validator: &mut Validator, state: &AppState) -> Result<(), ApiError> {
+        self.video.validate(validator, state).await
+    }
+}
+
+#[async_trait]
+impl Validate for UpdateVideoDto {
+    async fn validate(&self, validator: &mut Validator, state: &AppState) -> Result<(), ApiError> {
+        self.video.validate(validator, state).await
+    }
+}
+
+fn validate_path(validator: &mut Validator, state: &AppState, path: &str) {
+    let roots = state.config.library().roots();
+    validator
+        .length("path", path, 1..=MAX_PATH_LENGTH)
+        .path_within("path", path, &roots);
+}
+
+/// The duration and the bitrate are zero when they are unknown.
+fn validate_media(
+    validator: &mut Validator,
+    size: Bytes,
+    duration: Seconds,
+    bitrate: BytesPerSecond,
+    width: ScreenWidth,
+    height: ScreenHeight,
+    framerate: FramesPerSecond,
+) {
+    validator
+        .positive("size", size)
+        .not_negative("duration", duration)
+        .not_negative("bitrate", bitrate)
+        .positive("width", width)
+        .positive("height", height)
+        .positive("framerate", framerate);
+}
+
+async fn validate_links(
+    validator: &mut Validator,
+    state: &AppState,
+    catalog_id: EntityId,
+    sequent_id: Option<EntityId>,
+) -> Result<(), ApiError> {
+    let catalog = Catalog::find(&state.database, catalog_id).await?;
+    validator.exists(
+        "catalog_id",
+        format!("catalog {catalog_id}"),
+        catalog.is_some(),
+    );
+    if let Some(sequent_id) = sequent_id {
+        let sequent = Video::find(&state.database, sequent_id).await?;
+        validator.exists(
+            "sequent_id",
+            format!("video {sequent_id}"),
+            sequent.is_some(),
+        );
+    }
+    Ok(())
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(list_videos)
+        .typed_post(create_video)
+        .typed_post(create_videos)
+        .typed_delete(delete_videos)
+        .typed_get(find_video)
+        .typed_put(update_video)
+        .typed_delete(delete_video)
+        .typed_get(list_catalog_videos)
+}
+
+/// Converts videos to DTOs, loading the audio tracks of all of them at once.
+pub(super) async fn to_dtos(pool: &PgPool, videos: Vec<Video>) -> Result<Vec<VideoDto>, ApiError> {
+    let ids = videos.iter().map(|video| video.id).collect::<Vec<_>>();
+    let mut audio_tracks = HashMap::<_, Vec<_>>::new();
+    for track in AudioTrack::find_all_by_videos(pool, &ids).await? {
+        audio_tracks.entry(track.video_id).or_default().push(track);
+    }
+    Ok(videos
+        .into_iter()
+        .map(|video| {
+            let tracks = audio_tracks.remove(&video.id).unwrap_or_default();
+            VideoDto::new(video, tracks)
+        })
+        .collect())
+}
+
+pub(super) async fn to_dto(pool: &PgPool, video: Video) -> Result<VideoDto, ApiError> {
+    let audio_tracks = AudioTrack::find_all_by_videos(pool, &[video.id]).await?;
+    Ok(VideoDto::new(video, audio_tracks))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos",
+    tag = "video",
+    params(ListParams),
+    responses(
+        (status = 200, description = "Lists videos, in a page when paged by page, size or cursor", body = VideoListing),
+        (status = 400, description = "Invalid filtering, ordering or pagination")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn list_videos(
+    _: VideosEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    QueryParams(params): QueryParams<ListParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let filters = params.filters::<VideoFiltering>()?;
+    let ordering = params.ordering::<VideoOrdering>()?;
+    let pagination =
+        params.keyed_pagination::<Video>(&ordering, config.listing().max_page_size())?;
+    let Counted {
+        items: mut videos,
+        total,
+    } = if user.role.is_restricted() {
+        Video::find_all_granted(&pool, user.id, filters, ordering.clone(), pagination).await?
+    } else {
+        Video::find_all_counted(&pool, filters, ordering.clone(), pagination).await?
+    };
+    let cursors = params.cursors(&ordering, &mut videos);
+    Ok(params.listing(to_dtos(&pool, videos).await?, total, cursors))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/catalogs/{id}/videos",
+    tag = "video",
+    params(("id" = i64, Path, description = "Catalog id"), ListParams),
+    responses(
+        (status = 200, description = "Lists the videos of a catalog, in a page when paged by page, size or cursor", body = VideoListing),
+        (status = 400, description = "Invalid filtering, ordering or pagination"),
+        (status = 404, description = "Catalog not found")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn list_catalog_videos(
+    CatalogVideosEndpoint { id }: CatalogVideosEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    QueryParams(params): QueryParams<ListParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let filters = params.filters::<VideoFiltering>()?;
+    let ordering = params.ordering::<VideoOrdering>()?;
+    let pagination =
+        params.keyed_pagination::<Video>(&ordering, config.listing().max_page_size())?;
+    if Catalog::find(&pool, id).await?.is_none() {
+        return Err(ApiError::NotFound);
+    }
+    user.ensure_catalog_access(&pool, id).await?;
+    let Counted {
+        items: mut videos,
+        total,
+    } = Video::find_all_by_catalog(&pool, id, filters, ordering.clone(), pagination).await?;
+    let cursors = params.cursors(&ordering, &mut videos);
+    Ok(params.listing(to_dtos(&pool, videos).await?, total, cursors))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/videos",
+    tag = "video",
+    request_body = CreateVideoDto,
+    responses(
+        (status = 201, description = "Creates a video", body = VideoDto),
+        (status = 403, description = "Not an admin"),
+        (status = 409, description = "The sequent video already follows another video"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn create_video(
+    _: VideosEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    ValidJson(dto): ValidJson<CreateVideoDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = Video::create(&pool, dto.into()).await?;
+    Ok((StatusCode::CREATED, Json(to_dto(&pool, video).await?)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/videos/bulk",
+    tag = "video",
+    request_body = [CreateVideoDto],
+    responses(
+        (status = 201, description = "Creates multiple videos at once", body = [VideoDto]),
+        (status = 403, description = "Not an admin"),
+        (status = 409, description = "A sequent video already follows another video"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn create_videos(
+    _: VideosBulkEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    ValidJson(dtos): ValidJson<Vec<CreateVideoDto>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let requests = dtos.into_iter().map(CreateVideoRequest::from).collect();
+    let videos = Video::create_many(&pool, requests).await?;
+    Ok((StatusCode::CREATED, Json(to_dtos(&pool, videos).await?)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/videos/bulk",
+    tag = "video",
+    request_body = BulkDeleteDto,
+    responses(
+        (status = 200, description = "Deletes multiple videos at once", body = BulkDeleteResponse),
+        (status = 403, description = "Not an admin")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_videos(
+    _: VideosBulkEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    JsonBody(dto): JsonBody<BulkDeleteDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = Video::delete_many(&pool, dto.ids).await?;
+    Ok(Json(BulkDeleteResponse { deleted }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}",
+    tag = "video",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Finds a video", body = VideoDto,
+            headers(("ETag" = String, description = "The revision of the video"))),
+        (status = 404, description = "Video not found")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_video(
+    VideoEndpoint { id }: VideoEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    Ok((
+        etag_header(video.updated_at),
+        Json(to_dto(&pool, video).await?),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/videos/{id}",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ("If-Match" = Option<String>, Header, description = "Only updates the video at the revision of one of these ETags")
+    ),
+    request_body = UpdateVideoDto,
+    responses(
+        (status = 200, description = "Updates a video", body = VideoDto,
+            headers(("ETag" = String, description = "The new revision of the video"))),
+        (status = 400, description = "The If-Match header is invalid"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Video not found"),
+        (status = 409, description = "The sequent video already follows another video, or the series would loop"),
+        (status = 412, description = "The video changed since the revision of the If-Match header"),
+        (status = 422, description = "The payload is malformed or has invalid fields")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn update_video(
+    VideoEndpoint { id }: VideoEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    if_match: IfMatch,
+    ValidJson(dto): ValidJson<UpdateVideoDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(video) = Video::update(&pool, dto.into_request(id, if_match.0.clone())).await? else {
+        return Err(if_match.rejection(Video::find(&pool, id).await?.is_some()));
+    };
+    Ok((
+        etag_header(video.updated_at),
+        Json(to_dto(&pool, video).await?),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/videos/{id}",
+    tag = "video",
+    params(
+        ("id" = i64, Path, description = "Video id"),
+        ("If-Match" = Option<String>, Header, description = "Only deletes the video at the revision of one of these ETags")
+    ),
+    responses(
+        (status = 204, description = "Deletes a video"),
+        (status = 400, description = "The If-Match header is invalid"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Video not found"),
+        (status = 412, description = "The video changed since the revision of the If-Match header")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_video(
+    VideoEndpoint { id }: VideoEndpoint,
+    _: AdminUser,
+    State(pool): State<PgPool>,
+    if_match: IfMatch,
+) -> Result<impl IntoResponse, ApiError> {
+    if Video::delete_unless_changed(&pool, id, if_match.0.as_deref()).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(if_match.rejection(Video::find(&pool, id).await?.is_some()))
+    }
+}
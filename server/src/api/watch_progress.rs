@@ -0,0 +1,236 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum_extra::routing::{RouterExt, TypedPath};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    configuration::Configuration,
+    crud::watch_progress,
+    error::ApiError,
+    model::{EntityId, Seconds, Video, WatchProgress},
+};
+
+use super::{
+    auth::CurrentUser,
+    extract::QueryParams,
+    validation::{ValidJson, Validate, Validator},
+    video::{to_dtos, VideoDto},
+    AppState, LimitedBody,
+};
+
+const DEFAULT_CONTINUE_WATCHING_LIMIT: u64 = 20;
+const MAX_CONTINUE_WATCHING_LIMIT: u64 = 100;
+
+#[derive(Debug, TypedPath, Deserialize)]
+#[typed_path("/videos/:id/progress", rejection(ApiError))]
+pub struct VideoProgressEndpoint {
+    id: EntityId,
+}
+
+#[derive(Debug, TypedPath)]
+#[typed_path("/me/continue-watching")]
+pub struct ContinueWatchingEndpoint;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WatchProgressDto {
+    video_id: EntityId,
+    /// Where to resume the video, in seconds.
+    position: Seconds,
+    watched: bool,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+impl From<WatchProgress> for WatchProgressDto {
+    fn from(progress: WatchProgress) -> Self {
+        Self {
+            video_id: progress.video_id,
+            position: progress.position,
+            watched: progress.watched,
+            created_at: progress.created_at,
+            updated_at: progress.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateWatchProgressDto {
+    /// Where the user stopped watching the video, in seconds.
+    position: Seconds,
+}
+
+#[async_trait]
+impl Validate for UpdateWatchProgressDto {
+    async fn validate(&self, validator: &mut Validator, _: &AppState) -> Result<(), ApiError> {
+        validator.not_negative("position", self.position);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ContinueWatchingDto {
+    video: VideoDto,
+    progress: WatchProgressDto,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ContinueWatchingParams {
+    /// The maximum number of videos to return, at most 100.
+    limit: Option<u64>,
+}
+
+pub fn router() -> Router<AppState, LimitedBody> {
+    Router::new()
+        .typed_get(find_progress)
+        .typed_put(update_progress)
+        .typed_delete(delete_progress)
+        .typed_get(continue_watching)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/videos/{id}/progress",
+    tag = "progress",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 200, description = "Finds where the logged in user stopped watching a video", body = WatchProgressDto),
+        (status = 403, description = "The catalog of the video is not granted to the user"),
+        (status = 404, description = "Video not found or not started yet")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn find_progress(
+    VideoProgressEndpoint { id }: VideoProgressEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    let progress = WatchProgress::find(&pool, user.id, video.id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(Json(WatchProgressDto::from(progress)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/videos/{id}/progress",
+    tag = "progress",
+    params(("id" = i64, Path, description = "Video id")),
+    request_body = UpdateWatchProgressDto,
+    responses(
+        (status = 200, description = "Records where the logged in user stopped watching a video, marking it as watched past the watched threshold", body = WatchProgressDto),
+        (status = 400, description = "The position is past the end of the video"),
+        (status = 403, description = "The catalog of the video is not granted to the user"),
+        (status = 404, description = "Video not found"),
+        (status = 422, description = "The payload is malformed or the position is negative")
+    )
+)]
+#[instrument(skip(pool, config))]
+pub async fn update_progress(
+    VideoProgressEndpoint { id }: VideoProgressEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Configuration>>,
+    ValidJson(dto): ValidJson<UpdateWatchProgressDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    if video.duration > 0 && dto.position > video.duration {
+        return Err(ApiError::BadRequest(format!(
+            "the position must be between 0 and {} seconds",
+            video.duration
+        )));
+    }
+    let watched = watch_progress::is_watched(
+        dto.position,
+        video.duration,
+        config.media().watched_threshold(),
+    );
+    let progress = WatchProgress::upsert(&pool, user.id, video.id, dto.position, watched).await?;
+    Ok(Json(WatchProgressDto::from(progress)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/videos/{id}/progress",
+    tag = "progress",
+    params(("id" = i64, Path, description = "Video id")),
+    responses(
+        (status = 204, description = "Forgets the progress of the logged in user, the video is unwatched again"),
+        (status = 403, description = "The catalog of the video is not granted to the user"),
+        (status = 404, description = "Video not found or not started yet")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn delete_progress(
+    VideoProgressEndpoint { id }: VideoProgressEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+) -> Result<impl IntoResponse, ApiError> {
+    let video = user.find_video(&pool, id).await?;
+    if WatchProgress::delete(&pool, user.id, video.id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me/continue-watching",
+    tag = "progress",
+    params(ContinueWatchingParams),
+    responses(
+        (status = 200, description = "Lists the started but unwatched videos of the logged in user, the most recently watched first", body = [ContinueWatchingDto]),
+        (status = 400, description = "Invalid limit")
+    )
+)]
+#[instrument(skip(pool))]
+pub async fn continue_watching(
+    _: ContinueWatchingEndpoint,
+    user: CurrentUser,
+    State(pool): State<PgPool>,
+    QueryParams(params): QueryParams<ContinueWatchingParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_CONTINUE_WATCHING_LIMIT);
+    if !(1..=MAX_CONTINUE_WATCHING_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {MAX_CONTINUE_WATCHING_LIMIT}"
+        )));
+    }
+    let progress = WatchProgress::find_continue_watching(
+        &pool,
+        user.id,
+        user.role.is_restricted(),
+        limit as i64,
+    )
+    .await?;
+    let ids = progress.iter().map(|p| p.video_id).collect::<Vec<_>>();
+    let videos = Video::find_all_by_ids(&pool, &ids).await?;
+    let video_ids = videos.iter().map(|video| video.id).collect::<Vec<_>>();
+    let mut videos = video_ids
+        .into_iter()
+        .zip(to_dtos(&pool, videos).await?)
+        .collect::<HashMap<_, _>>();
+    Ok(Json(
+        progress
+            .into_iter()
+            .filter_map(|progress| {
+                let video = videos.remove(&progress.video_id)?;
+                Some(ContinueWatchingDto {
+                    video,
+                    progress: WatchProgressDto::from(progress),
+                })
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
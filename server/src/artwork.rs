@@ -0,0 +1,107 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::model::{Catalog, EntityId, Seconds};
+
+/// The file name of a poster placed next to the videos of a catalog.
+pub const SIDE_CAR_POSTER: &str = "poster.jpg";
+
+/// The widest resized variant of an image, larger requested widths are rejected.
+pub const MAX_WIDTH: u32 = 1920;
+
+/// Every JPEG file starts with a start of image marker followed by another marker.
+const JPEG_MAGIC: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+/// The timestamp of the thumbnail frame of a video in seconds, at a percentage of its duration.
+pub fn thumbnail_timestamp(duration: Seconds, percentage: u8) -> f64 {
+    duration.max(0) as f64 * f64::from(percentage.min(100)) / 100.0
+}
+
+/// The path of the poster uploaded for a catalog, which takes precedence over its side-car poster.
+pub fn uploaded_poster_path(data_dir: &Path, catalog_id: EntityId) -> PathBuf {
+    data_dir.join("posters").join(format!("{catalog_id}.jpg"))
+}
+
+/// Finds the poster of a catalog, either an uploaded one or a side-car one in its directory.
+pub async fn find_poster(data_dir: &Path, catalog: &Catalog) -> io::Result<Option<PathBuf>> {
+    let candidates = [
+        uploaded_poster_path(data_dir, catalog.id),
+        catalog.path().join(SIDE_CAR_POSTER),
+    ];
+    for candidate in candidates {
+        if fs::try_exists(&candidate).await? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(&JPEG_MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn thumbnail_timestamp_percentage_of_duration() {
+        assert_eq!(thumbnail_timestamp(600, 10), 60.0);
+        assert_eq!(thumbnail_timestamp(5, 50), 2.5);
+        assert_eq!(thumbnail_timestamp(600, 150), 600.0);
+        assert_eq!(thumbnail_timestamp(0, 10), 0.0);
+        assert_eq!(thumbnail_timestamp(-1, 10), 0.0);
+    }
+
+    #[test]
+    fn uploaded_poster_path_by_catalog_id() {
+        assert_eq!(
+            uploaded_poster_path(Path::new("/data"), 12),
+            PathBuf::from("/data/posters/12.jpg")
+        );
+    }
+
+    #[tokio::test]
+    async fn find_poster_prefers_uploaded() {
+        let root = std::env::temp_dir().join(format!("artwork-{}", Faker.fake::<u32>()));
+        let catalog_dir = root.join("catalog");
+        let data_dir = root.join("data");
+        let catalog = Catalog {
+            path: catalog_dir.to_string_lossy().into_owned(),
+            ..Faker.fake()
+        };
+        std::fs::create_dir_all(&catalog_dir).unwrap();
+        std::fs::create_dir_all(data_dir.join("posters")).unwrap();
+
+        assert_eq!(find_poster(&data_dir, &catalog).await.unwrap(), None);
+
+        let side_car = catalog_dir.join(SIDE_CAR_POSTER);
+        std::fs::write(&side_car, JPEG_MAGIC).unwrap();
+        assert_eq!(
+            find_poster(&data_dir, &catalog).await.unwrap(),
+            Some(side_car)
+        );
+
+        let uploaded = uploaded_poster_path(&data_dir, catalog.id);
+        std::fs::write(&uploaded, JPEG_MAGIC).unwrap();
+        assert_eq!(
+            find_poster(&data_dir, &catalog).await.unwrap(),
+            Some(uploaded)
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn is_jpeg_by_magic() {
+        assert!(is_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00]));
+        assert!(!is_jpeg(b"\x89PNG\r\n"));
+        assert!(!is_jpeg(&[0xFF, 0xD8]));
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Context};
+use argon2::{
+    password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::{rngs::OsRng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tokio::task::spawn_blocking;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    configuration::Configuration,
+    crud::{user::CreateUserRequest, Entity},
+    logging::Logger,
+    model::{User, UserRole},
+};
+
+/// The number of random bytes in a session token.
+const TOKEN_BYTES: usize = 32;
+
+/// The hash of a random, discarded password, with the default parameters.
+/// Logging in as an unknown user is verified against it, so it takes as long as with an existing one.
+pub const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$ALx3OWm8rFU/v3hfSSKJiw$jrC8XLKu/wI8uuZyme5s4pWs4c/Q+Ao4aKhjQkzxi9w";
+
+/// Hashes a password with Argon2 and a random salt, on the blocking thread pool as it is deliberately slow.
+pub async fn hash_password(password: Secret<String>) -> anyhow::Result<String> {
+    spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.expose_secret().as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| anyhow!("could not hash password: {e}"))
+    })
+    .await
+    .context("password hashing panicked")?
+}
+
+/// Checks a password against an Argon2 hash in PHC string format, on the blocking thread pool.
+pub async fn verify_password(password: Secret<String>, hash: String) -> anyhow::Result<bool> {
+    spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash).map_err(|e| anyhow!("invalid password hash: {e}"))?;
+        match Argon2::default().verify_password(password.expose_secret().as_bytes(), &hash) {
+            Ok(()) => Ok(true),
+            Err(password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(anyhow!("could not verify password: {e}")),
+        }
+    })
+    .await
+    .context("password verification panicked")?
+}
+
+/// Generates a random token, hex encoded.
+pub fn generate_token() -> String {
+    let mut bytes = [0; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// Hashes a token for storage, a fast hash is enough as tokens have full entropy unlike passwords.
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Creates the admin user from the configuration when there are no users yet.
+#[instrument(skip_all)]
+pub async fn bootstrap_admin(
+    config: &Configuration,
+    pool: &PgPool,
+    _: &Logger,
+) -> anyhow::Result<()> {
+    if User::count_all(pool).await? > 0 {
+        return Ok(());
+    }
+    let Some(password) = config.auth().admin_password() else {
+        warn!("there are no users and no admin password is configured, nobody can log in");
+        return Ok(());
+    };
+    let username = config.auth().admin_username().to_owned();
+    let password_hash = hash_password(password.clone()).await?;
+    User::create(
+        pool,
+        CreateUserRequest {
+            username: username.clone(),
+            password_hash,
+            role: UserRole::Admin,
+        },
+    )
+    .await
+    .context("could not create admin user")?;
+    info!("created admin user '{username}'");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn password_verified_against_its_hash() {
+        let password: String = Faker.fake();
+        let hash = hash_password(Secret::new(password.clone())).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password(Secret::new(password.clone()), hash.clone())
+            .await
+            .unwrap());
+        assert!(!verify_password(Secret::new(format!("{password}x")), hash)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn dummy_password_hash_costs_as_much_as_real_ones() {
+        let hash = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+        let params = argon2::Params::try_from(&hash).unwrap();
+        let defaults = Argon2::default();
+        assert_eq!(hash.algorithm, argon2::Algorithm::default().ident());
+        assert_eq!(
+            (params.m_cost(), params.t_cost(), params.p_cost()),
+            (
+                defaults.params().m_cost(),
+                defaults.params().t_cost(),
+                defaults.params().p_cost()
+            )
+        );
+        assert!(
+            !verify_password(Secret::new(Faker.fake()), DUMMY_PASSWORD_HASH.to_owned())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_password_hash_rejected() {
+        let result = verify_password(Secret::new(Faker.fake()), "plain".to_owned()).await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn tokens_random_hex() {
+        let token = generate_token();
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn token_hash_sha256_hex() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -19,6 +19,8 @@ use confique::{
 use tower_http::cors::AllowOrigin;
 use tracing::{info, instrument};
 
+use crate::model::Seconds;
+
 #[derive(Debug, Config)]
 pub struct Configuration {
     /// Host to bind to.
@@ -36,6 +38,18 @@ pub struct Configuration {
     /// Server middleware configuration options.
     #[config(nested)]
     middlewares: Middlewares,
+    /// Media library configuration options.
+    #[config(nested)]
+    library: Library,
+    /// Media tooling configuration options.
+    #[config(nested)]
+    media: Media,
+    /// Authentication configuration options.
+    #[config(nested)]
+    auth: Auth,
+    /// Listing endpoint configuration options.
+    #[config(nested)]
+    listing: Listing,
     /// Enables swagger ui.
     #[config(default = true)]
     swagger_ui: bool,
@@ -81,6 +95,93 @@ pub struct Middlewares {
     request_timeout: u64,
 }
 
+#[derive(Debug, Config, Deserialize)]
+pub struct Library {
+    /// The directories containing the video files. Every directory with videos in it becomes a catalog.
+    /// The catalogs and the videos created through the API must be inside them too.
+    #[config(default = [])]
+    roots: Vec<String>,
+    /// Scans the library when the server starts.
+    #[config(default = true)]
+    scan_on_startup: bool,
+    /// The number of seconds between scheduled library scans. Zero disables scheduled scanning.
+    #[config(default = 0)]
+    scan_interval: u64,
+    /// Live file system watching configuration options.
+    #[config(nested)]
+    watcher: Watcher,
+}
+
+#[derive(Debug, Config, Deserialize)]
+pub struct Watcher {
+    /// The library roots to watch for changes, which are then applied without a full scan. Each must also be listed in the library roots.
+    #[config(default = [])]
+    roots: Vec<String>,
+    /// The number of seconds a changed path has to stay untouched before it is processed, so files still being copied are skipped.
+    #[config(default = 10)]
+    debounce: u64,
+}
+
+#[derive(Debug, Config, Deserialize)]
+pub struct Media {
+    /// The path of the ffprobe executable, used when a container cannot be parsed natively.
+    #[config(default = "ffprobe")]
+    ffprobe: String,
+    /// The path of the ffmpeg executable, used for transcoding.
+    #[config(default = "ffmpeg")]
+    ffmpeg: String,
+    /// The directory of the transcoded files.
+    #[config(default = "cache")]
+    cache_dir: String,
+    /// The maximum number of ffmpeg processes producing streaming segments on demand at the same time.
+    #[config(default = 2)]
+    live_transcodes: usize,
+    /// The number of transcoding jobs processed at the same time.
+    #[config(default = 1)]
+    job_workers: usize,
+    /// The number of seconds between checks for queued transcoding jobs, besides the ones queued through the API.
+    #[config(default = 30)]
+    job_poll_interval: u64,
+    /// The position of the frame used as the thumbnail of a video, as a percentage of its duration.
+    #[config(default = 10)]
+    thumbnail_position: u8,
+    /// The position after which a video counts as watched, as a percentage of its duration.
+    #[config(default = 90)]
+    watched_threshold: u8,
+    /// The number of seconds between two frames of the scrubbing previews.
+    #[config(default = 10)]
+    preview_interval: u32,
+    /// The directory of the files uploaded through the API, like catalog posters.
+    #[config(default = "data")]
+    data_dir: String,
+    /// The maximum size of an uploaded poster in bytes.
+    #[config(default = 5242880)]
+    max_poster_size: u64,
+}
+
+#[derive(Debug, Config, Deserialize)]
+pub struct Auth {
+    /// The name of the admin user created on the first run, when there are no users yet.
+    #[config(default = "admin")]
+    admin_username: String,
+    /// The password of the admin user created on the first run. No user is created without it.
+    #[config(env = "ADMIN_PASSWORD")]
+    admin_password: Option<Secret<String>>,
+    /// The number of seconds a login session is valid for.
+    #[config(default = 2592000)]
+    session_ttl: u64,
+    /// Only sends the session cookie over https, enable it when the server is behind a TLS terminating proxy.
+    #[config(default = false)]
+    secure_cookie: bool,
+}
+
+#[derive(Debug, Config, Deserialize)]
+pub struct Listing {
+    /// The maximum number of items on a page, larger pages are rejected.
+    #[config(default = 100)]
+    max_page_size: u64,
+}
+
 impl Configuration {
     #[instrument]
     pub fn load() -> anyhow::Result<Self> {
@@ -128,6 +229,22 @@ impl Configuration {
         &self.middlewares
     }
 
+    pub fn library(&self) -> &Library {
+        &self.library
+    }
+
+    pub fn media(&self) -> &Media {
+        &self.media
+    }
+
+    pub fn auth(&self) -> &Auth {
+        &self.auth
+    }
+
+    pub fn listing(&self) -> &Listing {
+        &self.listing
+    }
+
     pub fn swagger_ui(&self) -> bool {
         self.swagger_ui
     }
@@ -188,6 +305,104 @@ impl Middlewares {
     }
 }
 
+impl Library {
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(PathBuf::from).collect()
+    }
+
+    pub fn scan_on_startup(&self) -> bool {
+        self.scan_on_startup
+    }
+
+    pub fn scan_interval(&self) -> Option<Duration> {
+        (self.scan_interval > 0).then(|| Duration::from_secs(self.scan_interval))
+    }
+
+    pub fn watcher(&self) -> &Watcher {
+        &self.watcher
+    }
+}
+
+impl Watcher {
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(PathBuf::from).collect()
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce)
+    }
+}
+
+impl Media {
+    pub fn ffprobe(&self) -> PathBuf {
+        PathBuf::from(&self.ffprobe)
+    }
+
+    pub fn ffmpeg(&self) -> PathBuf {
+        PathBuf::from(&self.ffmpeg)
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        PathBuf::from(&self.cache_dir)
+    }
+
+    pub fn live_transcodes(&self) -> usize {
+        self.live_transcodes
+    }
+
+    pub fn job_workers(&self) -> usize {
+        self.job_workers
+    }
+
+    pub fn job_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.job_poll_interval)
+    }
+
+    pub fn thumbnail_position(&self) -> u8 {
+        self.thumbnail_position.min(100)
+    }
+
+    pub fn watched_threshold(&self) -> u8 {
+        self.watched_threshold.min(100)
+    }
+
+    pub fn preview_interval(&self) -> Seconds {
+        Seconds::from(self.preview_interval.max(1))
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        PathBuf::from(&self.data_dir)
+    }
+
+    pub fn max_poster_size(&self) -> u64 {
+        self.max_poster_size
+    }
+}
+
+impl Auth {
+    pub fn admin_username(&self) -> &str {
+        self.admin_username.as_ref()
+    }
+
+    pub fn admin_password(&self) -> Option<&Secret<String>> {
+        self.admin_password.as_ref()
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        Duration::from_secs(self.session_ttl)
+    }
+
+    pub fn secure_cookie(&self) -> bool {
+        self.secure_cookie
+    }
+}
+
+impl Listing {
+    pub fn max_page_size(&self) -> u64 {
+        self.max_page_size
+    }
+}
+
 fn create_config_template(config_path: &PathBuf) -> Result<(), anyhow::Error> {
     let config_template = yaml::template::<Configuration>(FormatOptions::default());
     let parent_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
@@ -196,6 +411,7 @@ fn create_config_template(config_path: &PathBuf) -> Result<(), anyhow::Error> {
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .open(config_path)
         .with_context(|| format!("failed to open or create file: '{}'", config_path.display()))?;
     file.write_all(config_template.as_bytes())
@@ -2,52 +2,221 @@ use std::fmt::{self, Debug};
 
 use async_trait::async_trait;
 use convert_case::{Case, Casing};
-use sqlx::PgPool;
+use sqlx::{
+    postgres::{PgArguments, PgRow},
+    query::QueryAs,
+    FromRow, PgPool, Postgres,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 use crate::model::EntityId;
 
+pub mod api_token;
+pub mod audio_track;
 pub mod catalog;
+pub mod catalog_grant;
+pub mod search;
+pub mod session;
+pub mod subtitle;
+pub mod transcode_job;
+pub mod user;
 pub mod video;
+pub mod watch_progress;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub struct Pagination {
     limit: u64,
     offset: u64,
+    keyset: Option<Keyset>,
+}
+
+/// The reasons a page cannot be fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaginationError {
+    ZeroPage,
+    ZeroSize,
+    SizeAboveMax(u64),
+    /// The rows to skip are more than a query can skip.
+    PageTooFar,
+}
+
+impl fmt::Display for PaginationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ZeroPage => write!(f, "page must be greater than zero"),
+            Self::ZeroSize => write!(f, "size must be greater than zero"),
+            Self::SizeAboveMax(max_size) => write!(f, "size must be at most {max_size}"),
+            Self::PageTooFar => write!(f, "page is too far"),
+        }
+    }
 }
 
 impl Pagination {
-    pub fn new(size: u64, page: u64) -> Self {
-        Self {
-            limit: size,
-            offset: size * (page - 1),
+    /// The rows of a page, starting from 1, with at most `max_size` rows on a page.
+    pub fn new(size: u64, page: u64, max_size: u64) -> Result<Self, PaginationError> {
+        check_size(size, max_size)?;
+        if page == 0 {
+            return Err(PaginationError::ZeroPage);
         }
+        let offset = size
+            .checked_mul(page - 1)
+            .filter(|&offset| offset <= MAX_ROWS)
+            .ok_or(PaginationError::PageTooFar)?;
+        Ok(Self {
+            limit: size,
+            offset,
+            keyset: None,
+        })
+    }
+
+    /// Continues from a row instead of skipping rows, so rows inserted meanwhile do not shift the pages.
+    /// Fetches one more row than the size, showing whether there are more rows.
+    pub fn keyset(size: u64, keyset: Keyset, max_size: u64) -> Result<Self, PaginationError> {
+        check_size(size, max_size)?;
+        Ok(Self {
+            limit: size + 1,
+            offset: 0,
+            keyset: Some(keyset),
+        })
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
     }
 }
 
+/// Postgres takes the limit and the offset as a bigint.
+const MAX_ROWS: u64 = i64::MAX as u64;
+
+fn check_size(size: u64, max_size: u64) -> Result<(), PaginationError> {
+    if size == 0 {
+        return Err(PaginationError::ZeroSize);
+    }
+    // leaves room for the extra row of keyset pagination
+    let max_size = max_size.min(MAX_ROWS - 1);
+    if size > max_size {
+        return Err(PaginationError::SizeAboveMax(max_size));
+    }
+    Ok(())
+}
+
 impl fmt::Display for Pagination {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "LIMIT {} OFFSET {}", self.limit, self.offset)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct OrderBy<T: fmt::Debug>(T, Direction);
+/// Where keyset pagination continues from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyset {
+    /// The values of the ordering fields and the id of the row to continue after, the first page without it.
+    pub after: Option<(Vec<Option<FilterValue>>, EntityId)>,
+    /// Goes towards the start of the ordering, the rows are still returned in order.
+    pub backward: bool,
+}
+
+impl Keyset {
+    /// The condition of the rows after the keyset by the ordering, `None` on the first page.
+    /// Nulls are placed like Postgres does by default, last in ascending and first in descending order.
+    fn compile<T: Column>(
+        &self,
+        ordering: &[OrderBy<T>],
+        values: &mut Vec<FilterValue>,
+    ) -> Option<String> {
+        let (keys, id) = self.after.as_ref()?;
+        let mut bind = |value: &FilterValue| {
+            values.push(value.clone());
+            match value {
+                FilterValue::Enum(type_name, _) => {
+                    format!("CAST(${} AS {type_name})", values.len())
+                }
+                _ => format!("${}", values.len()),
+            }
+        };
+        let mut equal = vec![];
+        let mut alternatives = vec![];
+        for (OrderBy(field, direction), key) in ordering.iter().zip(keys) {
+            let column = field.column();
+            let ascending = (*direction == Direction::Asc) != self.backward;
+            let (after, equals) = match key {
+                Some(key) => {
+                    let placeholder = bind(key);
+                    let after = if ascending {
+                        format!("({column} > {placeholder} OR {column} IS NULL)")
+                    } else {
+                        format!("{column} < {placeholder}")
+                    };
+                    (Some(after), format!("{column} = {placeholder}"))
+                }
+                None => (
+                    (!ascending).then(|| format!("{column} IS NOT NULL")),
+                    format!("{column} IS NULL"),
+                ),
+            };
+            if let Some(after) = after {
+                alternatives.push(
+                    equal
+                        .iter()
+                        .cloned()
+                        .chain([after])
+                        .collect::<Vec<_>>()
+                        .join(" AND "),
+                );
+            }
+            equal.push(equals);
+        }
+        let placeholder = bind(&FilterValue::Integer(*id));
+        let operator = if self.backward { "<" } else { ">" };
+        alternatives.push(
+            equal
+                .into_iter()
+                .chain([format!("id {operator} {placeholder}")])
+                .collect::<Vec<_>>()
+                .join(" AND "),
+        );
+        Some(format!("({})", alternatives.join(" OR ")))
+    }
+}
+
+/// A field of an entity stored in a column.
+/// Only the identifiers of the columns are written into the queries, every value is bound as a parameter.
+pub trait Column {
+    /// The identifier of the column, a snake case literal never derived from the input.
+    fn column(&self) -> &'static str;
+}
 
-impl<T: fmt::Debug> OrderBy<T> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderBy<T: Column>(T, Direction);
+
+impl<T: Column> OrderBy<T> {
     pub fn new(field: T, direction: Direction) -> Self {
         Self(field, direction)
     }
+
+    pub fn field(&self) -> &T {
+        &self.0
+    }
+
+    fn reversed(self) -> Self {
+        let direction = match self.1 {
+            Direction::Asc => Direction::Desc,
+            Direction::Desc => Direction::Asc,
+        };
+        Self(self.0, direction)
+    }
 }
 
-impl<T: fmt::Debug> fmt::Display for OrderBy<T> {
+impl<T: Column> fmt::Display for OrderBy<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let ordering = format!("{:?}", self.0).to_case(Case::Snake);
-        let direction = &self.1;
-        write!(f, "{ordering} {direction}")
+        write!(f, "{} {}", self.0.column(), self.1)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Asc,
     Desc,
@@ -55,16 +224,208 @@ pub enum Direction {
 
 impl fmt::Display for Direction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let formatted = format!("{self:?}").to_uppercase();
-        write!(f, "{formatted}")
+        match self {
+            Self::Asc => write!(f, "ASC"),
+            Self::Desc => write!(f, "DESC"),
+        }
     }
 }
 
+/// The type of the values a field can be filtered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Integer,
+    Float,
+    Text,
+    Boolean,
+    Timestamp,
+    /// A database enum with the name of its type, filtered by its text representation.
+    Enum(&'static str),
+}
+
+impl ValueKind {
+    pub fn parse(self, value: &str) -> Option<FilterValue> {
+        match self {
+            Self::Integer => value.parse().ok().map(FilterValue::Integer),
+            Self::Float => value.parse().ok().map(FilterValue::Float),
+            Self::Text => Some(FilterValue::Text(value.to_owned())),
+            Self::Enum(type_name) => Some(FilterValue::Enum(type_name, value.to_owned())),
+            Self::Boolean => value.parse().ok().map(FilterValue::Boolean),
+            Self::Timestamp => OffsetDateTime::parse(value, &Rfc3339)
+                .ok()
+                .map(FilterValue::Timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Boolean(bool),
+    Timestamp(OffsetDateTime),
+    Enum(&'static str, String),
+}
+
+impl FilterValue {
+    /// The value of a database enum, named like its variant in snake case.
+    pub fn from_enum(type_name: &'static str, value: impl fmt::Debug) -> Self {
+        Self::Enum(type_name, format!("{value:?}").to_case(Case::Snake))
+    }
+}
+
+/// Formats the value the way [`ValueKind::parse`] parses it.
+impl fmt::Display for FilterValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value}"),
+            Self::Text(value) | Self::Enum(_, value) => write!(f, "{value}"),
+            Self::Boolean(value) => write!(f, "{value}"),
+            Self::Timestamp(value) => {
+                write!(f, "{}", value.format(&Rfc3339).map_err(|_| fmt::Error)?)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Eq(FilterValue),
+    Ne(FilterValue),
+    Gt(FilterValue),
+    Gte(FilterValue),
+    Lt(FilterValue),
+    Lte(FilterValue),
+    In(Vec<FilterValue>),
+    /// A pattern where `%` matches any text and `_` matches any character.
+    Like(String),
+    IsNull,
+    IsNotNull,
+}
+
+/// A field of an entity it can be filtered by.
+pub trait FilterField: Column + fmt::Debug {
+    fn kind(&self) -> ValueKind;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Filter<T: FilterField>(T, Condition);
+
+impl<T: FilterField> Filter<T> {
+    pub fn new(field: T, condition: Condition) -> Self {
+        Self(field, condition)
+    }
+}
+
+impl<T: FilterField> Filter<T> {
+    /// Compiles the filter to a condition, its values are bound as the next parameters.
+    fn compile(self, values: &mut Vec<FilterValue>) -> String {
+        let column = match self.0.kind() {
+            ValueKind::Enum(_) => format!("{}::TEXT", self.0.column()),
+            _ => self.0.column().to_owned(),
+        };
+        let mut bind = |value| {
+            values.push(value);
+            format!("${}", values.len())
+        };
+        match self.1 {
+            Condition::Eq(value) => format!("{column} = {}", bind(value)),
+            Condition::Ne(value) => format!("{column} <> {}", bind(value)),
+            Condition::Gt(value) => format!("{column} > {}", bind(value)),
+            Condition::Gte(value) => format!("{column} >= {}", bind(value)),
+            Condition::Lt(value) => format!("{column} < {}", bind(value)),
+            Condition::Lte(value) => format!("{column} <= {}", bind(value)),
+            Condition::In(values) if values.is_empty() => "FALSE".to_owned(),
+            Condition::In(values) => format!(
+                "{column} IN ({})",
+                values.into_iter().map(bind).collect::<Vec<_>>().join(", ")
+            ),
+            Condition::Like(pattern) => {
+                format!("{column} LIKE {}", bind(FilterValue::Text(pattern)))
+            }
+            Condition::IsNull => format!("{column} IS NULL"),
+            Condition::IsNotNull => format!("{column} IS NOT NULL"),
+        }
+    }
+}
+
+/// A find all query with the values of its parameters, in order.
+#[derive(Debug)]
+pub struct FindAllQuery {
+    sql: String,
+    values: Vec<FilterValue>,
+    /// The rows are fetched in reverse order when paging backward.
+    reversed: bool,
+    /// Counts the rows matching the query on every page, with the first `count_values` values.
+    count_sql: String,
+    count_values: usize,
+}
+
+/// Rows with the number of the rows matching the query on every page.
+#[derive(Debug)]
+pub struct Counted<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+}
+
+impl FindAllQuery {
+    pub async fn fetch_all<T>(self, pool: &PgPool) -> Result<Vec<T>, sqlx::Error>
+    where
+        T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+    {
+        let query = bind_values(sqlx::query_as(&self.sql), &self.values);
+        let mut rows = query.fetch_all(pool).await?;
+        if self.reversed {
+            rows.reverse();
+        }
+        Ok(rows)
+    }
+
+    /// Fetches the rows and counts the matching rows at the same time.
+    pub async fn fetch_counted<T>(self, pool: &PgPool) -> Result<Counted<T>, sqlx::Error>
+    where
+        T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+    {
+        let query = bind_values(sqlx::query_as(&self.sql), &self.values);
+        let count = bind_values(
+            sqlx::query_as(&self.count_sql),
+            &self.values[..self.count_values],
+        );
+        let (mut items, (total,)): (Vec<T>, (i64,)) =
+            tokio::try_join!(query.fetch_all(pool), count.fetch_one(pool))?;
+        if self.reversed {
+            items.reverse();
+        }
+        Ok(Counted { items, total })
+    }
+}
+
+fn bind_values<'q, T>(
+    mut query: QueryAs<'q, Postgres, T, PgArguments>,
+    values: &'q [FilterValue],
+) -> QueryAs<'q, Postgres, T, PgArguments> {
+    for value in values {
+        query = match value {
+            FilterValue::Integer(value) => query.bind(value),
+            FilterValue::Float(value) => query.bind(value),
+            FilterValue::Text(value) | FilterValue::Enum(_, value) => query.bind(value),
+            FilterValue::Boolean(value) => query.bind(value),
+            FilterValue::Timestamp(value) => query.bind(value),
+        };
+    }
+    query
+}
+
 #[async_trait]
 pub trait Entity<T> {
     type CreateRequest;
-    type Ordering: Debug;
+    type Filtering: FilterField;
+    type Ordering: Column + Debug;
     type UpdateRequest;
+    /// The error of [`Entity::update`], entities checking rules the schema cannot express report them in it.
+    type UpdateError: From<sqlx::Error>;
 
     async fn create(pool: &PgPool, request: Self::CreateRequest) -> Result<T, sqlx::Error>;
     async fn create_many(
@@ -74,93 +435,236 @@ pub trait Entity<T> {
     async fn find(pool: &PgPool, id: EntityId) -> Result<Option<T>, sqlx::Error>;
     async fn find_all(
         pool: &PgPool,
+        filters: Vec<Filter<Self::Filtering>>,
         ordering: Vec<OrderBy<Self::Ordering>>,
         pagination: Option<Pagination>,
     ) -> Result<Vec<T>, sqlx::Error>;
-    async fn update(pool: &PgPool, request: Self::UpdateRequest) -> Result<Option<T>, sqlx::Error>;
+    /// Like [`Entity::find_all`], also counting the matching rows on every page.
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<Self::Filtering>>,
+        ordering: Vec<OrderBy<Self::Ordering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<T>, sqlx::Error>;
+    async fn update(
+        pool: &PgPool,
+        request: Self::UpdateRequest,
+    ) -> Result<Option<T>, Self::UpdateError>;
     async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error>;
     async fn delete_many(pool: &PgPool, ids: Vec<EntityId>) -> Result<u64, sqlx::Error>;
     async fn count_all(pool: &PgPool) -> Result<i64, sqlx::Error>;
 }
 
-fn build_find_all_query<T: fmt::Debug>(
+/// An entity listed with keyset pagination, the cursors keep the values of its ordering fields.
+pub trait Keyed: Entity<Self> + Sized {
+    fn id(&self) -> EntityId;
+    fn key_kind(field: &Self::Ordering) -> ValueKind;
+    fn key(&self, field: &Self::Ordering) -> Option<FilterValue>;
+}
+
+fn build_find_all_query<F: FilterField, T: Column>(
+    table_name: &'static str,
+    filters: Vec<Filter<F>>,
+    ordering: Vec<OrderBy<T>>,
+    pagination: Option<Pagination>,
+) -> FindAllQuery {
+    build_find_all_query_where(table_name, None, filters, ordering, pagination)
+}
+
+/// Builds a find all query of the rows matching a condition and the filters.
+/// The condition refers to its id as `$1`.
+fn build_find_all_query_where<F: FilterField, T: Column>(
     table_name: &'static str,
+    condition: Option<(&'static str, EntityId)>,
+    filters: Vec<Filter<F>>,
     ordering: Vec<OrderBy<T>>,
     pagination: Option<Pagination>,
-) -> String {
-    let ordering_part = ordering
-        .iter()
-        .map(ToString::to_string)
-        .collect::<Vec<_>>()
-        .join(", ");
-    let pagination_part = pagination.map_or_else(String::new, |p| p.to_string());
-    format!(
-        "SELECT * FROM {}{}{}{}{}",
-        table_name,
-        if ordering_part.is_empty() {
-            ""
-        } else {
-            " ORDER BY "
-        },
-        ordering_part,
-        if pagination_part.is_empty() { "" } else { " " },
-        pagination_part
-    )
+) -> FindAllQuery {
+    let mut values = vec![];
+    let mut conditions = vec![];
+    if let Some((condition, id)) = condition {
+        conditions.push(condition.to_owned());
+        values.push(FilterValue::Integer(id));
+    }
+    for filter in filters {
+        conditions.push(filter.compile(&mut values));
+    }
+    let mut count_sql = format!("SELECT COUNT(*) FROM {table_name}");
+    if !conditions.is_empty() {
+        count_sql.push_str(" WHERE ");
+        count_sql.push_str(&conditions.join(" AND "));
+    }
+    let count_values = values.len();
+    let keyset = pagination.as_ref().and_then(|p| p.keyset.as_ref());
+    if let Some(condition) = keyset.and_then(|keyset| keyset.compile(&ordering, &mut values)) {
+        conditions.push(condition);
+    }
+    let reversed = keyset.is_some_and(|keyset| keyset.backward);
+    let mut sql = format!("SELECT * FROM {table_name}");
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+    let mut ordering_part = ordering
+        .into_iter()
+        .map(|order_by| {
+            if reversed {
+                order_by.reversed()
+            } else {
+                order_by
+            }
+            .to_string()
+        })
+        .collect::<Vec<_>>();
+    // the id breaks the ties, so every row has a distinct position to continue from
+    if keyset.is_some() {
+        ordering_part.push(format!("id {}", if reversed { "DESC" } else { "ASC" }));
+    }
+    if !ordering_part.is_empty() {
+        sql.push_str(" ORDER BY ");
+        sql.push_str(&ordering_part.join(", "));
+    }
+    if let Some(pagination) = pagination {
+        sql.push(' ');
+        sql.push_str(&pagination.to_string());
+    }
+    FindAllQuery {
+        sql,
+        values,
+        reversed,
+        count_sql,
+        count_values,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use fake::{Fake, Faker};
+    use fake::{Dummy, Fake, Faker};
     use pretty_assertions::assert_eq;
+    use proptest::{collection::vec, option, prelude::*};
+    use serde::{
+        de::{value::StrDeserializer, IntoDeserializer},
+        Deserialize,
+    };
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum TestFiltering {
+        Id,
+        DisplayName,
+        Status,
+    }
+
+    impl Column for TestFiltering {
+        fn column(&self) -> &'static str {
+            match self {
+                Self::Id => "id",
+                Self::DisplayName => "display_name",
+                Self::Status => "status",
+            }
+        }
+    }
+
+    impl FilterField for TestFiltering {
+        fn kind(&self) -> ValueKind {
+            match self {
+                Self::Id => ValueKind::Integer,
+                Self::DisplayName => ValueKind::Text,
+                Self::Status => ValueKind::Enum("test_status"),
+            }
+        }
+    }
+
+    fn no_filters() -> Vec<Filter<TestFiltering>> {
+        vec![]
+    }
+
+    fn no_ordering() -> Vec<OrderBy<TestFiltering>> {
+        vec![]
+    }
+
+    fn fake_page() -> (u64, u64) {
+        ((1..=255).fake(), (1..=255).fake())
+    }
 
     #[test]
     fn new_pagination() {
-        let size: u64 = Faker.fake::<u8>().into();
-        let page: u64 = Faker.fake::<u8>().into();
+        let (size, page) = fake_page();
         assert_eq!(
-            Pagination::new(size, page),
-            Pagination {
+            Pagination::new(size, page, size),
+            Ok(Pagination {
                 limit: size,
-                offset: size * (page - 1)
-            }
+                offset: size * (page - 1),
+                keyset: None,
+            })
+        );
+    }
+
+    #[test]
+    fn new_pagination_rejects_zero_page_and_size() {
+        assert_eq!(Pagination::new(10, 0, 100), Err(PaginationError::ZeroPage));
+        assert_eq!(Pagination::new(0, 1, 100), Err(PaginationError::ZeroSize));
+    }
+
+    #[test]
+    fn new_pagination_rejects_size_above_max() {
+        assert_eq!(
+            Pagination::new(101, 1, 100),
+            Err(PaginationError::SizeAboveMax(100))
+        );
+        let keyset = Keyset {
+            after: None,
+            backward: false,
+        };
+        assert_eq!(
+            Pagination::keyset(101, keyset, 100),
+            Err(PaginationError::SizeAboveMax(100))
+        );
+    }
+
+    #[test]
+    fn new_pagination_rejects_offset_out_of_range() {
+        assert_eq!(
+            Pagination::new(100, u64::MAX, 100),
+            Err(PaginationError::PageTooFar)
+        );
+        assert_eq!(
+            Pagination::new(2, MAX_ROWS / 2 + 2, 100),
+            Err(PaginationError::PageTooFar)
         );
+        assert!(Pagination::new(1, MAX_ROWS + 1, 100).is_ok());
     }
 
     #[test]
     fn display_pagination() {
-        let size: u64 = Faker.fake::<u8>().into();
-        let page: u64 = Faker.fake::<u8>().into();
+        let (size, page) = fake_page();
         assert_eq!(
-            Pagination::new(size, page).to_string(),
+            Pagination::new(size, page, size).unwrap().to_string(),
             format!("LIMIT {} OFFSET {}", size, size * (page - 1))
         );
     }
 
     #[test]
     fn new_order_by() {
-        let field: String = Faker.fake();
         assert_eq!(
-            OrderBy::new(&field, Direction::Asc),
-            OrderBy(&field, Direction::Asc)
+            OrderBy::new(TestFiltering::DisplayName, Direction::Asc),
+            OrderBy(TestFiltering::DisplayName, Direction::Asc)
         );
         assert_eq!(
-            OrderBy::new(&field, Direction::Desc),
-            OrderBy(&field, Direction::Desc)
+            OrderBy::new(TestFiltering::DisplayName, Direction::Desc),
+            OrderBy(TestFiltering::DisplayName, Direction::Desc)
         );
     }
 
     #[test]
     fn display_order_by() {
-        let field: String = Faker.fake();
-        let snake_field = field.to_case(Case::Snake);
         assert_eq!(
-            OrderBy::new(&field, Direction::Asc).to_string(),
-            format!("{snake_field:?} {}", Direction::Asc)
+            OrderBy::new(TestFiltering::DisplayName, Direction::Asc).to_string(),
+            "display_name ASC"
         );
         assert_eq!(
-            OrderBy::new(&field, Direction::Desc).to_string(),
-            format!("{snake_field:?} {}", Direction::Desc)
+            OrderBy::new(TestFiltering::Status, Direction::Desc).to_string(),
+            "status DESC"
         );
     }
 
@@ -176,36 +680,26 @@ mod tests {
 
     #[test]
     fn build_find_all_query_empty_params() {
-        let ordering: Vec<OrderBy<&str>> = vec![];
         let pagination: Option<Pagination> = None;
-        let query = build_find_all_query("table", ordering, pagination);
-        assert_eq!(query, "SELECT * FROM table");
+        let query = build_find_all_query("table", no_filters(), no_ordering(), pagination);
+        assert_eq!(query.sql, "SELECT * FROM table");
     }
 
     #[test]
     fn build_find_all_query_only_ordering() {
-        let field: String = Faker.fake();
-        let ordering = vec![OrderBy(field.clone(), Direction::Asc)];
+        let ordering = vec![OrderBy(TestFiltering::DisplayName, Direction::Asc)];
         let pagination: Option<Pagination> = None;
-        let query = build_find_all_query("table", ordering, pagination);
-        assert_eq!(
-            query,
-            format!(
-                "SELECT * FROM table ORDER BY {:?} ASC",
-                field.to_case(Case::Snake)
-            )
-        );
+        let query = build_find_all_query("table", no_filters(), ordering, pagination);
+        assert_eq!(query.sql, "SELECT * FROM table ORDER BY display_name ASC");
     }
 
     #[test]
     fn build_find_all_query_only_pagination() {
-        let size: u64 = Faker.fake::<u8>().into();
-        let page: u64 = Faker.fake::<u8>().into();
-        let ordering: Vec<OrderBy<&str>> = vec![];
-        let pagination = Some(Pagination::new(size, page));
-        let query = build_find_all_query("table", ordering, pagination);
+        let (size, page) = fake_page();
+        let pagination = Some(Pagination::new(size, page, size).unwrap());
+        let query = build_find_all_query("table", no_filters(), no_ordering(), pagination);
         assert_eq!(
-            query,
+            query.sql,
             format!(
                 "SELECT * FROM table LIMIT {size:?} OFFSET {}",
                 size * (page - 1)
@@ -215,17 +709,14 @@ mod tests {
 
     #[test]
     fn build_find_all_query_both_params() {
-        let field: String = Faker.fake();
-        let size: u64 = Faker.fake::<u8>().into();
-        let page: u64 = Faker.fake::<u8>().into();
-        let ordering = vec![OrderBy(field.clone(), Direction::Asc)];
-        let pagination = Some(Pagination::new(size, page));
-        let query = build_find_all_query("table", ordering, pagination);
+        let (size, page) = fake_page();
+        let ordering = vec![OrderBy(TestFiltering::DisplayName, Direction::Asc)];
+        let pagination = Some(Pagination::new(size, page, size).unwrap());
+        let query = build_find_all_query("table", no_filters(), ordering, pagination);
         assert_eq!(
-            query,
+            query.sql,
             format!(
-                "SELECT * FROM table ORDER BY {:?} ASC LIMIT {size} OFFSET {}",
-                field.to_case(Case::Snake),
+                "SELECT * FROM table ORDER BY display_name ASC LIMIT {size} OFFSET {}",
                 size * (page - 1)
             )
         );
@@ -233,27 +724,431 @@ mod tests {
 
     #[test]
     fn build_find_all_query_multiple_ordering_params_with_pagination() {
-        let field1: String = Faker.fake();
-        let field2: String = Faker.fake();
-        let field3: String = Faker.fake();
-        let size: u64 = Faker.fake::<u8>().into();
-        let page: u64 = Faker.fake::<u8>().into();
+        let (size, page) = fake_page();
         let ordering = vec![
-            OrderBy(field1.clone(), Direction::Asc),
-            OrderBy(field2.clone(), Direction::Desc),
-            OrderBy(field3.clone(), Direction::Asc),
+            OrderBy(TestFiltering::DisplayName, Direction::Asc),
+            OrderBy(TestFiltering::Status, Direction::Desc),
+            OrderBy(TestFiltering::Id, Direction::Asc),
         ];
-        let pagination = Some(Pagination::new(size, page));
-        let query = build_find_all_query("table", ordering, pagination);
+        let pagination = Some(Pagination::new(size, page, size).unwrap());
+        let query = build_find_all_query("table", no_filters(), ordering, pagination);
         assert_eq!(
-            query,
+            query.sql,
             format!(
-                "SELECT * FROM table ORDER BY {:?} ASC, {:?} DESC, {:?} ASC LIMIT {size} OFFSET {}",
-                field1.to_case(Case::Snake),
-                field2.to_case(Case::Snake),
-                field3.to_case(Case::Snake),
+                "SELECT * FROM table ORDER BY display_name ASC, status DESC, id ASC LIMIT {size} OFFSET {}",
                 size * (page - 1)
             )
         );
     }
+
+    #[test]
+    fn build_find_all_query_where_condition_before_ordering_and_pagination() {
+        let (size, _) = fake_page();
+        let ordering = vec![OrderBy(TestFiltering::DisplayName, Direction::Desc)];
+        let pagination = Some(Pagination::new(size, 1, size).unwrap());
+        let query = build_find_all_query_where(
+            "table",
+            Some(("parent_id = $1", 1)),
+            no_filters(),
+            ordering,
+            pagination,
+        );
+        assert_eq!(
+            query.sql,
+            format!(
+                "SELECT * FROM table WHERE parent_id = $1 ORDER BY display_name DESC LIMIT {size} OFFSET 0"
+            )
+        );
+    }
+
+    #[test]
+    fn build_find_all_query_filters_bound_in_order() {
+        let id: i64 = Faker.fake();
+        let name: String = Faker.fake();
+        let filters = vec![
+            Filter::new(TestFiltering::Id, Condition::Gte(FilterValue::Integer(id))),
+            Filter::new(TestFiltering::DisplayName, Condition::IsNull),
+            Filter::new(
+                TestFiltering::DisplayName,
+                Condition::In(vec![
+                    FilterValue::Text(name.clone()),
+                    FilterValue::Text(name.clone()),
+                ]),
+            ),
+        ];
+        let query = build_find_all_query("table", filters, no_ordering(), None);
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM table WHERE id >= $1 AND display_name IS NULL AND display_name IN ($2, $3)"
+        );
+        assert_eq!(
+            query.values,
+            vec![
+                FilterValue::Integer(id),
+                FilterValue::Text(name.clone()),
+                FilterValue::Text(name)
+            ]
+        );
+    }
+
+    #[test]
+    fn build_find_all_query_where_filters_after_condition() {
+        let pattern: String = Faker.fake();
+        let filters = vec![
+            Filter::new(TestFiltering::DisplayName, Condition::Like(pattern.clone())),
+            Filter::new(
+                TestFiltering::Status,
+                Condition::Ne(FilterValue::Text("failed".to_owned())),
+            ),
+        ];
+        let ordering = vec![OrderBy(TestFiltering::Id, Direction::Asc)];
+        let query = build_find_all_query_where(
+            "table",
+            Some(("parent_id = $1", 7)),
+            filters,
+            ordering,
+            None,
+        );
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM table WHERE parent_id = $1 AND display_name LIKE $2 AND status::TEXT <> $3 ORDER BY id ASC"
+        );
+        assert_eq!(
+            query.values,
+            vec![
+                FilterValue::Integer(7),
+                FilterValue::Text(pattern),
+                FilterValue::Text("failed".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn build_find_all_query_empty_in_matches_nothing() {
+        let filters = vec![Filter::new(TestFiltering::Id, Condition::In(vec![]))];
+        let query = build_find_all_query("table", filters, no_ordering(), None);
+        assert_eq!(query.sql, "SELECT * FROM table WHERE FALSE");
+        assert_eq!(query.values, vec![]);
+    }
+
+    #[test]
+    fn build_find_all_query_counts_without_ordering_pagination_and_keyset() {
+        let user_id: i64 = Faker.fake();
+        let id: i64 = Faker.fake();
+        let filters = vec![Filter::new(
+            TestFiltering::Id,
+            Condition::Gte(FilterValue::Integer(id)),
+        )];
+        let ordering = vec![OrderBy(TestFiltering::Id, Direction::Asc)];
+        let keyset = Keyset {
+            after: Some((vec![Some(FilterValue::Integer(id))], id)),
+            backward: false,
+        };
+        let pagination = Some(Pagination::keyset(10, keyset, 10).unwrap());
+        let query = build_find_all_query_where(
+            "table",
+            Some(("user_id = $1", user_id)),
+            filters,
+            ordering,
+            pagination,
+        );
+        assert_eq!(
+            query.count_sql,
+            "SELECT COUNT(*) FROM table WHERE user_id = $1 AND id >= $2"
+        );
+        assert_eq!(query.count_values, 2);
+        assert_eq!(query.values.len(), 4);
+    }
+
+    #[test]
+    fn build_find_all_query_keyset_first_page() {
+        let (size, _) = fake_page();
+        let ordering = vec![OrderBy(TestFiltering::DisplayName, Direction::Asc)];
+        let keyset = Keyset {
+            after: None,
+            backward: false,
+        };
+        let pagination = Some(Pagination::keyset(size, keyset, size).unwrap());
+        let query = build_find_all_query("table", no_filters(), ordering, pagination);
+        assert_eq!(
+            query.sql,
+            format!(
+                "SELECT * FROM table ORDER BY display_name ASC, id ASC LIMIT {} OFFSET 0",
+                size + 1
+            )
+        );
+        assert!(!query.reversed);
+    }
+
+    #[test]
+    fn build_find_all_query_keyset_after_row() {
+        let id: i64 = Faker.fake();
+        let name: String = Faker.fake();
+        let ordering = vec![
+            OrderBy(TestFiltering::DisplayName, Direction::Asc),
+            OrderBy(TestFiltering::Status, Direction::Desc),
+        ];
+        let keys = vec![
+            Some(FilterValue::Text(name.clone())),
+            Some(FilterValue::Enum("test_status", "done".to_owned())),
+        ];
+        let keyset = Keyset {
+            after: Some((keys, id)),
+            backward: false,
+        };
+        let pagination = Some(Pagination::keyset(10, keyset, 10).unwrap());
+        let query = build_find_all_query("table", no_filters(), ordering, pagination);
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM table WHERE ((display_name > $1 OR display_name IS NULL) \
+            OR display_name = $1 AND status < CAST($2 AS test_status) \
+            OR display_name = $1 AND status = CAST($2 AS test_status) AND id > $3) \
+            ORDER BY display_name ASC, status DESC, id ASC LIMIT 11 OFFSET 0"
+        );
+        assert_eq!(
+            query.values,
+            vec![
+                FilterValue::Text(name),
+                FilterValue::Enum("test_status", "done".to_owned()),
+                FilterValue::Integer(id)
+            ]
+        );
+    }
+
+    #[test]
+    fn build_find_all_query_keyset_backward_from_null() {
+        let id: i64 = Faker.fake();
+        let ordering = vec![OrderBy(TestFiltering::DisplayName, Direction::Asc)];
+        let keyset = Keyset {
+            after: Some((vec![None], id)),
+            backward: true,
+        };
+        let pagination = Some(Pagination::keyset(10, keyset, 10).unwrap());
+        let query = build_find_all_query("table", no_filters(), ordering, pagination);
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM table WHERE (display_name IS NOT NULL \
+            OR display_name IS NULL AND id < $1) \
+            ORDER BY display_name DESC, id DESC LIMIT 11 OFFSET 0"
+        );
+        assert_eq!(query.values, vec![FilterValue::Integer(id)]);
+        assert!(query.reversed);
+    }
+
+    #[test]
+    fn parse_value_kinds() {
+        assert_eq!(
+            ValueKind::Integer.parse("42"),
+            Some(FilterValue::Integer(42))
+        );
+        assert_eq!(ValueKind::Integer.parse("4.2"), None);
+        assert_eq!(
+            ValueKind::Float.parse("23.976"),
+            Some(FilterValue::Float(23.976))
+        );
+        assert_eq!(
+            ValueKind::Boolean.parse("true"),
+            Some(FilterValue::Boolean(true))
+        );
+        assert_eq!(
+            ValueKind::Timestamp.parse("2023-08-13T17:35:51Z"),
+            Some(FilterValue::Timestamp(
+                time::macros::datetime!(2023-08-13 17:35:51 UTC)
+            ))
+        );
+        assert_eq!(ValueKind::Timestamp.parse("yesterday"), None);
+    }
+
+    /// Every field is stored in the column named like the field in the API.
+    fn assert_whitelisted<T>()
+    where
+        T: Column + fmt::Debug + Dummy<Faker> + for<'de> Deserialize<'de>,
+    {
+        for _ in 0..100 {
+            let field: T = Faker.fake();
+            let column = field.column();
+            assert!(
+                column.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+                "{column}"
+            );
+            let deserializer: StrDeserializer<'_, serde::de::value::Error> =
+                column.into_deserializer();
+            let parsed = T::deserialize(deserializer).unwrap();
+            assert_eq!(format!("{parsed:?}"), format!("{field:?}"));
+        }
+    }
+
+    #[test]
+    fn columns_whitelisted() {
+        assert_whitelisted::<audio_track::AudioTrackOrdering>();
+        assert_whitelisted::<audio_track::AudioTrackFiltering>();
+        assert_whitelisted::<catalog::CatalogOrdering>();
+        assert_whitelisted::<catalog::CatalogFiltering>();
+        assert_whitelisted::<subtitle::SubtitleOrdering>();
+        assert_whitelisted::<subtitle::SubtitleFiltering>();
+        assert_whitelisted::<transcode_job::TranscodeJobOrdering>();
+        assert_whitelisted::<transcode_job::TranscodeJobFiltering>();
+        assert_whitelisted::<user::UserOrdering>();
+        assert_whitelisted::<user::UserFiltering>();
+        assert_whitelisted::<video::VideoOrdering>();
+        assert_whitelisted::<video::VideoFiltering>();
+    }
+
+    /// The words a query is made of, none of them comes from the input.
+    const TRUSTED_TOKENS: &[&str] = &[
+        "SELECT",
+        "*",
+        "FROM",
+        "table",
+        "WHERE",
+        "AND",
+        "OR",
+        "NOT",
+        "IS",
+        "NULL",
+        "IN",
+        "LIKE",
+        "FALSE",
+        "CAST",
+        "AS",
+        "ORDER",
+        "BY",
+        "ASC",
+        "DESC",
+        "LIMIT",
+        "OFFSET",
+        "=",
+        "<>",
+        ">",
+        ">=",
+        "<",
+        "<=",
+        "parent_id",
+        "id",
+        "display_name",
+        "status",
+        "status::TEXT",
+        "test_status",
+    ];
+
+    /// Fails when the query has a token besides the trusted ones, the numbers of the pagination
+    /// and the placeholders of the bound values.
+    fn assert_trusted(query: &FindAllQuery) {
+        let sql = query.sql.replace(['(', ')', ','], " ");
+        for token in sql.split_whitespace() {
+            if let Some(index) = token.strip_prefix('$') {
+                let index: usize = index.parse().unwrap();
+                assert!((1..=query.values.len()).contains(&index), "{token}");
+            } else {
+                assert!(
+                    TRUSTED_TOKENS.contains(&token) || token.parse::<u64>().is_ok(),
+                    "untrusted token {token:?} in {}",
+                    query.sql
+                );
+            }
+        }
+    }
+
+    fn field() -> impl Strategy<Value = TestFiltering> {
+        prop_oneof![
+            Just(TestFiltering::Id),
+            Just(TestFiltering::DisplayName),
+            Just(TestFiltering::Status),
+        ]
+    }
+
+    fn direction() -> impl Strategy<Value = Direction> {
+        prop_oneof![Just(Direction::Asc), Just(Direction::Desc)]
+    }
+
+    fn value() -> impl Strategy<Value = FilterValue> {
+        prop_oneof![
+            any::<i64>().prop_map(FilterValue::Integer),
+            any::<f64>().prop_map(FilterValue::Float),
+            any::<String>().prop_map(FilterValue::Text),
+            any::<bool>().prop_map(FilterValue::Boolean),
+            any::<String>().prop_map(|value| FilterValue::Enum("test_status", value)),
+        ]
+    }
+
+    fn condition() -> impl Strategy<Value = Condition> {
+        prop_oneof![
+            value().prop_map(Condition::Eq),
+            value().prop_map(Condition::Ne),
+            value().prop_map(Condition::Gt),
+            value().prop_map(Condition::Gte),
+            value().prop_map(Condition::Lt),
+            value().prop_map(Condition::Lte),
+            vec(value(), 0..4).prop_map(Condition::In),
+            any::<String>().prop_map(Condition::Like),
+            Just(Condition::IsNull),
+            Just(Condition::IsNotNull),
+        ]
+    }
+
+    fn keyset(keys: usize) -> impl Strategy<Value = Keyset> {
+        (
+            option::of((vec(option::of(value()), keys), any::<i64>())),
+            any::<bool>(),
+        )
+            .prop_map(|(after, backward)| Keyset { after, backward })
+    }
+
+    proptest! {
+        #[test]
+        fn no_input_in_sql(
+            filters in vec((field(), condition()), 0..4),
+            ordering in vec((field(), direction()), 0..4),
+            keyed in any::<bool>(),
+            (size, page, max_size) in (any::<u64>(), any::<u64>(), any::<u64>()),
+            keys in keyset(4),
+            parent_id in any::<i64>(),
+        ) {
+            let filters = filters
+                .into_iter()
+                .map(|(field, condition)| Filter::new(field, condition))
+                .collect::<Vec<_>>();
+            let ordering = ordering
+                .into_iter()
+                .map(|(field, direction)| OrderBy::new(field, direction))
+                .collect::<Vec<_>>();
+            let pagination = if keyed {
+                Pagination::keyset(size, keys, max_size).ok()
+            } else {
+                Pagination::new(size, page, max_size).ok()
+            };
+            let query = build_find_all_query_where(
+                "table",
+                Some(("parent_id = $1", parent_id)),
+                filters,
+                ordering,
+                pagination,
+            );
+            assert_trusted(&query);
+            let count_query = FindAllQuery {
+                sql: query.count_sql.replace("COUNT(*)", "*"),
+                values: query.values[..query.count_values].to_vec(),
+                ..query
+            };
+            assert_trusted(&count_query);
+        }
+
+        #[test]
+        fn pagination_in_range(size in any::<u64>(), page in any::<u64>(), max_size in any::<u64>()) {
+            match Pagination::new(size, page, max_size) {
+                Ok(pagination) => {
+                    prop_assert!(size > 0 && size <= max_size && page > 0);
+                    prop_assert!(pagination.limit() <= MAX_ROWS);
+                    prop_assert!(pagination.offset() <= MAX_ROWS);
+                    prop_assert_eq!(u128::from(pagination.offset()), u128::from(size) * u128::from(page - 1));
+                }
+                Err(_) => prop_assert!(
+                    size == 0
+                        || size > max_size.min(MAX_ROWS - 1)
+                        || page == 0
+                        || u128::from(size) * u128::from(page - 1) > u128::from(MAX_ROWS)
+                ),
+            }
+        }
+    }
 }
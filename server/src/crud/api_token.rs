@@ -0,0 +1,99 @@
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+
+use crate::model::{ApiToken, EntityId, TokenScope};
+
+#[derive(Debug)]
+pub struct CreateApiTokenRequest {
+    pub user_id: EntityId,
+    pub name: String,
+    pub token_hash: String,
+    pub scope: TokenScope,
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+impl ApiToken {
+    #[instrument(skip(pool))]
+    pub async fn create(
+        pool: &PgPool,
+        request: CreateApiTokenRequest,
+    ) -> Result<Self, sqlx::Error> {
+        let token = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO api_token ( user_id, name, token_hash, scope, expires_at )
+                VALUES ( $1, $2, $3, $4, $5 )
+                RETURNING
+                    id, user_id, name, token_hash, scope AS "scope: _",
+                    expires_at, last_used_at, created_at, updated_at
+            "#,
+            request.user_id,
+            request.name,
+            request.token_hash,
+            request.scope as _,
+            request.expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(token)
+    }
+
+    /// Lists the tokens of a user, the newest first.
+    #[instrument(skip(pool))]
+    pub async fn find_all_by_user(
+        pool: &PgPool,
+        user_id: EntityId,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let tokens = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT
+                    id, user_id, name, token_hash, scope AS "scope: _",
+                    expires_at, last_used_at, created_at, updated_at
+                FROM api_token WHERE user_id = $1
+                ORDER BY created_at DESC, id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(tokens)
+    }
+
+    /// Finds an unexpired token and records that it was used.
+    #[instrument(skip_all)]
+    pub async fn use_token(pool: &PgPool, token_hash: &str) -> Result<Option<Self>, sqlx::Error> {
+        let token = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE api_token SET last_used_at = NOW()
+                WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > NOW())
+                RETURNING
+                    id, user_id, name, token_hash, scope AS "scope: _",
+                    expires_at, last_used_at, created_at, updated_at
+            "#,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(token)
+    }
+
+    /// Deletes a token of a user, returns whether the user had such a token.
+    #[instrument(skip(pool))]
+    pub async fn delete_by_user(
+        pool: &PgPool,
+        id: EntityId,
+        user_id: EntityId,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM api_token WHERE id = $1 AND user_id = $2",
+            id,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
@@ -0,0 +1,301 @@
+use async_trait::async_trait;
+#[cfg(test)]
+use fake::Dummy;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::model::{AudioChannels, AudioTrack, EntityId};
+
+use super::{
+    build_find_all_query, Column, Counted, Entity, Filter, FilterField, OrderBy, Pagination,
+    ValueKind,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct CreateAudioTrackRequest {
+    pub video_id: EntityId,
+    pub stream_index: i32,
+    pub codec: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub channels: AudioChannels,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum AudioTrackOrdering {
+    VideoId,
+    StreamIndex,
+    Codec,
+    Language,
+    Title,
+    Channels,
+    IsDefault,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for AudioTrackOrdering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::VideoId => "video_id",
+            Self::StreamIndex => "stream_index",
+            Self::Codec => "codec",
+            Self::Language => "language",
+            Self::Title => "title",
+            Self::Channels => "channels",
+            Self::IsDefault => "is_default",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum AudioTrackFiltering {
+    Id,
+    VideoId,
+    StreamIndex,
+    Codec,
+    Language,
+    Title,
+    Channels,
+    IsDefault,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for AudioTrackFiltering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::VideoId => "video_id",
+            Self::StreamIndex => "stream_index",
+            Self::Codec => "codec",
+            Self::Language => "language",
+            Self::Title => "title",
+            Self::Channels => "channels",
+            Self::IsDefault => "is_default",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+impl FilterField for AudioTrackFiltering {
+    fn kind(&self) -> ValueKind {
+        match self {
+            Self::Id | Self::VideoId | Self::StreamIndex | Self::Channels => ValueKind::Integer,
+            Self::Codec | Self::Language | Self::Title => ValueKind::Text,
+            Self::IsDefault => ValueKind::Boolean,
+            Self::CreatedAt | Self::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct UpdateAudioTrackRequest {
+    pub id: EntityId,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub is_default: bool,
+}
+
+impl From<AudioTrack> for CreateAudioTrackRequest {
+    fn from(track: AudioTrack) -> Self {
+        Self {
+            video_id: track.video_id,
+            stream_index: track.stream_index,
+            codec: track.codec,
+            language: track.language,
+            title: track.title,
+            channels: track.channels,
+            is_default: track.is_default,
+        }
+    }
+}
+
+#[async_trait]
+impl Entity<Self> for AudioTrack {
+    type CreateRequest = CreateAudioTrackRequest;
+    type Filtering = AudioTrackFiltering;
+
+    type Ordering = AudioTrackOrdering;
+
+    type UpdateRequest = UpdateAudioTrackRequest;
+    type UpdateError = sqlx::Error;
+
+    #[instrument(skip(pool))]
+    async fn create(pool: &PgPool, request: CreateAudioTrackRequest) -> Result<Self, sqlx::Error> {
+        let track = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO audio_track ( video_id, stream_index, codec, language, title, channels, is_default )
+                VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+                RETURNING *
+            "#,
+            request.video_id,
+            request.stream_index,
+            request.codec,
+            request.language,
+            request.title,
+            request.channels,
+            request.is_default
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(track)
+    }
+
+    #[instrument(skip(pool))]
+    async fn create_many(
+        pool: &PgPool,
+        requests: Vec<CreateAudioTrackRequest>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let mut video_ids = vec![];
+        let mut stream_indexes = vec![];
+        let mut codecs = vec![];
+        let mut languages = vec![];
+        let mut titles = vec![];
+        let mut channels = vec![];
+        let mut defaults = vec![];
+
+        for item in requests {
+            video_ids.push(item.video_id);
+            stream_indexes.push(item.stream_index);
+            codecs.push(item.codec);
+            languages.push(item.language);
+            titles.push(item.title);
+            channels.push(item.channels);
+            defaults.push(item.is_default);
+        }
+
+        let tracks = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO audio_track ( video_id, stream_index, codec, language, title, channels, is_default )
+                SELECT * FROM UNNEST(
+                    $1::int8[], $2::int4[], $3::text[], $4::text[], $5::text[], $6::int2[], $7::bool[]
+                )
+                RETURNING *
+            "#,
+            &video_ids[..],
+            &stream_indexes[..],
+            &codecs[..],
+            &languages[..] as &[Option<String>],
+            &titles[..] as &[Option<String>],
+            &channels[..],
+            &defaults[..]
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(tracks)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find(pool: &PgPool, id: EntityId) -> Result<Option<Self>, sqlx::Error> {
+        let track = sqlx::query_as!(Self, "SELECT * FROM audio_track WHERE id = $1", id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(track)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all(
+        pool: &PgPool,
+        filters: Vec<Filter<AudioTrackFiltering>>,
+        ordering: Vec<OrderBy<AudioTrackOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query = build_find_all_query("audio_track", filters, ordering, pagination);
+
+        let tracks = query.fetch_all(pool).await?;
+
+        Ok(tracks)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<AudioTrackFiltering>>,
+        ordering: Vec<OrderBy<AudioTrackOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query("audio_track", filters, ordering, pagination);
+
+        let tracks = query.fetch_counted(pool).await?;
+
+        Ok(tracks)
+    }
+
+    #[instrument(skip(pool))]
+    async fn update(
+        pool: &PgPool,
+        request: UpdateAudioTrackRequest,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let track = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE audio_track SET language = $1, title = $2, is_default = $3
+                WHERE id = $4
+                RETURNING *
+            "#,
+            request.language,
+            request.title,
+            request.is_default,
+            request.id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(track)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM audio_track WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete_many(pool: &PgPool, ids: Vec<EntityId>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM audio_track WHERE id = ANY($1)", &ids[..])
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(skip(pool))]
+    async fn count_all(pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM audio_track"#)
+            .fetch_one(pool)
+            .await?;
+        Ok(count)
+    }
+}
+
+impl AudioTrack {
+    /// Lists the audio tracks of videos, in the order of the videos and then of their tracks.
+    #[instrument(skip(pool))]
+    pub async fn find_all_by_videos(
+        pool: &PgPool,
+        video_ids: &[EntityId],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let tracks = sqlx::query_as!(
+            Self,
+            "SELECT * FROM audio_track WHERE video_id = ANY($1) ORDER BY video_id, stream_index",
+            video_ids
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(tracks)
+    }
+}
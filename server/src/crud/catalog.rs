@@ -1,24 +1,30 @@
 use async_trait::async_trait;
 #[cfg(test)]
 use fake::Dummy;
+use serde::Deserialize;
 use sqlx::PgPool;
+use time::OffsetDateTime;
 use tracing::instrument;
 
 use crate::model::{Catalog, EntityId};
 
-use super::{build_find_all_query, Entity, OrderBy, Pagination};
+use super::{
+    build_find_all_query, build_find_all_query_where, Column, Counted, Entity, Filter, FilterField,
+    FilterValue, Keyed, OrderBy, Pagination, ValueKind,
+};
 
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(Dummy))]
 pub struct CreateCatalogRequest {
-    path: String,
-    display_name: String,
-    short_desc: String,
-    long_desc: String,
+    pub path: String,
+    pub display_name: String,
+    pub short_desc: String,
+    pub long_desc: String,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize)]
 #[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
 pub enum CatalogOrdering {
     Path,
     DisplayName,
@@ -28,20 +34,74 @@ pub enum CatalogOrdering {
     UpdatedAt,
 }
 
+impl Column for CatalogOrdering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::DisplayName => "display_name",
+            Self::ShortDesc => "short_desc",
+            Self::LongDesc => "long_desc",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum CatalogFiltering {
+    Id,
+    Path,
+    DisplayName,
+    ShortDesc,
+    LongDesc,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for CatalogFiltering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Path => "path",
+            Self::DisplayName => "display_name",
+            Self::ShortDesc => "short_desc",
+            Self::LongDesc => "long_desc",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+impl FilterField for CatalogFiltering {
+    fn kind(&self) -> ValueKind {
+        match self {
+            Self::Id => ValueKind::Integer,
+            Self::Path | Self::DisplayName | Self::ShortDesc | Self::LongDesc => ValueKind::Text,
+            Self::CreatedAt | Self::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(Dummy))]
 pub struct UpdateCatalogRequest {
-    id: EntityId,
-    display_name: String,
-    short_desc: String,
-    long_desc: String,
+    pub id: EntityId,
+    pub display_name: String,
+    pub short_desc: String,
+    pub long_desc: String,
+    /// Only updates the catalog while it is still at this revision, or at any revision when it is `None`.
+    pub expected_updated_at: Option<Vec<OffsetDateTime>>,
 }
 
 #[async_trait]
 impl Entity<Self> for Catalog {
     type CreateRequest = CreateCatalogRequest;
+    type Filtering = CatalogFiltering;
     type Ordering = CatalogOrdering;
     type UpdateRequest = UpdateCatalogRequest;
+    type UpdateError = sqlx::Error;
 
     #[instrument(skip(pool))]
     async fn create(pool: &PgPool, request: CreateCatalogRequest) -> Result<Self, sqlx::Error> {
@@ -108,12 +168,27 @@ impl Entity<Self> for Catalog {
     #[instrument(skip(pool))]
     async fn find_all(
         pool: &PgPool,
+        filters: Vec<Filter<CatalogFiltering>>,
         ordering: Vec<OrderBy<CatalogOrdering>>,
         pagination: Option<Pagination>,
     ) -> Result<Vec<Self>, sqlx::Error> {
-        let query = build_find_all_query("catalog", ordering, pagination);
+        let query = build_find_all_query("catalog", filters, ordering, pagination);
 
-        let catalogs = sqlx::query_as(&query).fetch_all(pool).await?;
+        let catalogs = query.fetch_all(pool).await?;
+
+        Ok(catalogs)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<CatalogFiltering>>,
+        ordering: Vec<OrderBy<CatalogOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query("catalog", filters, ordering, pagination);
+
+        let catalogs = query.fetch_counted(pool).await?;
 
         Ok(catalogs)
     }
@@ -124,24 +199,26 @@ impl Entity<Self> for Catalog {
         request: UpdateCatalogRequest,
     ) -> Result<Option<Self>, sqlx::Error> {
         let catalog = sqlx::query_as!(
-        Self,
-        "UPDATE catalog SET display_name = $1, short_desc = $2, long_desc = $3 WHERE id = $4 RETURNING *",
-        request.display_name,
-        request.short_desc,
-        request.long_desc,
-        request.id
-    )
-    .fetch_optional(pool)
-    .await?;
+            Self,
+            r#"
+                UPDATE catalog SET display_name = $1, short_desc = $2, long_desc = $3
+                WHERE id = $4 AND ($5::TIMESTAMPTZ[] IS NULL OR updated_at = ANY($5))
+                RETURNING *
+            "#,
+            request.display_name,
+            request.short_desc,
+            request.long_desc,
+            request.id,
+            request.expected_updated_at.as_deref()
+        )
+        .fetch_optional(pool)
+        .await?;
         Ok(catalog)
     }
 
     #[instrument(skip(pool))]
     async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query!("DELETE FROM catalog WHERE id = $1", id)
-            .execute(pool)
-            .await?;
-        Ok(result.rows_affected() == 1)
+        Self::delete_unless_changed(pool, id, None).await
     }
 
     #[instrument(skip(pool))]
@@ -161,6 +238,111 @@ impl Entity<Self> for Catalog {
     }
 }
 
+impl Keyed for Catalog {
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn key_kind(field: &CatalogOrdering) -> ValueKind {
+        match field {
+            CatalogOrdering::Path
+            | CatalogOrdering::DisplayName
+            | CatalogOrdering::ShortDesc
+            | CatalogOrdering::LongDesc => ValueKind::Text,
+            CatalogOrdering::CreatedAt | CatalogOrdering::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+
+    fn key(&self, field: &CatalogOrdering) -> Option<FilterValue> {
+        match field {
+            CatalogOrdering::Path => Some(FilterValue::Text(self.path.clone())),
+            CatalogOrdering::DisplayName => Some(FilterValue::Text(self.display_name.clone())),
+            CatalogOrdering::ShortDesc => Some(FilterValue::Text(self.short_desc.clone())),
+            CatalogOrdering::LongDesc => Some(FilterValue::Text(self.long_desc.clone())),
+            CatalogOrdering::CreatedAt => Some(FilterValue::Timestamp(self.created_at)),
+            CatalogOrdering::UpdatedAt => Some(FilterValue::Timestamp(self.updated_at)),
+        }
+    }
+}
+
+impl Catalog {
+    /// Deletes the catalog while it is still at one of the expected revisions, or at any revision without them.
+    #[instrument(skip(pool))]
+    pub async fn delete_unless_changed(
+        pool: &PgPool,
+        id: EntityId,
+        expected_updated_at: Option<&[OffsetDateTime]>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM catalog WHERE id = $1 AND ($2::TIMESTAMPTZ[] IS NULL OR updated_at = ANY($2))",
+            id,
+            expected_updated_at
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Deletes the catalogs at the paths that no video belongs to, returns the number of deleted catalogs.
+    #[instrument(skip(pool))]
+    pub async fn delete_unused(pool: &PgPool, paths: &[String]) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM catalog
+                WHERE path = ANY($1)
+                    AND NOT EXISTS ( SELECT 1 FROM video WHERE video.catalog_id = catalog.id )
+            "#,
+            paths
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Changes the path of a catalog, used when its directory is moved on the disk.
+    #[instrument(skip(pool))]
+    pub async fn update_path(
+        pool: &PgPool,
+        id: EntityId,
+        path: String,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let catalog = sqlx::query_as!(
+            Self,
+            "UPDATE catalog SET path = $1 WHERE id = $2 RETURNING *",
+            path,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(catalog)
+    }
+
+    /// Lists the catalogs granted to a user, counting them on every page.
+    #[instrument(skip(pool))]
+    pub async fn find_all_granted(
+        pool: &PgPool,
+        user_id: EntityId,
+        filters: Vec<Filter<CatalogFiltering>>,
+        ordering: Vec<OrderBy<CatalogOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query_where(
+            "catalog",
+            Some((
+                "id IN (SELECT catalog_id FROM catalog_grant WHERE user_id = $1)",
+                user_id,
+            )),
+            filters,
+            ordering,
+            pagination,
+        );
+
+        let catalogs = query.fetch_counted(pool).await?;
+
+        Ok(catalogs)
+    }
+}
+
 /* #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,78 @@
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::model::{CatalogGrant, EntityId};
+
+impl CatalogGrant {
+    /// Lists the ids of the catalogs granted to a user, in ascending order.
+    #[instrument(skip(pool))]
+    pub async fn find_catalog_ids(
+        pool: &PgPool,
+        user_id: EntityId,
+    ) -> Result<Vec<EntityId>, sqlx::Error> {
+        let ids = sqlx::query_scalar!(
+            "SELECT catalog_id FROM catalog_grant WHERE user_id = $1 ORDER BY catalog_id",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(ids)
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn exists(
+        pool: &PgPool,
+        user_id: EntityId,
+        catalog_id: EntityId,
+    ) -> Result<bool, sqlx::Error> {
+        let exists = sqlx::query_scalar!(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1 FROM catalog_grant WHERE user_id = $1 AND catalog_id = $2
+                ) AS "exists!"
+            "#,
+            user_id,
+            catalog_id
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(exists)
+    }
+
+    /// Replaces the catalogs granted to a user, returns the granted catalog ids in ascending order.
+    /// Returns `None` without changing anything if one of the catalogs does not exist.
+    #[instrument(skip(pool))]
+    pub async fn replace(
+        pool: &PgPool,
+        user_id: EntityId,
+        catalog_ids: &[EntityId],
+    ) -> Result<Option<Vec<EntityId>>, sqlx::Error> {
+        let mut requested = catalog_ids.to_vec();
+        requested.sort_unstable();
+        requested.dedup();
+
+        let mut transaction = pool.begin().await?;
+        sqlx::query!("DELETE FROM catalog_grant WHERE user_id = $1", user_id)
+            .execute(&mut *transaction)
+            .await?;
+        let mut granted = sqlx::query_scalar!(
+            r#"
+                INSERT INTO catalog_grant ( user_id, catalog_id )
+                SELECT $1, id FROM catalog WHERE id = ANY($2)
+                RETURNING catalog_id
+            "#,
+            user_id,
+            &requested[..]
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+        granted.sort_unstable();
+
+        if granted != requested {
+            transaction.rollback().await?;
+            return Ok(None);
+        }
+        transaction.commit().await?;
+        Ok(Some(granted))
+    }
+}
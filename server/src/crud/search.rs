@@ -0,0 +1,238 @@
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::model::EntityId;
+
+use super::Pagination;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, ToSchema)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    Catalog,
+    Video,
+}
+
+/// A catalog or a video matching a search.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    pub id: EntityId,
+    /// The catalog of a video, missing for catalogs.
+    pub catalog_id: Option<EntityId>,
+    pub display_name: String,
+    /// The matching parts of the name and the descriptions, the matched words wrapped in `<mark>` tags.
+    pub highlight: String,
+    pub rank: f32,
+}
+
+#[derive(Debug)]
+pub struct SearchRequest {
+    /// Web search syntax, like `"exact phrase" -excluded or alternative`.
+    pub query: String,
+    /// Only searches the catalogs granted to the user, and their videos.
+    pub granted_to: Option<EntityId>,
+    pub pagination: Pagination,
+}
+
+impl SearchHit {
+    /// Searches the names and the descriptions of catalogs and videos, the best matches first.
+    #[instrument(skip(pool))]
+    pub async fn search(pool: &PgPool, request: SearchRequest) -> Result<Vec<Self>, sqlx::Error> {
+        let hits = sqlx::query_as!(
+            Self,
+            r#"
+                WITH
+                    query AS ( SELECT websearch_to_tsquery('simple', $1) AS query ),
+                    hits AS (
+                        SELECT
+                            'catalog' AS kind, catalog.id, NULL::BIGINT AS catalog_id,
+                            catalog.display_name, catalog.short_desc, catalog.long_desc,
+                            ts_rank(
+                                search_document(catalog.display_name, catalog.short_desc, catalog.long_desc),
+                                query.query
+                            ) AS rank
+                        FROM catalog, query
+                        WHERE search_document(catalog.display_name, catalog.short_desc, catalog.long_desc)
+                                @@ query.query
+                            AND ($2::BIGINT IS NULL OR catalog.id IN (
+                                SELECT catalog_id FROM catalog_grant WHERE user_id = $2
+                            ))
+                        UNION ALL
+                        SELECT
+                            'video' AS kind, video.id, video.catalog_id,
+                            video.display_name, video.short_desc, video.long_desc,
+                            ts_rank(
+                                search_document(video.display_name, video.short_desc, video.long_desc),
+                                query.query
+                            ) AS rank
+                        FROM video, query
+                        WHERE search_document(video.display_name, video.short_desc, video.long_desc)
+                                @@ query.query
+                            AND ($2::BIGINT IS NULL OR video.catalog_id IN (
+                                SELECT catalog_id FROM catalog_grant WHERE user_id = $2
+                            ))
+                        ORDER BY rank DESC, kind, id
+                        LIMIT $3 OFFSET $4
+                    )
+                SELECT
+                    hits.kind AS "kind!: SearchHitKind",
+                    hits.id AS "id!",
+                    hits.catalog_id,
+                    hits.display_name AS "display_name!",
+                    ts_headline(
+                        'simple',
+                        concat_ws(' … ', hits.display_name, NULLIF(hits.short_desc, ''), NULLIF(hits.long_desc, '')),
+                        query.query,
+                        'StartSel=<mark>, StopSel=</mark>, MaxFragments=3'
+                    ) AS "highlight!",
+                    hits.rank AS "rank!"
+                FROM hits, query
+                ORDER BY hits.rank DESC, hits.kind, hits.id
+            "#,
+            request.query,
+            request.granted_to,
+            request.pagination.limit() as i64,
+            request.pagination.offset() as i64
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crud::{
+            catalog::CreateCatalogRequest, user::CreateUserRequest, video::CreateVideoRequest,
+            Entity,
+        },
+        model::{Catalog, CatalogGrant, User, UserRole, Video},
+    };
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    async fn catalog(pool: &PgPool, display_name: &str, short_desc: &str) -> Catalog {
+        let request = CreateCatalogRequest {
+            display_name: display_name.to_owned(),
+            short_desc: short_desc.to_owned(),
+            long_desc: String::new(),
+            ..Faker.fake()
+        };
+        Catalog::create(pool, request).await.unwrap()
+    }
+
+    async fn video(pool: &PgPool, catalog: &Catalog, display_name: &str, long_desc: &str) -> Video {
+        let request = CreateVideoRequest {
+            display_name: display_name.to_owned(),
+            short_desc: String::new(),
+            long_desc: long_desc.to_owned(),
+            catalog_id: catalog.id,
+            sequent_id: None,
+            ..Faker.fake()
+        };
+        Video::create(pool, request).await.unwrap()
+    }
+
+    async fn search(pool: &PgPool, query: &str, granted_to: Option<EntityId>) -> Vec<SearchHit> {
+        let request = SearchRequest {
+            query: query.to_owned(),
+            granted_to,
+            pagination: Pagination::new(10, 1, 10).unwrap(),
+        };
+        SearchHit::search(pool, request).await.unwrap()
+    }
+
+    fn hits(hits: &[SearchHit]) -> Vec<(SearchHitKind, EntityId)> {
+        hits.iter().map(|hit| (hit.kind, hit.id)).collect()
+    }
+
+    #[sqlx::test]
+    async fn search_document_weighs_names_above_descriptions(pool: PgPool) {
+        let document = sqlx::query_scalar!(
+            r#"SELECT search_document('The Running Man', 'Games', 'Of the future')::TEXT AS "document!""#
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        // neither stemmed nor stripped of stop words
+        assert_eq!(
+            document,
+            "'future':7C 'games':4B 'man':3A 'of':5C 'running':2A 'the':1A,6C"
+        );
+    }
+
+    #[sqlx::test]
+    async fn search_ranks_names_first(pool: PgPool) {
+        let described = catalog(&pool, "Deserts", "dune seas of the world").await;
+        let in_description = video(&pool, &described, "Sahara", "the tallest dune").await;
+        let named = video(&pool, &described, "Dune", "").await;
+        video(&pool, &described, "Dunes", "").await;
+
+        let found = search(&pool, "DUNE", None).await;
+
+        assert_eq!(
+            hits(&found),
+            vec![
+                (SearchHitKind::Video, named.id),
+                (SearchHitKind::Catalog, described.id),
+                (SearchHitKind::Video, in_description.id),
+            ]
+        );
+        assert_eq!(found[0].catalog_id, Some(described.id));
+        assert_eq!(found[1].catalog_id, None);
+        assert_eq!(found[2].highlight, "Sahara … the tallest <mark>dune</mark>");
+    }
+
+    #[sqlx::test]
+    async fn search_supports_phrases_and_exclusions(pool: PgPool) {
+        let films = catalog(&pool, "Films", "").await;
+        let first = video(&pool, &films, "Dune", "the desert planet").await;
+        let sequel = video(&pool, &films, "Dune Messiah", "the desert planet again").await;
+        video(&pool, &films, "Planet Desert", "").await;
+
+        let excluded = search(&pool, "dune -messiah", None).await;
+        let phrase = search(&pool, r#""desert planet" again"#, None).await;
+
+        assert_eq!(hits(&excluded), vec![(SearchHitKind::Video, first.id)]);
+        assert_eq!(hits(&phrase), vec![(SearchHitKind::Video, sequel.id)]);
+    }
+
+    #[sqlx::test]
+    async fn search_only_finds_granted_catalogs_and_their_videos(pool: PgPool) {
+        let granted = catalog(&pool, "Granted space", "").await;
+        let granted_video = video(&pool, &granted, "Space", "").await;
+        let other = catalog(&pool, "Other space", "").await;
+        video(&pool, &other, "Space", "").await;
+        let guest = User::create(
+            &pool,
+            CreateUserRequest {
+                role: UserRole::Guest,
+                ..Faker.fake()
+            },
+        )
+        .await
+        .unwrap();
+        CatalogGrant::replace(&pool, guest.id, &[granted.id])
+            .await
+            .unwrap();
+
+        let found = search(&pool, "space", Some(guest.id)).await;
+        let all = search(&pool, "space", None).await;
+
+        // equally ranked hits are listed catalogs first
+        assert_eq!(
+            hits(&found),
+            vec![
+                (SearchHitKind::Catalog, granted.id),
+                (SearchHitKind::Video, granted_video.id),
+            ]
+        );
+        assert_eq!(all.len(), 4);
+    }
+}
@@ -0,0 +1,49 @@
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::instrument;
+
+use crate::model::{EntityId, Session};
+
+impl Session {
+    #[instrument(skip(pool, token_hash))]
+    pub async fn create(
+        pool: &PgPool,
+        user_id: EntityId,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<Self, sqlx::Error> {
+        let session = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO session ( user_id, token_hash, expires_at )
+                VALUES ( $1, $2, $3 )
+                RETURNING *
+            "#,
+            user_id,
+            token_hash,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(session)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_by_token_hash(
+        pool: &PgPool,
+        token_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM session WHERE token_hash = $1", token_hash)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn delete_expired(pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM session WHERE expires_at <= NOW()")
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,346 @@
+use async_trait::async_trait;
+#[cfg(test)]
+use fake::Dummy;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::model::{EntityId, Subtitle, SubtitleFormat};
+
+use super::{
+    build_find_all_query, Column, Counted, Entity, Filter, FilterField, OrderBy, Pagination,
+    ValueKind,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct CreateSubtitleRequest {
+    pub video_id: EntityId,
+    pub path: Option<String>,
+    pub stream_index: Option<i32>,
+    pub format: SubtitleFormat,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleOrdering {
+    VideoId,
+    Path,
+    StreamIndex,
+    Format,
+    Language,
+    Title,
+    IsDefault,
+    IsForced,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for SubtitleOrdering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::VideoId => "video_id",
+            Self::Path => "path",
+            Self::StreamIndex => "stream_index",
+            Self::Format => "format",
+            Self::Language => "language",
+            Self::Title => "title",
+            Self::IsDefault => "is_default",
+            Self::IsForced => "is_forced",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleFiltering {
+    Id,
+    VideoId,
+    Path,
+    StreamIndex,
+    Format,
+    Language,
+    Title,
+    IsDefault,
+    IsForced,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for SubtitleFiltering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::VideoId => "video_id",
+            Self::Path => "path",
+            Self::StreamIndex => "stream_index",
+            Self::Format => "format",
+            Self::Language => "language",
+            Self::Title => "title",
+            Self::IsDefault => "is_default",
+            Self::IsForced => "is_forced",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+impl FilterField for SubtitleFiltering {
+    fn kind(&self) -> ValueKind {
+        match self {
+            Self::Id | Self::VideoId | Self::StreamIndex => ValueKind::Integer,
+            Self::Path | Self::Language | Self::Title => ValueKind::Text,
+            Self::Format => ValueKind::Enum("subtitle_format"),
+            Self::IsDefault | Self::IsForced => ValueKind::Boolean,
+            Self::CreatedAt | Self::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct UpdateSubtitleRequest {
+    pub id: EntityId,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+}
+
+impl From<Subtitle> for CreateSubtitleRequest {
+    fn from(subtitle: Subtitle) -> Self {
+        Self {
+            video_id: subtitle.video_id,
+            path: subtitle.path,
+            stream_index: subtitle.stream_index,
+            format: subtitle.format,
+            language: subtitle.language,
+            title: subtitle.title,
+            is_default: subtitle.is_default,
+            is_forced: subtitle.is_forced,
+        }
+    }
+}
+
+/// Column lists of the parallel arrays inserted with `UNNEST`.
+#[derive(Debug, Default)]
+struct SubtitleColumns {
+    video_ids: Vec<EntityId>,
+    paths: Vec<Option<String>>,
+    stream_indexes: Vec<Option<i32>>,
+    formats: Vec<SubtitleFormat>,
+    languages: Vec<Option<String>>,
+    titles: Vec<Option<String>>,
+    defaults: Vec<bool>,
+    forced: Vec<bool>,
+}
+
+impl From<Vec<CreateSubtitleRequest>> for SubtitleColumns {
+    fn from(requests: Vec<CreateSubtitleRequest>) -> Self {
+        let mut columns = Self::default();
+        for item in requests {
+            columns.video_ids.push(item.video_id);
+            columns.paths.push(item.path);
+            columns.stream_indexes.push(item.stream_index);
+            columns.formats.push(item.format);
+            columns.languages.push(item.language);
+            columns.titles.push(item.title);
+            columns.defaults.push(item.is_default);
+            columns.forced.push(item.is_forced);
+        }
+        columns
+    }
+}
+
+#[async_trait]
+impl Entity<Self> for Subtitle {
+    type CreateRequest = CreateSubtitleRequest;
+    type Filtering = SubtitleFiltering;
+
+    type Ordering = SubtitleOrdering;
+
+    type UpdateRequest = UpdateSubtitleRequest;
+    type UpdateError = sqlx::Error;
+
+    #[instrument(skip(pool))]
+    async fn create(pool: &PgPool, request: CreateSubtitleRequest) -> Result<Self, sqlx::Error> {
+        let subtitle = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO subtitle ( video_id, path, stream_index, format, language, title, is_default, is_forced )
+                VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 )
+                RETURNING
+                    id, video_id, path, stream_index, format AS "format: _", language, title,
+                    is_default, is_forced, created_at, updated_at
+            "#,
+            request.video_id,
+            request.path,
+            request.stream_index,
+            request.format as _,
+            request.language,
+            request.title,
+            request.is_default,
+            request.is_forced
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(subtitle)
+    }
+
+    #[instrument(skip(pool))]
+    async fn create_many(
+        pool: &PgPool,
+        requests: Vec<CreateSubtitleRequest>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let columns = SubtitleColumns::from(requests);
+        let subtitles = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO subtitle ( video_id, path, stream_index, format, language, title, is_default, is_forced )
+                SELECT * FROM UNNEST(
+                    $1::int8[], $2::text[], $3::int4[], $4::subtitle_format[], $5::text[], $6::text[], $7::bool[], $8::bool[]
+                )
+                RETURNING
+                    id, video_id, path, stream_index, format AS "format: _", language, title,
+                    is_default, is_forced, created_at, updated_at
+            "#,
+            &columns.video_ids[..],
+            &columns.paths[..] as &[Option<String>],
+            &columns.stream_indexes[..] as &[Option<i32>],
+            &columns.formats[..] as &[SubtitleFormat],
+            &columns.languages[..] as &[Option<String>],
+            &columns.titles[..] as &[Option<String>],
+            &columns.defaults[..],
+            &columns.forced[..]
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(subtitles)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find(pool: &PgPool, id: EntityId) -> Result<Option<Self>, sqlx::Error> {
+        let subtitle = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT
+                    id, video_id, path, stream_index, format AS "format: _", language, title,
+                    is_default, is_forced, created_at, updated_at
+                FROM subtitle WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(subtitle)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all(
+        pool: &PgPool,
+        filters: Vec<Filter<SubtitleFiltering>>,
+        ordering: Vec<OrderBy<SubtitleOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query = build_find_all_query("subtitle", filters, ordering, pagination);
+
+        let subtitles = query.fetch_all(pool).await?;
+
+        Ok(subtitles)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<SubtitleFiltering>>,
+        ordering: Vec<OrderBy<SubtitleOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query("subtitle", filters, ordering, pagination);
+
+        let subtitles = query.fetch_counted(pool).await?;
+
+        Ok(subtitles)
+    }
+
+    #[instrument(skip(pool))]
+    async fn update(
+        pool: &PgPool,
+        request: UpdateSubtitleRequest,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let subtitle = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE subtitle SET language = $1, title = $2, is_default = $3, is_forced = $4
+                WHERE id = $5
+                RETURNING
+                    id, video_id, path, stream_index, format AS "format: _", language, title,
+                    is_default, is_forced, created_at, updated_at
+            "#,
+            request.language,
+            request.title,
+            request.is_default,
+            request.is_forced,
+            request.id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(subtitle)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM subtitle WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete_many(pool: &PgPool, ids: Vec<EntityId>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM subtitle WHERE id = ANY($1)", &ids[..])
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(skip(pool))]
+    async fn count_all(pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM subtitle"#)
+            .fetch_one(pool)
+            .await?;
+        Ok(count)
+    }
+}
+
+impl Subtitle {
+    /// Lists the subtitles of a video, the embedded tracks in their order first, then the side-car files by path.
+    #[instrument(skip(pool))]
+    pub async fn find_all_by_video(
+        pool: &PgPool,
+        video_id: EntityId,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let subtitles = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT
+                    id, video_id, path, stream_index, format AS "format: _", language, title,
+                    is_default, is_forced, created_at, updated_at
+                FROM subtitle WHERE video_id = $1
+                ORDER BY stream_index NULLS LAST, path
+            "#,
+            video_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(subtitles)
+    }
+}
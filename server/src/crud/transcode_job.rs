@@ -0,0 +1,501 @@
+use async_trait::async_trait;
+#[cfg(test)]
+use fake::Dummy;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::model::{EntityId, Progress, TranscodeJob, TranscodeJobKind, TranscodeJobStatus};
+
+use super::{
+    build_find_all_query, Column, Counted, Entity, Filter, FilterField, FilterValue, Keyed,
+    OrderBy, Pagination, ValueKind,
+};
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct CreateTranscodeJobRequest {
+    pub video_id: EntityId,
+    pub kind: TranscodeJobKind,
+    pub rendition: Option<String>,
+    pub audio_index: Option<i32>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeJobOrdering {
+    VideoId,
+    Kind,
+    Rendition,
+    AudioIndex,
+    Status,
+    Progress,
+    StartedAt,
+    FinishedAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for TranscodeJobOrdering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::VideoId => "video_id",
+            Self::Kind => "kind",
+            Self::Rendition => "rendition",
+            Self::AudioIndex => "audio_index",
+            Self::Status => "status",
+            Self::Progress => "progress",
+            Self::StartedAt => "started_at",
+            Self::FinishedAt => "finished_at",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeJobFiltering {
+    Id,
+    VideoId,
+    Kind,
+    Rendition,
+    AudioIndex,
+    Status,
+    Progress,
+    StartedAt,
+    FinishedAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for TranscodeJobFiltering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::VideoId => "video_id",
+            Self::Kind => "kind",
+            Self::Rendition => "rendition",
+            Self::AudioIndex => "audio_index",
+            Self::Status => "status",
+            Self::Progress => "progress",
+            Self::StartedAt => "started_at",
+            Self::FinishedAt => "finished_at",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+impl FilterField for TranscodeJobFiltering {
+    fn kind(&self) -> ValueKind {
+        match self {
+            Self::Id | Self::VideoId | Self::AudioIndex => ValueKind::Integer,
+            Self::Kind => ValueKind::Enum("transcode_job_kind"),
+            Self::Status => ValueKind::Enum("transcode_job_status"),
+            Self::Rendition => ValueKind::Text,
+            Self::Progress => ValueKind::Float,
+            Self::StartedAt | Self::FinishedAt | Self::CreatedAt | Self::UpdatedAt => {
+                ValueKind::Timestamp
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct UpdateTranscodeJobRequest {
+    pub id: EntityId,
+    pub status: TranscodeJobStatus,
+    pub progress: Progress,
+    pub error: Option<String>,
+}
+
+#[async_trait]
+impl Entity<Self> for TranscodeJob {
+    type CreateRequest = CreateTranscodeJobRequest;
+    type Filtering = TranscodeJobFiltering;
+
+    type Ordering = TranscodeJobOrdering;
+
+    type UpdateRequest = UpdateTranscodeJobRequest;
+    type UpdateError = sqlx::Error;
+
+    #[instrument(skip(pool))]
+    async fn create(
+        pool: &PgPool,
+        request: CreateTranscodeJobRequest,
+    ) -> Result<Self, sqlx::Error> {
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO transcode_job ( video_id, kind, rendition, audio_index, request_id )
+                VALUES ( $1, $2, $3, $4, $5 )
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#,
+            request.video_id,
+            request.kind as _,
+            request.rendition,
+            request.audio_index,
+            request.request_id
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(job)
+    }
+
+    #[instrument(skip(pool))]
+    async fn create_many(
+        pool: &PgPool,
+        requests: Vec<CreateTranscodeJobRequest>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let mut video_ids = vec![];
+        let mut kinds = vec![];
+        let mut renditions = vec![];
+        let mut audio_indexes = vec![];
+        let mut request_ids = vec![];
+
+        for item in requests {
+            video_ids.push(item.video_id);
+            kinds.push(item.kind);
+            renditions.push(item.rendition);
+            audio_indexes.push(item.audio_index);
+            request_ids.push(item.request_id);
+        }
+
+        let jobs = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO transcode_job ( video_id, kind, rendition, audio_index, request_id )
+                SELECT * FROM UNNEST(
+                    $1::int8[], $2::transcode_job_kind[], $3::text[], $4::int4[], $5::text[]
+                )
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#,
+            &video_ids[..],
+            &kinds[..] as &[TranscodeJobKind],
+            &renditions[..] as &[Option<String>],
+            &audio_indexes[..] as &[Option<i32>],
+            &request_ids[..] as &[Option<String>]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(jobs)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find(pool: &PgPool, id: EntityId) -> Result<Option<Self>, sqlx::Error> {
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+                FROM transcode_job WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all(
+        pool: &PgPool,
+        filters: Vec<Filter<TranscodeJobFiltering>>,
+        ordering: Vec<OrderBy<TranscodeJobOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query = build_find_all_query("transcode_job", filters, ordering, pagination);
+
+        let jobs = query.fetch_all(pool).await?;
+
+        Ok(jobs)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<TranscodeJobFiltering>>,
+        ordering: Vec<OrderBy<TranscodeJobOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query("transcode_job", filters, ordering, pagination);
+
+        let jobs = query.fetch_counted(pool).await?;
+
+        Ok(jobs)
+    }
+
+    #[instrument(skip(pool))]
+    async fn update(
+        pool: &PgPool,
+        request: UpdateTranscodeJobRequest,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE transcode_job SET
+                    status = $1, progress = $2, error = $3,
+                    finished_at = CASE
+                        WHEN $1::transcode_job_status IN ('completed', 'failed', 'cancelled')
+                        THEN COALESCE(finished_at, NOW())
+                    END
+                WHERE id = $4
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#,
+            request.status as _,
+            request.progress,
+            request.error,
+            request.id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM transcode_job WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete_many(pool: &PgPool, ids: Vec<EntityId>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM transcode_job WHERE id = ANY($1)", &ids[..])
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(skip(pool))]
+    async fn count_all(pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM transcode_job"#)
+            .fetch_one(pool)
+            .await?;
+        Ok(count)
+    }
+}
+
+impl Keyed for TranscodeJob {
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn key_kind(field: &TranscodeJobOrdering) -> ValueKind {
+        match field {
+            TranscodeJobOrdering::VideoId | TranscodeJobOrdering::AudioIndex => ValueKind::Integer,
+            TranscodeJobOrdering::Kind => ValueKind::Enum("transcode_job_kind"),
+            TranscodeJobOrdering::Rendition => ValueKind::Text,
+            TranscodeJobOrdering::Status => ValueKind::Enum("transcode_job_status"),
+            TranscodeJobOrdering::Progress => ValueKind::Float,
+            TranscodeJobOrdering::StartedAt
+            | TranscodeJobOrdering::FinishedAt
+            | TranscodeJobOrdering::CreatedAt
+            | TranscodeJobOrdering::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+
+    fn key(&self, field: &TranscodeJobOrdering) -> Option<FilterValue> {
+        match field {
+            TranscodeJobOrdering::VideoId => Some(FilterValue::Integer(self.video_id)),
+            TranscodeJobOrdering::Kind => {
+                Some(FilterValue::from_enum("transcode_job_kind", self.kind))
+            }
+            TranscodeJobOrdering::Rendition => self.rendition.clone().map(FilterValue::Text),
+            TranscodeJobOrdering::AudioIndex => self
+                .audio_index
+                .map(|index| FilterValue::Integer(index.into())),
+            TranscodeJobOrdering::Status => {
+                Some(FilterValue::from_enum("transcode_job_status", self.status))
+            }
+            TranscodeJobOrdering::Progress => Some(FilterValue::Float(self.progress)),
+            TranscodeJobOrdering::StartedAt => self.started_at.map(FilterValue::Timestamp),
+            TranscodeJobOrdering::FinishedAt => self.finished_at.map(FilterValue::Timestamp),
+            TranscodeJobOrdering::CreatedAt => Some(FilterValue::Timestamp(self.created_at)),
+            TranscodeJobOrdering::UpdatedAt => Some(FilterValue::Timestamp(self.updated_at)),
+        }
+    }
+}
+
+impl TranscodeJob {
+    /// Claims the oldest queued job and marks it running.
+    /// Jobs locked by other workers are skipped, so every job is claimed exactly once.
+    #[instrument(skip(pool))]
+    pub async fn claim_next(pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE transcode_job SET status = 'running', progress = 0, started_at = NOW()
+                WHERE id = (
+                    SELECT id FROM transcode_job
+                    WHERE status = 'queued'
+                    ORDER BY created_at, id
+                    FOR UPDATE SKIP LOCKED
+                    LIMIT 1
+                )
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+
+    /// Updates the progress of a running job, returns the current status, which tells the worker whether it was cancelled.
+    #[instrument(skip(pool))]
+    pub async fn report_progress(
+        pool: &PgPool,
+        id: EntityId,
+        progress: Progress,
+    ) -> Result<Option<TranscodeJobStatus>, sqlx::Error> {
+        let status = sqlx::query_scalar!(
+            r#"
+                UPDATE transcode_job SET
+                    progress = CASE WHEN status = 'running' THEN $1 ELSE progress END
+                WHERE id = $2
+                RETURNING status AS "status: TranscodeJobStatus"
+            "#,
+            progress,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(status)
+    }
+
+    /// Finishes a running job, a job cancelled in the meantime is left as is.
+    #[instrument(skip(pool))]
+    pub async fn finish(
+        pool: &PgPool,
+        id: EntityId,
+        status: TranscodeJobStatus,
+        error: Option<String>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE transcode_job SET
+                    status = $1, error = $2, finished_at = NOW(),
+                    progress = CASE WHEN $1 = 'completed'::transcode_job_status THEN 1 ELSE progress END
+                WHERE id = $3 AND status = 'running'
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#,
+            status as _,
+            error,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+
+    /// Cancels a queued or running job, returns `None` if there is no such job.
+    #[instrument(skip(pool))]
+    pub async fn cancel(pool: &PgPool, id: EntityId) -> Result<Option<Self>, sqlx::Error> {
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE transcode_job SET status = 'cancelled', finished_at = NOW()
+                WHERE id = $1 AND status IN ('queued', 'running')
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(job)
+    }
+
+    /// Finds the latest job doing the same work as the request, creating the job if there is none or the latest one
+    /// completed or failed, so failed work is retried. A cancelled job is returned, an admin stopped that work.
+    /// The video is locked meanwhile, so concurrent requests do not queue the same work twice.
+    /// Returns whether the job was created.
+    #[instrument(skip(pool))]
+    pub async fn find_or_create(
+        pool: &PgPool,
+        request: CreateTranscodeJobRequest,
+    ) -> Result<(Self, bool), sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            "SELECT id FROM video WHERE id = $1 FOR UPDATE",
+            request.video_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let latest = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+                FROM transcode_job
+                WHERE video_id = $1 AND kind = $2
+                    AND rendition IS NOT DISTINCT FROM $3 AND audio_index IS NOT DISTINCT FROM $4
+                ORDER BY created_at DESC, id DESC
+                LIMIT 1
+            "#,
+            request.video_id,
+            request.kind as _,
+            request.rendition,
+            request.audio_index
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let finished = [TranscodeJobStatus::Completed, TranscodeJobStatus::Failed];
+        if let Some(job) = latest.filter(|job| !finished.contains(&job.status)) {
+            transaction.commit().await?;
+            return Ok((job, false));
+        }
+
+        let job = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO transcode_job ( video_id, kind, rendition, audio_index, request_id )
+                VALUES ( $1, $2, $3, $4, $5 )
+                RETURNING
+                    id, video_id, kind AS "kind: _", rendition, audio_index, status AS "status: _",
+                    progress, error, request_id, started_at, finished_at, created_at, updated_at
+            "#,
+            request.video_id,
+            request.kind as _,
+            request.rendition,
+            request.audio_index,
+            request.request_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok((job, true))
+    }
+
+    /// Queues the jobs left running by a previous run of the server again.
+    #[instrument(skip(pool))]
+    pub async fn requeue_running(pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE transcode_job SET status = 'queued', progress = 0, started_at = NULL WHERE status = 'running'"
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,295 @@
+use async_trait::async_trait;
+#[cfg(test)]
+use fake::Dummy;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::model::{EntityId, User, UserRole};
+
+use super::{
+    build_find_all_query, Column, Counted, Entity, Filter, FilterField, FilterValue, Keyed,
+    OrderBy, Pagination, ValueKind,
+};
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password_hash: String,
+    pub role: UserRole,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum UserOrdering {
+    Username,
+    Role,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for UserOrdering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Username => "username",
+            Self::Role => "role",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum UserFiltering {
+    Id,
+    Username,
+    Role,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for UserFiltering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Username => "username",
+            Self::Role => "role",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+impl FilterField for UserFiltering {
+    fn kind(&self) -> ValueKind {
+        match self {
+            Self::Id => ValueKind::Integer,
+            Self::Username => ValueKind::Text,
+            Self::Role => ValueKind::Enum("user_role"),
+            Self::CreatedAt | Self::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct UpdateUserRequest {
+    pub id: EntityId,
+    pub username: String,
+    pub password_hash: String,
+    pub role: UserRole,
+}
+
+#[async_trait]
+impl Entity<Self> for User {
+    type CreateRequest = CreateUserRequest;
+    type Filtering = UserFiltering;
+
+    type Ordering = UserOrdering;
+
+    type UpdateRequest = UpdateUserRequest;
+    type UpdateError = sqlx::Error;
+
+    #[instrument(skip(pool))]
+    async fn create(pool: &PgPool, request: CreateUserRequest) -> Result<Self, sqlx::Error> {
+        let user = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO "user" ( username, password_hash, role )
+                VALUES ( $1, $2, $3 )
+                RETURNING id, username, password_hash, role AS "role: _", created_at, updated_at
+            "#,
+            request.username,
+            request.password_hash,
+            request.role as _
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(user)
+    }
+
+    #[instrument(skip(pool))]
+    async fn create_many(
+        pool: &PgPool,
+        requests: Vec<CreateUserRequest>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let mut usernames = vec![];
+        let mut password_hashes = vec![];
+        let mut roles = vec![];
+
+        for item in requests {
+            usernames.push(item.username);
+            password_hashes.push(item.password_hash);
+            roles.push(item.role);
+        }
+
+        let users = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO "user" ( username, password_hash, role )
+                SELECT * FROM UNNEST( $1::text[], $2::text[], $3::user_role[] )
+                RETURNING id, username, password_hash, role AS "role: _", created_at, updated_at
+            "#,
+            &usernames[..],
+            &password_hashes[..],
+            &roles[..] as &[UserRole]
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(users)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find(pool: &PgPool, id: EntityId) -> Result<Option<Self>, sqlx::Error> {
+        let user = sqlx::query_as!(Self, r#"
+                SELECT id, username, password_hash, role AS "role: _", created_at, updated_at FROM "user" WHERE id = $1
+            "#, id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(user)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all(
+        pool: &PgPool,
+        filters: Vec<Filter<UserFiltering>>,
+        ordering: Vec<OrderBy<UserOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query = build_find_all_query(r#""user""#, filters, ordering, pagination);
+
+        let users = query.fetch_all(pool).await?;
+
+        Ok(users)
+    }
+
+    #[instrument(skip(pool))]
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<UserFiltering>>,
+        ordering: Vec<OrderBy<UserOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query(r#""user""#, filters, ordering, pagination);
+
+        let users = query.fetch_counted(pool).await?;
+
+        Ok(users)
+    }
+
+    #[instrument(skip(pool))]
+    async fn update(
+        pool: &PgPool,
+        request: UpdateUserRequest,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            Self,
+            r#"
+                UPDATE "user" SET username = $1, password_hash = $2, role = $3
+                WHERE id = $4
+                RETURNING id, username, password_hash, role AS "role: _", created_at, updated_at
+            "#,
+            request.username,
+            request.password_hash,
+            request.role as _,
+            request.id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(r#"DELETE FROM "user" WHERE id = $1"#, id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(skip(pool))]
+    async fn delete_many(pool: &PgPool, ids: Vec<EntityId>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(r#"DELETE FROM "user" WHERE id = ANY($1)"#, &ids[..])
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(skip(pool))]
+    async fn count_all(pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM "user""#)
+            .fetch_one(pool)
+            .await?;
+        Ok(count)
+    }
+}
+
+impl Keyed for User {
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn key_kind(field: &UserOrdering) -> ValueKind {
+        match field {
+            UserOrdering::Username => ValueKind::Text,
+            UserOrdering::Role => ValueKind::Enum("user_role"),
+            UserOrdering::CreatedAt | UserOrdering::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+
+    fn key(&self, field: &UserOrdering) -> Option<FilterValue> {
+        match field {
+            UserOrdering::Username => Some(FilterValue::Text(self.username.clone())),
+            UserOrdering::Role => Some(FilterValue::from_enum("user_role", self.role)),
+            UserOrdering::CreatedAt => Some(FilterValue::Timestamp(self.created_at)),
+            UserOrdering::UpdatedAt => Some(FilterValue::Timestamp(self.updated_at)),
+        }
+    }
+}
+
+impl User {
+    #[instrument(skip(pool))]
+    pub async fn find_by_username(
+        pool: &PgPool,
+        username: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT id, username, password_hash, role AS "role: _", created_at, updated_at
+                FROM "user" WHERE username = $1
+            "#,
+            username
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// Finds the owner of an unexpired session.
+    #[instrument(skip_all)]
+    pub async fn find_by_session(
+        pool: &PgPool,
+        token_hash: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT
+                    "user".id, username, password_hash, role AS "role: _",
+                    "user".created_at, "user".updated_at
+                FROM "user"
+                JOIN session ON session.user_id = "user".id
+                WHERE session.token_hash = $1 AND session.expires_at > NOW()
+            "#,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+}
@@ -1,14 +1,21 @@
+use std::{error::Error, fmt};
+
 use async_trait::async_trait;
 #[cfg(test)]
 use fake::Dummy;
-use sqlx::PgPool;
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use time::OffsetDateTime;
 use tracing::instrument;
 
 use crate::model::{
     Bytes, BytesPerSecond, EntityId, FramesPerSecond, ScreenHeight, ScreenWidth, Seconds, Video,
 };
 
-use super::{build_find_all_query, Entity, OrderBy, Pagination};
+use super::{
+    build_find_all_query, build_find_all_query_where, Column, Counted, Entity, Filter, FilterField,
+    FilterValue, Keyed, OrderBy, Pagination, ValueKind,
+};
 
 #[derive(Debug)]
 #[cfg_attr(test, derive(Dummy))]
@@ -28,8 +35,9 @@ pub struct CreateVideoRequest {
     pub framerate: FramesPerSecond,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Deserialize)]
 #[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
 pub enum VideoOrdering {
     Path,
     DisplayName,
@@ -47,6 +55,88 @@ pub enum VideoOrdering {
     UpdatedAt,
 }
 
+impl Column for VideoOrdering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::DisplayName => "display_name",
+            Self::ShortDesc => "short_desc",
+            Self::LongDesc => "long_desc",
+            Self::CatalogId => "catalog_id",
+            Self::SequentId => "sequent_id",
+            Self::Size => "size",
+            Self::Duration => "duration",
+            Self::Bitrate => "bitrate",
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::Framerate => "framerate",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "snake_case")]
+pub enum VideoFiltering {
+    Id,
+    Path,
+    DisplayName,
+    ShortDesc,
+    LongDesc,
+    CatalogId,
+    SequentId,
+    Size,
+    Duration,
+    Bitrate,
+    Width,
+    Height,
+    Framerate,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Column for VideoFiltering {
+    fn column(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Path => "path",
+            Self::DisplayName => "display_name",
+            Self::ShortDesc => "short_desc",
+            Self::LongDesc => "long_desc",
+            Self::CatalogId => "catalog_id",
+            Self::SequentId => "sequent_id",
+            Self::Size => "size",
+            Self::Duration => "duration",
+            Self::Bitrate => "bitrate",
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::Framerate => "framerate",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+impl FilterField for VideoFiltering {
+    fn kind(&self) -> ValueKind {
+        match self {
+            Self::Id
+            | Self::CatalogId
+            | Self::SequentId
+            | Self::Size
+            | Self::Duration
+            | Self::Bitrate
+            | Self::Width
+            | Self::Height => ValueKind::Integer,
+            Self::Path | Self::DisplayName | Self::ShortDesc | Self::LongDesc => ValueKind::Text,
+            Self::Framerate => ValueKind::Float,
+            Self::CreatedAt | Self::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(test, derive(Dummy))]
 pub struct UpdateVideoRequest {
@@ -64,15 +154,64 @@ pub struct UpdateVideoRequest {
     pub width: ScreenWidth,
     pub height: ScreenHeight,
     pub framerate: FramesPerSecond,
+    /// Only updates the video while it is still at this revision, or at any revision when it is `None`.
+    pub expected_updated_at: Option<Vec<OffsetDateTime>>,
+}
+
+/// The constraint reported when a video would become the sequent of a second video.
+pub const UNIQUE_SEQUENT_CONSTRAINT: &str = "video_sequent_id_key";
+
+/// The advisory lock held while the sequent of a video is set, so the loop checks of concurrent updates
+/// see the series changed by each other.
+const SERIES_LOCK: i64 = 0x0073_6571_7565_6e74;
+
+/// The reasons a video cannot be updated.
+#[derive(Debug)]
+pub enum UpdateVideoError {
+    /// The video would follow itself, directly or through the sequents of its sequent.
+    SeriesLoop {
+        id: EntityId,
+        sequent_id: EntityId,
+    },
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for UpdateVideoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SeriesLoop { id, sequent_id } => write!(
+                f,
+                "video {sequent_id} cannot follow video {id}, the series would loop back to it"
+            ),
+            Self::Database(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for UpdateVideoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::SeriesLoop { .. } => None,
+            Self::Database(e) => Some(e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for UpdateVideoError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Database(error)
+    }
 }
 
 #[async_trait]
 impl Entity<Self> for Video {
     type CreateRequest = CreateVideoRequest;
+    type Filtering = VideoFiltering;
 
     type Ordering = VideoOrdering;
 
     type UpdateRequest = UpdateVideoRequest;
+    type UpdateError = UpdateVideoError;
 
     #[instrument(skip(pool))]
     async fn create(pool: &PgPool, request: CreateVideoRequest) -> Result<Self, sqlx::Error> {
@@ -114,6 +253,7 @@ impl Entity<Self> for Video {
         let mut short_descs = vec![];
         let mut long_descs = vec![];
         let mut catalog_ids = vec![];
+        let mut sequent_ids = vec![];
         let mut sizes = vec![];
         let mut durations = vec![];
         let mut bitrates = vec![];
@@ -127,6 +267,7 @@ impl Entity<Self> for Video {
             short_descs.push(item.short_desc.clone());
             long_descs.push(item.long_desc.clone());
             catalog_ids.push(item.catalog_id);
+            sequent_ids.push(item.sequent_id);
             sizes.push(item.size);
             durations.push(item.duration);
             bitrates.push(item.bitrate);
@@ -139,12 +280,12 @@ impl Entity<Self> for Video {
             Self,
             r#"
                 INSERT INTO video ( 
-                    path, display_name, short_desc, long_desc, catalog_id, size, 
-                    duration, bitrate, width, height, framerate 
+                    path, display_name, short_desc, long_desc, catalog_id, sequent_id, 
+                    size, duration, bitrate, width, height, framerate 
                 ) 
                 SELECT * FROM UNNEST(
                     $1::text[], $2::text[], $3::text[], $4::text[], $5::int8[], $6::int8[], 
-                    $7::int8[], $8::int8[], $9::int2[], $10::int2[], $11::float8[]
+                    $7::int8[], $8::int8[], $9::int8[], $10::int2[], $11::int2[], $12::float8[]
                 )
                 RETURNING *
             "#,
@@ -153,6 +294,7 @@ impl Entity<Self> for Video {
             &short_descs[..],
             &long_descs[..],
             &catalog_ids[..],
+            &sequent_ids[..] as &[Option<EntityId>],
             &sizes[..],
             &durations[..],
             &bitrates[..],
@@ -177,54 +319,85 @@ impl Entity<Self> for Video {
     #[instrument(skip(pool))]
     async fn find_all(
         pool: &PgPool,
+        filters: Vec<Filter<VideoFiltering>>,
         ordering: Vec<OrderBy<VideoOrdering>>,
         pagination: Option<Pagination>,
     ) -> Result<Vec<Self>, sqlx::Error> {
-        let query = build_find_all_query("video", ordering, pagination);
+        let query = build_find_all_query("video", filters, ordering, pagination);
+
+        let videos = query.fetch_all(pool).await?;
+
+        Ok(videos)
+    }
 
-        let videos = sqlx::query_as(&query).fetch_all(pool).await?;
+    #[instrument(skip(pool))]
+    async fn find_all_counted(
+        pool: &PgPool,
+        filters: Vec<Filter<VideoFiltering>>,
+        ordering: Vec<OrderBy<VideoOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query("video", filters, ordering, pagination);
+
+        let videos = query.fetch_counted(pool).await?;
 
         Ok(videos)
     }
 
+    /// Fails with [`UpdateVideoError::SeriesLoop`] if the video would follow itself through its sequents.
     #[instrument(skip(pool))]
     async fn update(
         pool: &PgPool,
         request: UpdateVideoRequest,
-    ) -> Result<Option<Self>, sqlx::Error> {
+    ) -> Result<Option<Self>, UpdateVideoError> {
+        let mut transaction = pool.begin().await?;
+        if let Some(sequent_id) = request.sequent_id {
+            // held until the commit, a concurrent update could close the loop after the check otherwise
+            sqlx::query("SELECT pg_advisory_xact_lock($1)")
+                .bind(SERIES_LOCK)
+                .execute(&mut *transaction)
+                .await?;
+            if Self::leads_to(&mut transaction, sequent_id, request.id).await? {
+                return Err(UpdateVideoError::SeriesLoop {
+                    id: request.id,
+                    sequent_id,
+                });
+            }
+        }
         let video = sqlx::query_as!(
-        Self,
-        r#"
+            Self,
+            r#"
             UPDATE video SET 
-                display_name = $1, short_desc = $2, long_desc = $3, catalog_id = $4, sequent_id = $5, 
-                size = $6, duration = $7, bitrate = $8, width = $9, height = $10, framerate = $11
-            WHERE id = $12
+                path = $1, display_name = $2, short_desc = $3, long_desc = $4, catalog_id = $5, 
+                sequent_id = $6, size = $7, duration = $8, bitrate = $9, width = $10, height = $11, 
+                framerate = $12
+            WHERE id = $13 AND ($14::TIMESTAMPTZ[] IS NULL OR updated_at = ANY($14))
             RETURNING *
         "#,
-        request.display_name,
-        request.short_desc,
-        request.long_desc,
-        request.catalog_id,
-        request.sequent_id,
-        request.size,
-        request.duration,
-        request.bitrate,
-        request.width,
-        request.height,
-        request.framerate,
-        request.id
-    )
-    .fetch_optional(pool)
-    .await?;
+            request.path,
+            request.display_name,
+            request.short_desc,
+            request.long_desc,
+            request.catalog_id,
+            request.sequent_id,
+            request.size,
+            request.duration,
+            request.bitrate,
+            request.width,
+            request.height,
+            request.framerate,
+            request.id,
+            request.expected_updated_at.as_deref()
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
         Ok(video)
     }
 
     #[instrument(skip(pool))]
     async fn delete(pool: &PgPool, id: EntityId) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query!("DELETE FROM video WHERE id = $1", id)
-            .execute(pool)
-            .await?;
-        Ok(result.rows_affected() == 1)
+        Self::delete_unless_changed(pool, id, None).await
     }
 
     #[instrument(skip(pool))]
@@ -243,3 +416,316 @@ impl Entity<Self> for Video {
         Ok(count)
     }
 }
+
+impl Keyed for Video {
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn key_kind(field: &VideoOrdering) -> ValueKind {
+        match field {
+            VideoOrdering::Path
+            | VideoOrdering::DisplayName
+            | VideoOrdering::ShortDesc
+            | VideoOrdering::LongDesc => ValueKind::Text,
+            VideoOrdering::CatalogId
+            | VideoOrdering::SequentId
+            | VideoOrdering::Size
+            | VideoOrdering::Duration
+            | VideoOrdering::Bitrate
+            | VideoOrdering::Width
+            | VideoOrdering::Height => ValueKind::Integer,
+            VideoOrdering::Framerate => ValueKind::Float,
+            VideoOrdering::CreatedAt | VideoOrdering::UpdatedAt => ValueKind::Timestamp,
+        }
+    }
+
+    fn key(&self, field: &VideoOrdering) -> Option<FilterValue> {
+        match field {
+            VideoOrdering::Path => Some(FilterValue::Text(self.path.clone())),
+            VideoOrdering::DisplayName => Some(FilterValue::Text(self.display_name.clone())),
+            VideoOrdering::ShortDesc => Some(FilterValue::Text(self.short_desc.clone())),
+            VideoOrdering::LongDesc => Some(FilterValue::Text(self.long_desc.clone())),
+            VideoOrdering::CatalogId => Some(FilterValue::Integer(self.catalog_id)),
+            VideoOrdering::SequentId => self.sequent_id.map(FilterValue::Integer),
+            VideoOrdering::Size => Some(FilterValue::Integer(self.size)),
+            VideoOrdering::Duration => Some(FilterValue::Integer(self.duration)),
+            VideoOrdering::Bitrate => Some(FilterValue::Integer(self.bitrate)),
+            VideoOrdering::Width => Some(FilterValue::Integer(self.width.into())),
+            VideoOrdering::Height => Some(FilterValue::Integer(self.height.into())),
+            VideoOrdering::Framerate => Some(FilterValue::Float(self.framerate)),
+            VideoOrdering::CreatedAt => Some(FilterValue::Timestamp(self.created_at)),
+            VideoOrdering::UpdatedAt => Some(FilterValue::Timestamp(self.updated_at)),
+        }
+    }
+}
+
+impl Video {
+    /// Whether `target` is reached by following the sequents from `id`, including `id` itself.
+    async fn leads_to(
+        connection: &mut PgConnection,
+        id: EntityId,
+        target: EntityId,
+    ) -> Result<bool, sqlx::Error> {
+        let leads_to = sqlx::query_scalar!(
+            r#"
+                WITH RECURSIVE sequents ( id, sequent_id, path ) AS (
+                    SELECT id, sequent_id, ARRAY[id] FROM video WHERE id = $1
+                    UNION ALL
+                    SELECT video.id, video.sequent_id, sequents.path || video.id
+                    FROM video JOIN sequents ON video.id = sequents.sequent_id
+                    WHERE video.id <> ALL(sequents.path)
+                )
+                SELECT EXISTS ( SELECT 1 FROM sequents WHERE id = $2 ) AS "exists!"
+            "#,
+            id,
+            target
+        )
+        .fetch_one(connection)
+        .await?;
+        Ok(leads_to)
+    }
+
+    /// Deletes the video while it is still at one of the expected revisions, or at any revision without them.
+    #[instrument(skip(pool))]
+    pub async fn delete_unless_changed(
+        pool: &PgPool,
+        id: EntityId,
+        expected_updated_at: Option<&[OffsetDateTime]>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM video WHERE id = $1 AND ($2::TIMESTAMPTZ[] IS NULL OR updated_at = ANY($2))",
+            id,
+            expected_updated_at
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Lists the series of a video in order, from the first video through the ones it is the sequent of,
+    /// to the last one following it. Links looping back are not followed.
+    #[instrument(skip(pool))]
+    pub async fn find_series(pool: &PgPool, id: EntityId) -> Result<Vec<Self>, sqlx::Error> {
+        let videos = sqlx::query_as!(
+            Self,
+            r#"
+                WITH RECURSIVE
+                    previous ( id, position, path ) AS (
+                        SELECT id, 0, ARRAY[id] FROM video WHERE id = $1
+                        UNION ALL
+                        SELECT video.id, previous.position - 1, previous.path || video.id
+                        FROM video JOIN previous ON video.sequent_id = previous.id
+                        WHERE video.id <> ALL(previous.path)
+                    ),
+                    next ( id, sequent_id, position, path ) AS (
+                        SELECT id, sequent_id, 0, ARRAY[id] FROM video WHERE id = $1
+                        UNION ALL
+                        SELECT video.id, video.sequent_id, next.position + 1, next.path || video.id
+                        FROM video JOIN next ON video.id = next.sequent_id
+                        WHERE video.id <> ALL(next.path)
+                    ),
+                    series ( id, position ) AS (
+                        SELECT id, position FROM previous
+                        UNION ALL
+                        SELECT id, position FROM next
+                        WHERE position > 0 AND id NOT IN ( SELECT id FROM previous )
+                    )
+                SELECT video.* FROM series JOIN video ON video.id = series.id
+                ORDER BY series.position
+            "#,
+            id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(videos)
+    }
+
+    /// Finds the video this one is the sequent of.
+    #[instrument(skip(pool))]
+    pub async fn find_previous(pool: &PgPool, id: EntityId) -> Result<Option<Self>, sqlx::Error> {
+        let video = sqlx::query_as!(Self, "SELECT * FROM video WHERE sequent_id = $1", id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(video)
+    }
+
+    /// Lists the videos of a catalog, counting them on every page.
+    #[instrument(skip(pool))]
+    pub async fn find_all_by_catalog(
+        pool: &PgPool,
+        catalog_id: EntityId,
+        filters: Vec<Filter<VideoFiltering>>,
+        ordering: Vec<OrderBy<VideoOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query_where(
+            "video",
+            Some(("catalog_id = $1", catalog_id)),
+            filters,
+            ordering,
+            pagination,
+        );
+
+        let videos = query.fetch_counted(pool).await?;
+
+        Ok(videos)
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn find_all_by_ids(
+        pool: &PgPool,
+        ids: &[EntityId],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let videos = sqlx::query_as!(Self, "SELECT * FROM video WHERE id = ANY($1)", ids)
+            .fetch_all(pool)
+            .await?;
+        Ok(videos)
+    }
+
+    /// Lists the videos of the catalogs granted to a user, counting them on every page.
+    #[instrument(skip(pool))]
+    pub async fn find_all_granted(
+        pool: &PgPool,
+        user_id: EntityId,
+        filters: Vec<Filter<VideoFiltering>>,
+        ordering: Vec<OrderBy<VideoOrdering>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Counted<Self>, sqlx::Error> {
+        let query = build_find_all_query_where(
+            "video",
+            Some((
+                "catalog_id IN (SELECT catalog_id FROM catalog_grant WHERE user_id = $1)",
+                user_id,
+            )),
+            filters,
+            ordering,
+            pagination,
+        );
+
+        let videos = query.fetch_counted(pool).await?;
+
+        Ok(videos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crud::catalog::CreateCatalogRequest, model::Catalog};
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    /// Creates videos without sequents in a new catalog.
+    async fn videos(pool: &PgPool, count: usize) -> Vec<Video> {
+        let catalog = Catalog::create(pool, Faker.fake::<CreateCatalogRequest>())
+            .await
+            .unwrap();
+        let mut videos = vec![];
+        for _ in 0..count {
+            let request = CreateVideoRequest {
+                catalog_id: catalog.id,
+                sequent_id: None,
+                ..Faker.fake()
+            };
+            videos.push(Video::create(pool, request).await.unwrap());
+        }
+        videos
+    }
+
+    /// Makes a video followed by the sequent, keeping its other fields.
+    async fn link(
+        pool: &PgPool,
+        video: &Video,
+        sequent_id: EntityId,
+    ) -> Result<Option<Video>, UpdateVideoError> {
+        let request = UpdateVideoRequest {
+            id: video.id,
+            path: video.path.clone(),
+            display_name: video.display_name.clone(),
+            short_desc: video.short_desc.clone(),
+            long_desc: video.long_desc.clone(),
+            catalog_id: video.catalog_id,
+            sequent_id: Some(sequent_id),
+            size: video.size,
+            duration: video.duration,
+            bitrate: video.bitrate,
+            width: video.width,
+            height: video.height,
+            framerate: video.framerate,
+            expected_updated_at: None,
+        };
+        Video::update(pool, request).await
+    }
+
+    fn ids(videos: &[Video]) -> Vec<EntityId> {
+        videos.iter().map(|video| video.id).collect()
+    }
+
+    #[sqlx::test]
+    async fn find_series_lists_series_in_order(pool: PgPool) {
+        let videos = videos(&pool, 5).await;
+        link(&pool, &videos[2], videos[3].id).await.unwrap();
+        link(&pool, &videos[0], videos[1].id).await.unwrap();
+        link(&pool, &videos[1], videos[2].id).await.unwrap();
+
+        let series = Video::find_series(&pool, videos[2].id).await.unwrap();
+        let first = Video::find_series(&pool, videos[0].id).await.unwrap();
+        let alone = Video::find_series(&pool, videos[4].id).await.unwrap();
+
+        assert_eq!(ids(&series), ids(&videos[..4]));
+        assert_eq!(ids(&first), ids(&videos[..4]));
+        assert_eq!(ids(&alone), vec![videos[4].id]);
+    }
+
+    #[sqlx::test]
+    async fn update_rejects_series_loops(pool: PgPool) {
+        let videos = videos(&pool, 3).await;
+        link(&pool, &videos[0], videos[1].id).await.unwrap();
+        link(&pool, &videos[1], videos[2].id).await.unwrap();
+
+        let looped = link(&pool, &videos[2], videos[0].id).await;
+        let itself = link(&pool, &videos[0], videos[0].id).await;
+
+        assert!(matches!(
+            looped,
+            Err(UpdateVideoError::SeriesLoop { id, sequent_id })
+                if id == videos[2].id && sequent_id == videos[0].id
+        ));
+        assert!(matches!(itself, Err(UpdateVideoError::SeriesLoop { .. })));
+        let last = Video::find(&pool, videos[2].id).await.unwrap().unwrap();
+        assert_eq!(last.sequent_id, None);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_updates_cannot_close_series_loops(pool: PgPool) {
+        for _ in 0..20 {
+            let videos = videos(&pool, 2).await;
+
+            let (forward, backward) = tokio::join!(
+                link(&pool, &videos[0], videos[1].id),
+                link(&pool, &videos[1], videos[0].id)
+            );
+
+            let rejected = [&forward, &backward]
+                .into_iter()
+                .filter(|result| matches!(result, Err(UpdateVideoError::SeriesLoop { .. })))
+                .count();
+            assert_eq!(rejected, 1, "{forward:?} {backward:?}");
+        }
+    }
+
+    #[sqlx::test]
+    async fn update_rejects_second_video_followed_by_sequent(pool: PgPool) {
+        let videos = videos(&pool, 3).await;
+        link(&pool, &videos[0], videos[2].id).await.unwrap();
+
+        let result = link(&pool, &videos[1], videos[2].id).await;
+
+        let Err(UpdateVideoError::Database(e)) = result else {
+            panic!("the sequent was linked twice: {result:?}");
+        };
+        let constraint = e.as_database_error().and_then(|e| e.constraint());
+        assert_eq!(constraint, Some(UNIQUE_SEQUENT_CONSTRAINT));
+    }
+}
@@ -0,0 +1,140 @@
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::model::{EntityId, Seconds, WatchProgress};
+
+impl WatchProgress {
+    #[instrument(skip(pool))]
+    pub async fn find(
+        pool: &PgPool,
+        user_id: EntityId,
+        video_id: EntityId,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let progress = sqlx::query_as!(
+            Self,
+            "SELECT * FROM watch_progress WHERE user_id = $1 AND video_id = $2",
+            user_id,
+            video_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(progress)
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn find_all_by_videos(
+        pool: &PgPool,
+        user_id: EntityId,
+        video_ids: &[EntityId],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let progress = sqlx::query_as!(
+            Self,
+            "SELECT * FROM watch_progress WHERE user_id = $1 AND video_id = ANY($2)",
+            user_id,
+            video_ids
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(progress)
+    }
+
+    /// Records where a user stopped watching a video. A video marked as watched stays watched.
+    #[instrument(skip(pool))]
+    pub async fn upsert(
+        pool: &PgPool,
+        user_id: EntityId,
+        video_id: EntityId,
+        position: Seconds,
+        watched: bool,
+    ) -> Result<Self, sqlx::Error> {
+        let progress = sqlx::query_as!(
+            Self,
+            r#"
+                INSERT INTO watch_progress ( user_id, video_id, position, watched )
+                VALUES ( $1, $2, $3, $4 )
+                ON CONFLICT ( user_id, video_id ) DO UPDATE SET
+                    position = EXCLUDED.position,
+                    watched = watch_progress.watched OR EXCLUDED.watched
+                RETURNING *
+            "#,
+            user_id,
+            video_id,
+            position,
+            watched
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(progress)
+    }
+
+    #[instrument(skip(pool))]
+    pub async fn delete(
+        pool: &PgPool,
+        user_id: EntityId,
+        video_id: EntityId,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM watch_progress WHERE user_id = $1 AND video_id = $2",
+            user_id,
+            video_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Lists the started but unwatched videos of a user, the most recently watched first.
+    /// With `granted_only` the videos of catalogs not granted to the user are left out.
+    #[instrument(skip(pool))]
+    pub async fn find_continue_watching(
+        pool: &PgPool,
+        user_id: EntityId,
+        granted_only: bool,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let progress = sqlx::query_as!(
+            Self,
+            r#"
+                SELECT watch_progress.* FROM watch_progress
+                JOIN video ON video.id = watch_progress.video_id
+                WHERE watch_progress.user_id = $1
+                    AND NOT watch_progress.watched
+                    AND watch_progress.position > 0
+                    AND (NOT $2 OR video.catalog_id IN (
+                        SELECT catalog_id FROM catalog_grant WHERE user_id = $1
+                    ))
+                ORDER BY watch_progress.updated_at DESC, watch_progress.video_id DESC
+                LIMIT $3
+            "#,
+            user_id,
+            granted_only,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(progress)
+    }
+}
+
+/// Whether a position is past the watched threshold, a percentage of the duration.
+pub fn is_watched(position: Seconds, duration: Seconds, threshold: u8) -> bool {
+    duration > 0 && position * 100 >= duration * Seconds::from(threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_watched_past_threshold() {
+        assert!(is_watched(90, 100, 90));
+        assert!(is_watched(95, 100, 90));
+        assert!(!is_watched(89, 100, 90));
+    }
+
+    #[test]
+    fn is_watched_without_duration() {
+        assert!(!is_watched(0, 0, 90));
+        assert!(!is_watched(10, 0, 0));
+    }
+}
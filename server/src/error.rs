@@ -0,0 +1,344 @@
+use std::{io, mem};
+
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::crud::{
+    video::{UpdateVideoError, UNIQUE_SEQUENT_CONSTRAINT},
+    PaginationError,
+};
+
+/// The errors of the API, every one of them is answered with an [`ErrorResponse`].
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    /// The payload of the request is malformed.
+    Validation(String),
+    /// Fields of the payload of the request are invalid.
+    InvalidFields(Vec<FieldError>),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound,
+    Conflict(String),
+    /// The request would duplicate a row that has to be unique.
+    UniqueViolation,
+    /// The request refers to a missing row, or removes a row others still refer to.
+    ForeignKeyViolation,
+    /// The resource changed since the revision the request expects.
+    PreconditionFailed,
+    /// The request body is larger than the body size limit.
+    PayloadTooLarge,
+    /// The server gave up on the request after the request timeout.
+    Timeout,
+    Database(sqlx::Error),
+    Io(io::Error),
+    Internal,
+    Panic,
+}
+
+/// Identifies the kind of an error for the clients, they stay the same across versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Validation,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    UniqueViolation,
+    ForeignKeyViolation,
+    PreconditionFailed,
+    PayloadTooLarge,
+    Timeout,
+    Database,
+    Io,
+    Internal,
+    Panic,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    code: ErrorCode,
+    /// Describes the error for humans, it may change any time.
+    message: String,
+    /// The invalid fields of the request, only present on validation errors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FieldError {
+    /// The path of the field, e.g. `display_name` or `[2].size` in a list.
+    pub field: String,
+    pub message: String,
+}
+
+impl ApiError {
+    /// Maps a missing file to [`ApiError::NotFound`], every other io error is unexpected.
+    pub fn from_io(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::NotFound {
+            Self::NotFound
+        } else {
+            Self::Io(error)
+        }
+    }
+
+    /// Maps the errors of the middlewares, only the timeout is expected.
+    pub async fn from_middleware(error: BoxError) -> Self {
+        if error.is::<tower::timeout::error::Elapsed>() {
+            Self::Timeout
+        } else {
+            tracing::error!("middleware error: {error}");
+            Self::Internal
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::BadRequest(_) => ErrorCode::BadRequest,
+            Self::Validation(_) | Self::InvalidFields(_) => ErrorCode::Validation,
+            Self::Unauthorized(_) => ErrorCode::Unauthorized,
+            Self::Forbidden(_) => ErrorCode::Forbidden,
+            Self::NotFound => ErrorCode::NotFound,
+            Self::Conflict(_) => ErrorCode::Conflict,
+            Self::UniqueViolation => ErrorCode::UniqueViolation,
+            Self::ForeignKeyViolation => ErrorCode::ForeignKeyViolation,
+            Self::PreconditionFailed => ErrorCode::PreconditionFailed,
+            Self::PayloadTooLarge => ErrorCode::PayloadTooLarge,
+            Self::Timeout => ErrorCode::Timeout,
+            Self::Database(_) => ErrorCode::Database,
+            Self::Io(_) => ErrorCode::Io,
+            Self::Internal => ErrorCode::Internal,
+            Self::Panic => ErrorCode::Panic,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Validation(_) | Self::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict(_) | Self::UniqueViolation | Self::ForeignKeyViolation => {
+                StatusCode::CONFLICT
+            }
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Timeout => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Database(_) | Self::Io(_) | Self::Internal | Self::Panic => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn into_body(mut self) -> ErrorBody {
+        let fields = match &mut self {
+            Self::InvalidFields(fields) => mem::take(fields),
+            _ => vec![],
+        };
+        ErrorBody {
+            code: self.code(),
+            message: self.message(),
+            fields,
+        }
+    }
+
+    fn message(self) -> String {
+        match self {
+            Self::BadRequest(message)
+            | Self::Validation(message)
+            | Self::Unauthorized(message)
+            | Self::Forbidden(message)
+            | Self::Conflict(message) => message,
+            Self::NotFound => "the requested resource does not exist".to_owned(),
+            Self::UniqueViolation => "the resource conflicts with an existing one".to_owned(),
+            Self::ForeignKeyViolation => {
+                "the resource refers to a missing one, or others still refer to it".to_owned()
+            }
+            Self::PreconditionFailed => {
+                "the resource changed since the revision the request expects".to_owned()
+            }
+            Self::PayloadTooLarge => "the request body is larger than allowed".to_owned(),
+            Self::Timeout => "the request took too long to handle".to_owned(),
+            Self::Database(e) => {
+                tracing::error!("database error: {e}");
+                "an unexpected database error occurred".to_owned()
+            }
+            Self::Io(e) => {
+                tracing::error!("io error: {e}");
+                "an unexpected io error occurred".to_owned()
+            }
+            Self::InvalidFields(_) => "the request has invalid fields".to_owned(),
+            Self::Internal => "an unexpected error occurred".to_owned(),
+            Self::Panic => "the service panicked while handling the request".to_owned(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(value: sqlx::Error) -> Self {
+        if let sqlx::Error::RowNotFound = value {
+            return Self::NotFound;
+        }
+        let Some(e) = value.as_database_error() else {
+            return Self::Database(value);
+        };
+        match e.constraint() {
+            Some(UNIQUE_SEQUENT_CONSTRAINT) => {
+                Self::Conflict("the sequent video already follows another video".to_owned())
+            }
+            _ if e.is_unique_violation() => Self::UniqueViolation,
+            _ if e.is_foreign_key_violation() => Self::ForeignKeyViolation,
+            _ => Self::Database(value),
+        }
+    }
+}
+
+impl From<UpdateVideoError> for ApiError {
+    fn from(error: UpdateVideoError) -> Self {
+        match error {
+            UpdateVideoError::SeriesLoop { .. } => Self::Conflict(error.to_string()),
+            UpdateVideoError::Database(e) => Self::from(e),
+        }
+    }
+}
+
+impl From<PaginationError> for ApiError {
+    fn from(error: PaginationError) -> Self {
+        Self::BadRequest(error.to_string())
+    }
+}
+
+/// A payload not matching its type is invalid, any other rejected payload is a bad request.
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(e) => Self::Validation(e.body_text()),
+            // the body is only known to be too large once it was read up to the limit
+            rejection if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                Self::PayloadTooLarge
+            }
+            rejection => Self::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+/// Path parameters not matching their type are a bad request, the others are mistakes of the routes.
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        match rejection {
+            PathRejection::FailedToDeserializePathParams(e) => Self::BadRequest(e.body_text()),
+            rejection => {
+                tracing::error!("path rejection: {}", rejection.body_text());
+                Self::Internal
+            }
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let body = ErrorResponse {
+            error: self.into_body(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crud::{catalog::CreateCatalogRequest, video::CreateVideoRequest, Entity},
+        model::{Catalog, Video},
+    };
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+    use sqlx::PgPool;
+
+    #[test]
+    fn row_not_found_is_not_found() {
+        let error = ApiError::from(sqlx::Error::RowNotFound);
+        assert_eq!(error.code(), ErrorCode::NotFound);
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn other_sqlx_errors_are_database_errors() {
+        let error = ApiError::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(error.code(), ErrorCode::Database);
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn series_loop_is_conflict() {
+        let error = ApiError::from(UpdateVideoError::SeriesLoop {
+            id: 1,
+            sequent_id: 2,
+        });
+        assert_eq!(error.code(), ErrorCode::Conflict);
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            error.message(),
+            "video 2 cannot follow video 1, the series would loop back to it"
+        );
+    }
+
+    #[sqlx::test]
+    async fn shared_sequent_is_conflict(pool: PgPool) {
+        let catalog = Catalog::create(&pool, Faker.fake::<CreateCatalogRequest>())
+            .await
+            .unwrap();
+        let video = |sequent_id| CreateVideoRequest {
+            catalog_id: catalog.id,
+            sequent_id,
+            ..Faker.fake()
+        };
+        let sequent = Video::create(&pool, video(None)).await.unwrap();
+        Video::create(&pool, video(Some(sequent.id))).await.unwrap();
+
+        let e = Video::create(&pool, video(Some(sequent.id)))
+            .await
+            .unwrap_err();
+
+        let error = ApiError::from(e);
+        assert_eq!(error.code(), ErrorCode::Conflict);
+        assert_eq!(
+            error.message(),
+            "the sequent video already follows another video"
+        );
+    }
+
+    #[tokio::test]
+    async fn elapsed_middleware_error_is_timeout() {
+        let error = ApiError::from_middleware(tower::timeout::error::Elapsed::new().into()).await;
+        assert_eq!(error.code(), ErrorCode::Timeout);
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn error_codes_serialized_in_snake_case() {
+        let code = serde_json::to_value(ErrorCode::ForeignKeyViolation).unwrap();
+        assert_eq!(code, "foreign_key_violation");
+    }
+}
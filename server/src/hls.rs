@@ -0,0 +1,225 @@
+use std::fmt::Write;
+
+use crate::model::Video;
+
+/// The target length of a segment in seconds.
+pub const SEGMENT_DURATION: i64 = 6;
+
+/// The bitrate of the stereo AAC audio in every rendition, in bits per second.
+pub const AUDIO_BITRATE: u32 = 128_000;
+
+/// The renditions offered at most, a source is never upscaled into a higher one.
+const LADDER: [(&str, i16, u32); 3] = [
+    ("1080p", 1080, 5_000_000),
+    ("720p", 720, 2_800_000),
+    ("480p", 480, 1_400_000),
+];
+
+/// A quality level of the adaptive stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rendition {
+    pub name: &'static str,
+    pub width: i16,
+    pub height: i16,
+    /// The target video bitrate in bits per second.
+    pub video_bitrate: u32,
+}
+
+impl Rendition {
+    /// The peak bandwidth advertised in the master playlist, in bits per second.
+    pub fn bandwidth(&self) -> u32 {
+        self.video_bitrate + AUDIO_BITRATE
+    }
+}
+
+/// Chooses the renditions of a video from its stored dimensions and bitrate.
+/// The source bitrate caps every rendition, as re-encoding cannot add quality, only bandwidth.
+/// A source smaller than every rendition, or one without known dimensions, gets the lowest rendition, scaled to at most the source size.
+pub fn renditions(video: &Video) -> Vec<Rendition> {
+    let source_bitrate = u32::try_from(video.bitrate.saturating_mul(8)).unwrap_or(u32::MAX);
+    let mut renditions = LADDER
+        .iter()
+        .filter(|(_, height, _)| *height <= video.height)
+        .map(|(name, height, bitrate)| rendition(video, name, *height, *bitrate, source_bitrate))
+        .collect::<Vec<_>>();
+    if renditions.is_empty() {
+        let (name, height, bitrate) = LADDER[LADDER.len() - 1];
+        let height = if video.height > 0 {
+            height.min(video.height)
+        } else {
+            height
+        };
+        renditions.push(rendition(video, name, height, bitrate, source_bitrate));
+    }
+    renditions
+}
+
+fn rendition(
+    video: &Video,
+    name: &'static str,
+    height: i16,
+    bitrate: u32,
+    source_bitrate: u32,
+) -> Rendition {
+    let aspect_ratio = if video.width > 0 && video.height > 0 {
+        f64::from(video.width) / f64::from(video.height)
+    } else {
+        16.0 / 9.0
+    };
+    // rounded to an even number, as required by most encoders
+    let width = (f64::from(height) * aspect_ratio / 2.0).round() as i16 * 2;
+    Rendition {
+        name,
+        width,
+        height,
+        video_bitrate: if source_bitrate > 0 {
+            bitrate.min(source_bitrate)
+        } else {
+            bitrate
+        },
+    }
+}
+
+/// The number of segments a video of the given duration is split into.
+pub fn segment_count(duration: i64) -> i64 {
+    (duration + SEGMENT_DURATION - 1) / SEGMENT_DURATION
+}
+
+/// The start and the length of a segment in seconds, `None` if the segment is out of range.
+pub fn segment_bounds(duration: i64, index: i64) -> Option<(i64, i64)> {
+    if index < 0 || index >= segment_count(duration) {
+        return None;
+    }
+    let start = index * SEGMENT_DURATION;
+    Some((start, SEGMENT_DURATION.min(duration - start)))
+}
+
+pub fn master_playlist(renditions: &[Rendition]) -> String {
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    for rendition in renditions {
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{},NAME=\"{}\"\n{}/index.m3u8",
+            rendition.bandwidth(),
+            rendition.width,
+            rendition.height,
+            rendition.name,
+            rendition.name
+        );
+    }
+    playlist
+}
+
+/// The media playlist of a rendition, listing every segment up front, as the duration is known.
+pub fn rendition_playlist(duration: i64) -> String {
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{SEGMENT_DURATION}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n"
+    );
+    let mut index = 0;
+    while let Some((_, length)) = segment_bounds(duration, index) {
+        let _ = writeln!(playlist, "#EXTINF:{length}.000,\n{index}.ts");
+        index += 1;
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    fn video(width: i16, height: i16, bitrate: i64) -> Video {
+        Video {
+            width,
+            height,
+            bitrate,
+            ..Faker.fake()
+        }
+    }
+
+    #[test]
+    fn renditions_4k_source_full_ladder() {
+        let renditions = renditions(&video(3840, 2160, 5_000_000));
+        assert_eq!(
+            renditions
+                .iter()
+                .map(|rendition| (rendition.name, rendition.width, rendition.height))
+                .collect::<Vec<_>>(),
+            vec![
+                ("1080p", 1920, 1080),
+                ("720p", 1280, 720),
+                ("480p", 854, 480)
+            ]
+        );
+    }
+
+    #[test]
+    fn renditions_never_upscale() {
+        let renditions = renditions(&video(1280, 720, 500_000));
+        assert_eq!(
+            renditions
+                .iter()
+                .map(|rendition| rendition.name)
+                .collect::<Vec<_>>(),
+            vec!["720p", "480p"]
+        );
+    }
+
+    #[test]
+    fn renditions_capped_by_source_bitrate() {
+        let renditions = renditions(&video(1920, 1080, 250_000));
+        assert!(renditions
+            .iter()
+            .all(|rendition| rendition.video_bitrate <= 2_000_000));
+        assert_eq!(renditions[2].video_bitrate, 1_400_000);
+    }
+
+    #[test]
+    fn renditions_small_or_unknown_source_single_rendition() {
+        let small = renditions(&video(640, 360, 0));
+        assert_eq!(
+            small,
+            vec![Rendition {
+                name: "480p",
+                width: 640,
+                height: 360,
+                video_bitrate: 1_400_000
+            }]
+        );
+        let unknown = renditions(&video(0, 0, 0));
+        assert_eq!(unknown.len(), 1);
+        assert_eq!((unknown[0].width, unknown[0].height), (854, 480));
+    }
+
+    #[test]
+    fn segment_bounds_last_segment_shorter() {
+        assert_eq!(segment_count(20), 4);
+        assert_eq!(segment_bounds(20, 0), Some((0, 6)));
+        assert_eq!(segment_bounds(20, 3), Some((18, 2)));
+        assert_eq!(segment_bounds(20, 4), None);
+        assert_eq!(segment_bounds(20, -1), None);
+    }
+
+    #[test]
+    fn master_playlist_lists_renditions() {
+        let playlist = master_playlist(&renditions(&video(1280, 720, 0)));
+        assert_eq!(
+            playlist,
+            "#EXTM3U\n#EXT-X-VERSION:3\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2928000,RESOLUTION=1280x720,NAME=\"720p\"\n720p/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=1528000,RESOLUTION=854x480,NAME=\"480p\"\n480p/index.m3u8\n"
+        );
+    }
+
+    #[test]
+    fn rendition_playlist_lists_segments() {
+        let playlist = rendition_playlist(8);
+        assert_eq!(
+            playlist,
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n\
+            #EXTINF:6.000,\n0.ts\n#EXTINF:2.000,\n1.ts\n#EXT-X-ENDLIST\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use sqlx::PgPool;
+use tokio::sync::Notify;
+use tracing::{error, info, info_span, instrument, warn, Instrument};
+
+use crate::{
+    configuration::Configuration,
+    crud::{transcode_job::CreateTranscodeJobRequest, Entity},
+    hls,
+    logging::Logger,
+    model::{TranscodeJob, TranscodeJobKind, TranscodeJobStatus, Video},
+    transcoder::{TranscodeControl, Transcoder},
+};
+
+/// How often a running job reports its progress, which is also when it notices its cancellation.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+static MISSING_REQUEST_ID: &str = "missing_request_id";
+
+/// Queues transcoding jobs in the database, which a pool of workers processes in the background.
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    pool: PgPool,
+    wakeup: Arc<Notify>,
+}
+
+#[derive(Debug)]
+struct Worker {
+    pool: PgPool,
+    transcoder: Transcoder,
+    wakeup: Arc<Notify>,
+    poll_interval: Duration,
+}
+
+#[instrument(skip_all)]
+pub fn init(config: &Configuration, pool: PgPool, transcoder: Transcoder, _: &Logger) -> JobQueue {
+    let queue = JobQueue::new(pool.clone());
+    let workers = config.media().job_workers();
+    let poll_interval = config.media().job_poll_interval();
+    let wakeup = queue.wakeup.clone();
+
+    tokio::spawn(
+        async move {
+            // jobs interrupted by a shutdown are started over
+            match TranscodeJob::requeue_running(&pool).await {
+                Ok(0) => {}
+                Ok(requeued) => info!("requeued {requeued} interrupted transcoding jobs"),
+                Err(e) => error!("could not requeue interrupted transcoding jobs: {e}"),
+            }
+            for worker_id in 0..workers {
+                let worker = Worker {
+                    pool: pool.clone(),
+                    transcoder: transcoder.clone(),
+                    wakeup: wakeup.clone(),
+                    poll_interval,
+                };
+                tokio::spawn(
+                    worker
+                        .run()
+                        .instrument(info_span!("transcode_worker", worker_id)),
+                );
+            }
+        }
+        .instrument(info_span!("job_queue")),
+    );
+    info!("started {workers} transcoding workers");
+
+    queue
+}
+
+impl JobQueue {
+    /// Creates a queue without workers, see [`init`] for starting them.
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            wakeup: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Persists a new job and wakes up an idle worker.
+    pub async fn enqueue(
+        &self,
+        request: CreateTranscodeJobRequest,
+    ) -> Result<TranscodeJob, sqlx::Error> {
+        let job = TranscodeJob::create(&self.pool, request).await?;
+        self.wakeup.notify_one();
+        Ok(job)
+    }
+
+    /// Returns the latest job doing the same work, queueing a new one if there is none or it completed or failed,
+    /// see [`TranscodeJob::find_or_create`].
+    pub async fn find_or_enqueue(
+        &self,
+        request: CreateTranscodeJobRequest,
+    ) -> Result<TranscodeJob, sqlx::Error> {
+        let (job, created) = TranscodeJob::find_or_create(&self.pool, request).await?;
+        if created {
+            self.wakeup.notify_one();
+        }
+        Ok(job)
+    }
+}
+
+impl Worker {
+    async fn run(self) {
+        loop {
+            match TranscodeJob::claim_next(&self.pool).await {
+                Ok(Some(job)) => {
+                    let span = info_span!(
+                        "transcode_job",
+                        job.id = job.id,
+                        request_id = job.request_id.as_deref().unwrap_or(MISSING_REQUEST_ID)
+                    );
+                    self.process(job).instrument(span).await;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => error!("could not claim transcoding job: {e}"),
+            }
+            tokio::select! {
+                _ = self.wakeup.notified() => {}
+                _ = tokio::time::sleep(self.poll_interval) => {}
+            }
+        }
+    }
+
+    async fn process(&self, job: TranscodeJob) {
+        info!("running {:?} job of video {}", job.kind, job.video_id);
+        let control = TranscodeControl::default();
+        let execution = self.execute(&job, &control);
+        tokio::pin!(execution);
+
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        ticker.tick().await;
+        let result = loop {
+            tokio::select! {
+                result = &mut execution => break result,
+                _ = ticker.tick() => {
+                    match TranscodeJob::report_progress(&self.pool, job.id, control.progress()).await {
+                        Ok(Some(TranscodeJobStatus::Running)) => {}
+                        // cancelled or deleted, dropping the execution stops it
+                        Ok(_) => {
+                            control.cancel();
+                            info!("transcoding job was cancelled");
+                            return;
+                        }
+                        Err(e) => warn!("could not report transcoding progress: {e}"),
+                    }
+                }
+            }
+        };
+
+        let (status, error) = match result {
+            Ok(()) => {
+                info!("transcoding job completed");
+                (TranscodeJobStatus::Completed, None)
+            }
+            Err(e) => {
+                error!("transcoding job failed: {e:#}");
+                (TranscodeJobStatus::Failed, Some(format!("{e:#}")))
+            }
+        };
+        if let Err(e) = TranscodeJob::finish(&self.pool, job.id, status, error).await {
+            error!("could not finish transcoding job: {e}");
+        }
+    }
+
+    async fn execute(&self, job: &TranscodeJob, control: &TranscodeControl) -> anyhow::Result<()> {
+        let video = Video::find(&self.pool, job.video_id)
+            .await?
+            .context("the video no longer exists")?;
+        match job.kind {
+            TranscodeJobKind::HlsRendition => {
+                let name = job
+                    .rendition
+                    .as_deref()
+                    .context("the job has no rendition")?;
+                let Some(rendition) = hls::renditions(&video)
+                    .into_iter()
+                    .find(|rendition| rendition.name == name)
+                else {
+                    bail!("the video has no '{name}' rendition");
+                };
+                self.transcoder
+                    .hls_rendition(&video, &rendition, control)
+                    .await
+            }
+            TranscodeJobKind::Remux => self
+                .transcoder
+                .remux(&video, job.audio_index, control.clone())
+                .await
+                .map(|_| ()),
+        }
+    }
+}
@@ -1,11 +1,23 @@
 pub mod api;
+pub mod artwork;
+pub mod auth;
 pub mod configuration;
 pub mod crud;
 pub mod database;
+pub mod error;
+pub mod hls;
+pub mod jobs;
 pub mod logging;
+pub mod media;
+pub mod metadata;
 pub mod model;
+pub mod previews;
+pub mod scanner;
 pub mod startup;
+pub mod subtitles;
 pub mod swagger;
+pub mod transcoder;
+pub mod watcher;
 
 pub fn print_banner() {
     print!("{}", include_str!("../resources/banner.txt"));
@@ -82,7 +82,7 @@ where
         let is_configured_log_level = *metadata.level() <= self.log_level;
         let is_hyper_debug_log = metadata
             .module_path()
-            .map_or(false, |path| path.starts_with("hyper"));
+            .is_some_and(|path| path.starts_with("hyper"));
         let enabled = is_configured_log_level && !is_hyper_debug_log;
         if enabled {
             Interest::always()
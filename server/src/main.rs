@@ -39,11 +39,14 @@ use std::net::SocketAddr;
 use anyhow::Ok;
 use binge_at_home::{
     api::init,
+    auth,
     configuration::Configuration,
     database::{self},
+    jobs,
     logging::{self, with_default_logger},
-    print_banner,
+    print_banner, scanner,
     startup::Application,
+    transcoder, watcher,
 };
 use tracing::debug;
 
@@ -61,8 +64,14 @@ async fn main() -> anyhow::Result<()> {
     #[cfg(feature = "migrate")]
     sqlx::migrate!().run(&database).await?;
 
+    auth::bootstrap_admin(&config, &database, &logger).await?;
+
     let address = SocketAddr::new(config.host(), config.port());
-    let router = init(config, database, &logger)?;
+    let scanner = scanner::init(&config, database.clone(), &logger);
+    watcher::init(&config, scanner.clone(), &logger);
+    let transcoder = transcoder::init(&config, &logger);
+    let jobs = jobs::init(&config, database.clone(), transcoder.clone(), &logger);
+    let router = init(config, database, scanner, transcoder, jobs, &logger)?;
     let app = Application::new(address, router, logger);
     app.run_until_stopped().await
 }
@@ -0,0 +1,61 @@
+use std::path::Path;
+
+/// Video container formats recognised by the application, by file extension and MIME type.
+const VIDEO_CONTAINERS: &[(&str, &str)] = &[
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mkv", "video/x-matroska"),
+    ("mov", "video/quicktime"),
+    ("avi", "video/x-msvideo"),
+    ("ts", "video/mp2t"),
+    ("m2ts", "video/mp2t"),
+    ("ogv", "video/ogg"),
+    ("wmv", "video/x-ms-wmv"),
+    ("flv", "video/x-flv"),
+];
+
+/// Returns the MIME type of a video file based on its extension, if it is a recognised container.
+pub fn video_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    VIDEO_CONTAINERS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+}
+
+pub fn is_video(path: &Path) -> bool {
+    video_mime_type(path).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn video_mime_type_known_extension() {
+        assert_eq!(
+            video_mime_type(Path::new("/movies/inception.mp4")),
+            Some("video/mp4")
+        );
+        assert_eq!(
+            video_mime_type(Path::new("/series/episode.mkv")),
+            Some("video/x-matroska")
+        );
+    }
+
+    #[test]
+    fn video_mime_type_case_insensitive() {
+        assert_eq!(
+            video_mime_type(Path::new("/movies/INCEPTION.WebM")),
+            Some("video/webm")
+        );
+    }
+
+    #[test]
+    fn video_mime_type_unknown_extension() {
+        assert_eq!(video_mime_type(Path::new("/movies/poster.jpg")), None);
+        assert_eq!(video_mime_type(Path::new("/movies/no_extension")), None);
+    }
+}
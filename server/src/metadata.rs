@@ -0,0 +1,440 @@
+mod ffprobe;
+mod matroska;
+mod mp4;
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Once,
+};
+
+use anyhow::{bail, Context};
+use tracing::{debug, instrument, warn};
+
+use crate::model::{
+    AudioChannels, Bytes, BytesPerSecond, FramesPerSecond, ScreenHeight, ScreenWidth, Seconds,
+    SubtitleFormat,
+};
+
+const MATROSKA_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+
+/// A missing `ffprobe` is only reported once, instead of for every probed file.
+static FFPROBE_MISSING: Once = Once::new();
+
+/// The technical metadata of a video file, as stored on a [`crate::model::Video`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VideoMetadata {
+    pub size: Bytes,
+    pub duration: Seconds,
+    pub bitrate: BytesPerSecond,
+    pub width: ScreenWidth,
+    pub height: ScreenHeight,
+    pub framerate: FramesPerSecond,
+}
+
+/// A text subtitle track embedded in a video file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedSubtitle {
+    /// The position among the subtitle tracks of the file, as addressed by `ffmpeg` with `0:s:{index}`.
+    pub index: i32,
+    pub format: SubtitleFormat,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+}
+
+/// An audio track of a video file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedAudioTrack {
+    /// The position among the audio tracks of the file, as addressed by `ffmpeg` with `0:a:{index}`.
+    pub index: i32,
+    /// The codec as named by `ffmpeg`, e.g. `aac` or `eac3`.
+    pub codec: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Zero if unknown.
+    pub channels: AudioChannels,
+    pub is_default: bool,
+}
+
+/// Everything probed from a video file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProbedVideo {
+    pub metadata: VideoMetadata,
+    pub audio_tracks: Vec<EmbeddedAudioTrack>,
+    pub subtitles: Vec<EmbeddedSubtitle>,
+}
+
+/// Container level information extracted by one of the parsers.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct ContainerInfo {
+    /// Duration in seconds.
+    duration: Option<f64>,
+    tracks: Vec<TrackInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct TrackInfo {
+    kind: TrackKind,
+    codec: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    framerate: Option<f64>,
+    /// Duration in seconds.
+    duration: Option<f64>,
+    channels: Option<u32>,
+    language: Option<String>,
+    title: Option<String>,
+    default: bool,
+    forced: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum TrackKind {
+    Video,
+    Audio,
+    Subtitle,
+    #[default]
+    Other,
+}
+
+impl ContainerInfo {
+    fn video_track(&self) -> Option<&TrackInfo> {
+        self.tracks
+            .iter()
+            .find(|track| track.kind == TrackKind::Video)
+    }
+
+    /// Whether every field of [`VideoMetadata`] could be derived from the container.
+    fn is_complete(&self) -> bool {
+        self.duration.is_some()
+            && self.video_track().is_some_and(|track| {
+                track.width.is_some() && track.height.is_some() && track.framerate.is_some()
+            })
+    }
+
+    /// The text subtitle tracks, image based ones are skipped, but they still count for the indexes.
+    fn subtitles(&self) -> Vec<EmbeddedSubtitle> {
+        self.tracks
+            .iter()
+            .filter(|track| track.kind == TrackKind::Subtitle)
+            .zip(0..)
+            .filter_map(|(track, index)| {
+                Some(EmbeddedSubtitle {
+                    index,
+                    format: SubtitleFormat::from_codec(track.codec.as_deref()?)?,
+                    language: track.language.clone(),
+                    title: track.title.clone(),
+                    is_default: track.default,
+                    is_forced: track.forced,
+                })
+            })
+            .collect()
+    }
+
+    fn audio_tracks(&self) -> Vec<EmbeddedAudioTrack> {
+        self.tracks
+            .iter()
+            .filter(|track| track.kind == TrackKind::Audio)
+            .zip(0..)
+            .map(|(track, index)| EmbeddedAudioTrack {
+                index,
+                codec: track
+                    .codec
+                    .as_deref()
+                    .map_or_else(|| "unknown".to_owned(), audio_codec_name),
+                language: track.language.clone(),
+                title: track.title.clone(),
+                channels: track.channels.map_or(0, |channels| {
+                    channels.try_into().unwrap_or(AudioChannels::MAX)
+                }),
+                is_default: track.default,
+            })
+            .collect()
+    }
+
+    fn into_probed(self, size: u64) -> ProbedVideo {
+        ProbedVideo {
+            audio_tracks: self.audio_tracks(),
+            subtitles: self.subtitles(),
+            metadata: self.into_metadata(size),
+        }
+    }
+
+    fn into_metadata(self, size: u64) -> VideoMetadata {
+        let duration = self.duration.unwrap_or_default();
+        let video = self.video_track().cloned().unwrap_or_default();
+        let bitrate = if duration > 0.0 {
+            (size as f64 / duration).round() as BytesPerSecond
+        } else {
+            0
+        };
+        VideoMetadata {
+            size: size as Bytes,
+            duration: duration.round() as Seconds,
+            bitrate,
+            width: saturate(video.width),
+            height: saturate(video.height),
+            framerate: video.framerate.unwrap_or_default(),
+        }
+    }
+}
+
+fn saturate(dimension: Option<u32>) -> i16 {
+    dimension.map_or(0, |dimension| i16::try_from(dimension).unwrap_or(i16::MAX))
+}
+
+/// The `ffmpeg` name of an audio codec, as named by Matroska, MP4 or `ffprobe`.
+fn audio_codec_name(codec: &str) -> String {
+    let name = match codec {
+        "A_AAC" | "mp4a" => "aac",
+        "A_AC3" | "ac-3" => "ac3",
+        "A_EAC3" | "ec-3" => "eac3",
+        "A_DTS" => "dts",
+        "A_TRUEHD" | "mlpa" => "truehd",
+        "A_OPUS" | "Opus" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_FLAC" | "fLaC" => "flac",
+        "A_MPEG/L3" | ".mp3" => "mp3",
+        // Matroska has variants like `A_AAC/MPEG4/LC`
+        codec if codec.starts_with("A_AAC") => "aac",
+        codec => return codec.trim_start_matches("A_").to_lowercase(),
+    };
+    name.to_owned()
+}
+
+/// Normalizes the language code of a track, the undetermined `und` code means no language.
+fn language(code: &str) -> Option<String> {
+    let code = code.trim().trim_end_matches('\0').to_lowercase();
+    (!code.is_empty() && code != "und").then_some(code)
+}
+
+/// Reads the technical metadata of video files.
+/// MP4/MOV and Matroska/WebM headers are parsed natively, `ffprobe` is used for anything else or when the headers are incomplete.
+#[derive(Debug, Clone)]
+pub struct Prober {
+    ffprobe: PathBuf,
+}
+
+impl Prober {
+    pub fn new(ffprobe: PathBuf) -> Self {
+        Self { ffprobe }
+    }
+
+    /// Probes a video file. This is blocking, so it should be called with [`crate::logging::spawn_blocking_with_tracing`].
+    #[instrument(skip(self))]
+    pub fn probe(&self, path: &Path) -> anyhow::Result<ProbedVideo> {
+        let file = File::open(path)
+            .with_context(|| format!("could not open video file '{}'", path.display()))?;
+        let size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let native = parse_container(&mut reader);
+        match native {
+            Ok(info) if info.is_complete() => return Ok(info.into_probed(size)),
+            Ok(_) => debug!("container headers are incomplete, falling back to ffprobe"),
+            Err(ref e) => debug!("container could not be parsed natively: {e:#}"),
+        }
+
+        match ffprobe::probe(&self.ffprobe, path) {
+            Ok(Some(info)) => Ok(info.into_probed(size)),
+            Ok(None) => {
+                FFPROBE_MISSING.call_once(|| {
+                    warn!(
+                        "ffprobe is not available at '{}', metadata may be incomplete",
+                        self.ffprobe.display()
+                    );
+                });
+                native.map(|info| info.into_probed(size))
+            }
+            Err(e) => native
+                .map(|info| info.into_probed(size))
+                .map_err(|native_error| native_error.context(e)),
+        }
+    }
+}
+
+fn parse_container<R: Read + Seek>(reader: &mut R) -> anyhow::Result<ContainerInfo> {
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .context("file is too short to be a video")?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    if magic[..4] == MATROSKA_MAGIC {
+        matroska::parse(reader)
+    } else if mp4::is_box_type(&magic[4..]) {
+        mp4::parse(reader)
+    } else {
+        bail!("unknown container format")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn complete_info() -> ContainerInfo {
+        ContainerInfo {
+            duration: Some(100.4),
+            tracks: vec![
+                TrackInfo {
+                    kind: TrackKind::Audio,
+                    codec: Some("aac".to_owned()),
+                    ..TrackInfo::default()
+                },
+                TrackInfo {
+                    kind: TrackKind::Video,
+                    codec: Some("avc1".to_owned()),
+                    width: Some(1920),
+                    height: Some(1080),
+                    framerate: Some(25.0),
+                    duration: Some(100.4),
+                    ..TrackInfo::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn into_metadata_uses_video_track() {
+        let metadata = complete_info().into_metadata(1_004_000);
+        assert_eq!(
+            metadata,
+            VideoMetadata {
+                size: 1_004_000,
+                duration: 100,
+                bitrate: 10_000,
+                width: 1920,
+                height: 1080,
+                framerate: 25.0,
+            }
+        );
+    }
+
+    #[test]
+    fn into_metadata_without_duration_zero_bitrate() {
+        let mut info = complete_info();
+        info.duration = None;
+        let metadata = info.into_metadata(1000);
+        assert_eq!(metadata.duration, 0);
+        assert_eq!(metadata.bitrate, 0);
+    }
+
+    #[test]
+    fn subtitles_text_tracks_with_indexes() {
+        let mut info = complete_info();
+        info.tracks.extend([
+            TrackInfo {
+                kind: TrackKind::Subtitle,
+                codec: Some("S_HDMV/PGS".to_owned()),
+                ..TrackInfo::default()
+            },
+            TrackInfo {
+                kind: TrackKind::Subtitle,
+                codec: Some("S_TEXT/UTF8".to_owned()),
+                language: Some("hun".to_owned()),
+                forced: true,
+                ..TrackInfo::default()
+            },
+        ]);
+        assert_eq!(
+            info.subtitles(),
+            vec![EmbeddedSubtitle {
+                index: 1,
+                format: SubtitleFormat::Srt,
+                language: Some("hun".to_owned()),
+                title: None,
+                is_default: false,
+                is_forced: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn audio_tracks_with_indexes() {
+        let mut info = complete_info();
+        info.tracks.retain(|track| track.kind != TrackKind::Audio);
+        info.tracks.extend([
+            TrackInfo {
+                kind: TrackKind::Audio,
+                codec: Some("A_EAC3".to_owned()),
+                channels: Some(6),
+                language: Some("eng".to_owned()),
+                default: true,
+                ..TrackInfo::default()
+            },
+            TrackInfo {
+                kind: TrackKind::Audio,
+                codec: None,
+                title: Some("Commentary".to_owned()),
+                ..TrackInfo::default()
+            },
+        ]);
+        assert_eq!(
+            info.audio_tracks(),
+            vec![
+                EmbeddedAudioTrack {
+                    index: 0,
+                    codec: "eac3".to_owned(),
+                    language: Some("eng".to_owned()),
+                    title: None,
+                    channels: 6,
+                    is_default: true,
+                },
+                EmbeddedAudioTrack {
+                    index: 1,
+                    codec: "unknown".to_owned(),
+                    language: None,
+                    title: Some("Commentary".to_owned()),
+                    channels: 0,
+                    is_default: false,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn audio_codec_name_normalized() {
+        assert_eq!(audio_codec_name("A_AAC/MPEG4/LC"), "aac");
+        assert_eq!(audio_codec_name("ec-3"), "eac3");
+        assert_eq!(audio_codec_name("eac3"), "eac3");
+        assert_eq!(audio_codec_name("A_MS/ACM"), "ms/acm");
+    }
+
+    #[test]
+    fn language_undetermined_none() {
+        assert_eq!(language("ENG"), Some("eng".to_owned()));
+        assert_eq!(language("und"), None);
+        assert_eq!(language(""), None);
+    }
+
+    #[test]
+    fn is_complete_requires_video_track() {
+        assert!(complete_info().is_complete());
+        let mut info = complete_info();
+        info.tracks.retain(|track| track.kind != TrackKind::Video);
+        assert!(!info.is_complete());
+    }
+
+    #[test]
+    fn parse_container_detects_format() {
+        let mp4 = parse_container(&mut std::io::Cursor::new(mp4::tests::test_mp4())).unwrap();
+        assert!(mp4.is_complete());
+        let matroska = parse_container(&mut std::io::Cursor::new(matroska::tests::test_matroska(
+            &[matroska::tests::video_track_entry()],
+            false,
+        )))
+        .unwrap();
+        assert!(matroska.is_complete());
+    }
+
+    #[test]
+    fn parse_container_unknown_format() {
+        let mut reader = std::io::Cursor::new(b"RIFF\0\0\0\0AVI LIST".to_vec());
+        assert!(parse_container(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Fallback prober, shelling out to a locally installed `ffprobe`.
+
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{ensure, Context};
+use serde::Deserialize;
+
+use super::{language, ContainerInfo, TrackInfo, TrackKind};
+
+#[derive(Debug, Deserialize)]
+struct Output {
+    #[serde(default)]
+    format: Option<Format>,
+    #[serde(default)]
+    streams: Vec<Stream>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Format {
+    duration: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    duration: Option<String>,
+    channels: Option<u32>,
+    #[serde(default)]
+    tags: Tags,
+    #[serde(default)]
+    disposition: Disposition,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Tags {
+    language: Option<String>,
+    title: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Disposition {
+    #[serde(default)]
+    default: u8,
+    #[serde(default)]
+    forced: u8,
+}
+
+/// Probes the file with `ffprobe`, returns `None` if the executable cannot be found.
+pub(super) fn probe(ffprobe: &Path, path: &Path) -> anyhow::Result<Option<ContainerInfo>> {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("could not run ffprobe"),
+    };
+    ensure!(
+        output.status.success(),
+        "ffprobe failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let output = serde_json::from_slice::<Output>(&output.stdout)
+        .context("could not parse ffprobe output")?;
+    Ok(Some(output.into()))
+}
+
+/// Parses the rational frame rates reported by `ffprobe`, like `24000/1001`.
+fn parse_rate(rate: &str) -> Option<f64> {
+    let (numerator, denominator) = rate.split_once('/')?;
+    let numerator = numerator.parse::<f64>().ok()?;
+    let denominator = denominator.parse::<f64>().ok()?;
+    (numerator > 0.0 && denominator > 0.0).then(|| numerator / denominator)
+}
+
+impl From<Output> for ContainerInfo {
+    fn from(output: Output) -> Self {
+        let tracks = output
+            .streams
+            .into_iter()
+            .map(|stream| TrackInfo {
+                kind: match stream.codec_type.as_deref() {
+                    Some("video") => TrackKind::Video,
+                    Some("audio") => TrackKind::Audio,
+                    Some("subtitle") => TrackKind::Subtitle,
+                    _ => TrackKind::Other,
+                },
+                codec: stream.codec_name,
+                width: stream.width,
+                height: stream.height,
+                framerate: stream
+                    .avg_frame_rate
+                    .as_deref()
+                    .and_then(parse_rate)
+                    .or_else(|| stream.r_frame_rate.as_deref().and_then(parse_rate)),
+                duration: stream.duration.and_then(|duration| duration.parse().ok()),
+                channels: stream.channels,
+                language: stream.tags.language.as_deref().and_then(language),
+                title: stream.tags.title,
+                default: stream.disposition.default != 0,
+                forced: stream.disposition.forced != 0,
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            duration: output
+                .format
+                .and_then(|format| format.duration)
+                .and_then(|duration| duration.parse().ok()),
+            tracks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_rate_rational() {
+        assert_eq!(parse_rate("25/1"), Some(25.0));
+        assert!((parse_rate("24000/1001").unwrap() - 23.976).abs() < 0.001);
+        assert_eq!(parse_rate("0/0"), None);
+        assert_eq!(parse_rate("25"), None);
+    }
+
+    #[test]
+    fn output_into_container_info() {
+        let output = serde_json::from_str::<Output>(
+            r#"{
+                "streams": [
+                    { "codec_type": "video", "codec_name": "hevc", "width": 3840, "height": 2160, "avg_frame_rate": "0/0", "r_frame_rate": "30/1" },
+                    { "codec_type": "audio", "codec_name": "eac3", "channels": 6 },
+                    {
+                        "codec_type": "subtitle", "codec_name": "subrip",
+                        "tags": { "language": "ger", "title": "Forced" },
+                        "disposition": { "default": 0, "forced": 1 }
+                    }
+                ],
+                "format": { "duration": "1234.567000" }
+            }"#,
+        )
+        .unwrap();
+        let info = ContainerInfo::from(output);
+
+        assert_eq!(info.duration, Some(1234.567));
+        let video = info.video_track().unwrap();
+        assert_eq!(video.codec.as_deref(), Some("hevc"));
+        assert_eq!((video.width, video.height), (Some(3840), Some(2160)));
+        assert_eq!(video.framerate, Some(30.0));
+        assert_eq!(info.tracks[1].kind, TrackKind::Audio);
+        assert_eq!(info.tracks[1].channels, Some(6));
+        let subtitle = &info.tracks[2];
+        assert_eq!(subtitle.kind, TrackKind::Subtitle);
+        assert_eq!(subtitle.language.as_deref(), Some("ger"));
+        assert_eq!(subtitle.title.as_deref(), Some("Forced"));
+        assert!(!subtitle.default);
+        assert!(subtitle.forced);
+    }
+}
@@ -0,0 +1,396 @@
+//! Parser of Matroska and WebM files, reading the `Info` and `Tracks` elements of the first `Segment`.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use anyhow::{bail, ensure, Context};
+
+use super::{language, ContainerInfo, TrackInfo, TrackKind};
+
+const EBML_HEADER: u32 = 0x1A45_DFA3;
+const SEGMENT: u32 = 0x1853_8067;
+const CLUSTER: u32 = 0x1F43_B675;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const DURATION: u32 = 0x4489;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const NAME: u32 = 0x536E;
+const LANGUAGE: u32 = 0x22_B59C;
+const LANGUAGE_BCP47: u32 = 0x22_B59D;
+const FLAG_DEFAULT: u32 = 0x88;
+const FLAG_FORCED: u32 = 0x55AA;
+const DEFAULT_DURATION: u32 = 0x23_E383;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const CHANNELS: u32 = 0x9F;
+
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+const NANOS_PER_SECOND: f64 = 1_000_000_000.0;
+/// An upper bound for the elements read into memory, to not allocate arbitrary amounts for corrupted files.
+const MAX_ELEMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+struct ElementHeader {
+    id: u32,
+    /// `None` for elements of unknown size, which extend to the end of their parent.
+    size: Option<u64>,
+}
+
+/// Reads a variable length integer, returning its value without the length marker and its length in bytes.
+fn read_vint<R: Read>(reader: &mut R) -> anyhow::Result<(u64, usize)> {
+    let mut first = [0; 1];
+    reader.read_exact(&mut first)?;
+    let length = first[0].leading_zeros() as usize + 1;
+    ensure!(length <= 8, "invalid variable length integer");
+
+    let mut value = u64::from(first[0]) & (0xFF >> length);
+    let mut rest = [0; 7];
+    reader.read_exact(&mut rest[..length - 1])?;
+    for byte in &rest[..length - 1] {
+        value = (value << 8) | u64::from(*byte);
+    }
+    Ok((value, length))
+}
+
+fn read_element_header<R: Read>(reader: &mut R) -> anyhow::Result<ElementHeader> {
+    let mut first = [0; 1];
+    reader.read_exact(&mut first)?;
+    let id_length = first[0].leading_zeros() as usize + 1;
+    ensure!(id_length <= 4, "invalid element id");
+    let mut id = u32::from(first[0]);
+    let mut rest = [0; 3];
+    reader.read_exact(&mut rest[..id_length - 1])?;
+    for byte in &rest[..id_length - 1] {
+        id = (id << 8) | u32::from(*byte);
+    }
+
+    let (size, size_length) = read_vint(reader)?;
+    let unknown = size == (1 << (7 * size_length)) - 1;
+    Ok(ElementHeader {
+        id,
+        size: (!unknown).then_some(size),
+    })
+}
+
+fn read_body<R: Read>(reader: &mut R, size: Option<u64>) -> anyhow::Result<Vec<u8>> {
+    let size = size.context("element of unknown size cannot be read")?;
+    ensure!(size <= MAX_ELEMENT_SIZE, "element is too large");
+    let mut body = vec![0; usize::try_from(size)?];
+    reader
+        .read_exact(&mut body)
+        .context("element is truncated")?;
+    Ok(body)
+}
+
+/// Iterator over the child elements of an in-memory element body.
+struct Elements<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut cursor = self.data;
+        let header = read_element_header(&mut cursor).ok()?;
+        let size = usize::try_from(header.size.unwrap_or(cursor.len() as u64)).ok()?;
+        if size > cursor.len() {
+            self.data = &[];
+            return None;
+        }
+        let (body, rest) = cursor.split_at(size);
+        self.data = rest;
+        Some((header.id, body))
+    }
+}
+
+fn elements(data: &[u8]) -> Elements<'_> {
+    Elements { data }
+}
+
+fn read_uint(data: &[u8]) -> Option<u64> {
+    (data.len() <= 8).then(|| {
+        data.iter()
+            .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+    })
+}
+
+fn read_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f64::from(f32::from_be_bytes(data.try_into().ok()?))),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+pub(super) fn parse<R: Read + Seek>(reader: &mut R) -> anyhow::Result<ContainerInfo> {
+    let header = read_element_header(reader)?;
+    ensure!(header.id == EBML_HEADER, "missing EBML header");
+    let header_size = header.size.context("EBML header has unknown size")?;
+    reader.seek(SeekFrom::Current(i64::try_from(header_size)?))?;
+
+    let segment = read_element_header(reader)?;
+    ensure!(segment.id == SEGMENT, "missing segment");
+    let segment_end = segment
+        .size
+        .map(|size| reader.stream_position().map(|position| position + size))
+        .transpose()?;
+
+    let mut info = None;
+    let mut tracks = None;
+    while info.is_none() || tracks.is_none() {
+        let position = reader.stream_position()?;
+        if segment_end.is_some_and(|end| position >= end) {
+            break;
+        }
+        let Ok(element) = read_element_header(reader) else {
+            break;
+        };
+        match element.id {
+            INFO => info = Some(read_body(reader, element.size)?),
+            TRACKS => tracks = Some(read_body(reader, element.size)?),
+            // clusters of unknown size cannot be skipped, but the headers are expected to come before them anyway
+            CLUSTER if element.size.is_none() => break,
+            _ => {
+                let size = element
+                    .size
+                    .context("cannot skip element of unknown size")?;
+                reader.seek(SeekFrom::Current(i64::try_from(size)?))?;
+            }
+        }
+    }
+
+    let Some(tracks) = tracks else {
+        bail!("no tracks element found");
+    };
+    Ok(ContainerInfo {
+        duration: info.as_deref().and_then(parse_duration),
+        tracks: elements(&tracks)
+            .filter(|(id, _)| *id == TRACK_ENTRY)
+            .map(|(_, entry)| parse_track_entry(entry))
+            .collect(),
+    })
+}
+
+fn parse_duration(info: &[u8]) -> Option<f64> {
+    let mut scale = DEFAULT_TIMESTAMP_SCALE;
+    let mut duration = None;
+    for (id, body) in elements(info) {
+        match id {
+            TIMESTAMP_SCALE => scale = read_uint(body)?,
+            DURATION => duration = read_float(body),
+            _ => {}
+        }
+    }
+    duration.map(|duration| duration * scale as f64 / NANOS_PER_SECOND)
+}
+
+fn parse_track_entry(entry: &[u8]) -> TrackInfo {
+    // the defaults of the flag and the language, which are usually only written if they differ
+    let mut track = TrackInfo {
+        default: true,
+        language: Some("eng".to_owned()),
+        ..TrackInfo::default()
+    };
+    let mut bcp47_language = None;
+    for (id, body) in elements(entry) {
+        match id {
+            TRACK_TYPE => {
+                track.kind = match read_uint(body) {
+                    Some(1) => TrackKind::Video,
+                    Some(2) => TrackKind::Audio,
+                    Some(17) => TrackKind::Subtitle,
+                    _ => TrackKind::Other,
+                }
+            }
+            CODEC_ID => track.codec = Some(read_string(body)),
+            NAME => track.title = Some(read_string(body)).filter(|name| !name.is_empty()),
+            LANGUAGE => track.language = language(&read_string(body)),
+            LANGUAGE_BCP47 => bcp47_language = Some(language(&read_string(body))),
+            FLAG_DEFAULT => track.default = read_uint(body) != Some(0),
+            FLAG_FORCED => track.forced = read_uint(body).is_some_and(|forced| forced != 0),
+            DEFAULT_DURATION => {
+                track.framerate = read_uint(body)
+                    .filter(|nanos| *nanos > 0)
+                    .map(|nanos| NANOS_PER_SECOND / nanos as f64);
+            }
+            VIDEO => {
+                for (id, body) in elements(body) {
+                    match id {
+                        PIXEL_WIDTH => {
+                            track.width = read_uint(body).and_then(|w| w.try_into().ok())
+                        }
+                        PIXEL_HEIGHT => {
+                            track.height = read_uint(body).and_then(|h| h.try_into().ok())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            AUDIO => {
+                track.channels = elements(body)
+                    .find(|(id, _)| *id == CHANNELS)
+                    .and_then(|(_, body)| read_uint(body))
+                    .and_then(|channels| channels.try_into().ok());
+            }
+            _ => {}
+        }
+    }
+    // the newer language element takes precedence, if present
+    if let Some(language) = bcp47_language {
+        track.language = language;
+    }
+    track
+}
+
+fn read_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    pub fn element(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut data = id
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte| *byte == 0)
+            .collect::<Vec<_>>();
+        // 8 byte size vint, marker in the first byte
+        data.push(0x01);
+        data.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+        data.extend_from_slice(body);
+        data
+    }
+
+    pub fn uint(id: u32, value: u64) -> Vec<u8> {
+        element(id, &value.to_be_bytes())
+    }
+
+    pub fn video_track_entry() -> Vec<u8> {
+        element(
+            TRACK_ENTRY,
+            &[
+                uint(TRACK_TYPE, 1),
+                element(CODEC_ID, b"V_MPEG4/ISO/AVC"),
+                uint(DEFAULT_DURATION, 41_708_333),
+                element(
+                    VIDEO,
+                    &[uint(PIXEL_WIDTH, 1920), uint(PIXEL_HEIGHT, 800)].concat(),
+                ),
+            ]
+            .concat(),
+        )
+    }
+
+    pub fn test_matroska(track_entries: &[Vec<u8>], unknown_segment_size: bool) -> Vec<u8> {
+        let info = element(
+            INFO,
+            &[
+                uint(TIMESTAMP_SCALE, 1_000_000),
+                element(DURATION, &5_400_500.0_f64.to_be_bytes()),
+            ]
+            .concat(),
+        );
+        let tracks = element(TRACKS, &track_entries.concat());
+        let void = element(0xEC, &[0; 32]);
+        let cluster = element(CLUSTER, &[0; 64]);
+        let mut segment = element(SEGMENT, &[void, info, tracks, cluster].concat());
+        if unknown_segment_size {
+            // replace the 8 byte size with the reserved unknown size value
+            segment[4..12].copy_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        }
+        [element(EBML_HEADER, &element(0x4282, b"matroska")), segment].concat()
+    }
+
+    #[test]
+    fn read_vint_lengths() {
+        assert_eq!(read_vint(&mut Cursor::new([0x81])).unwrap(), (1, 1));
+        assert_eq!(read_vint(&mut Cursor::new([0x40, 0x02])).unwrap(), (2, 2));
+        assert_eq!(
+            read_vint(&mut Cursor::new([0x10, 0x00, 0x01, 0x00])).unwrap(),
+            (256, 4)
+        );
+        assert!(read_vint(&mut Cursor::new([0x00])).is_err());
+    }
+
+    #[test]
+    fn parse_info_and_tracks() {
+        let audio = element(
+            TRACK_ENTRY,
+            &[
+                uint(TRACK_TYPE, 2),
+                element(CODEC_ID, b"A_AAC"),
+                element(AUDIO, &uint(CHANNELS, 6)),
+            ]
+            .concat(),
+        );
+        let data = test_matroska(&[video_track_entry(), audio], false);
+        let info = parse(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(info.duration, Some(5400.5));
+        assert_eq!(info.tracks.len(), 2);
+        let video = info.video_track().unwrap();
+        assert_eq!(video.codec.as_deref(), Some("V_MPEG4/ISO/AVC"));
+        assert_eq!(video.width, Some(1920));
+        assert_eq!(video.height, Some(800));
+        assert!((video.framerate.unwrap() - 23.976).abs() < 0.001);
+        assert_eq!(info.tracks[1].kind, TrackKind::Audio);
+        assert_eq!(info.tracks[1].channels, Some(6));
+    }
+
+    #[test]
+    fn parse_track_flags_and_language() {
+        let subtitle = element(
+            TRACK_ENTRY,
+            &[
+                uint(TRACK_TYPE, 17),
+                element(CODEC_ID, b"S_TEXT/UTF8"),
+                element(NAME, b"Signs"),
+                element(LANGUAGE, b"hun\0"),
+                uint(FLAG_DEFAULT, 0),
+                uint(FLAG_FORCED, 1),
+            ]
+            .concat(),
+        );
+        let data = test_matroska(&[video_track_entry(), subtitle], false);
+        let info = parse(&mut Cursor::new(data)).unwrap();
+
+        let video = &info.tracks[0];
+        assert_eq!(video.language.as_deref(), Some("eng"));
+        assert!(video.default);
+        let subtitle = &info.tracks[1];
+        assert_eq!(subtitle.kind, TrackKind::Subtitle);
+        assert_eq!(subtitle.title.as_deref(), Some("Signs"));
+        assert_eq!(subtitle.language.as_deref(), Some("hun"));
+        assert!(!subtitle.default);
+        assert!(subtitle.forced);
+    }
+
+    #[test]
+    fn parse_unknown_segment_size() {
+        let data = test_matroska(&[video_track_entry()], true);
+        let info = parse(&mut Cursor::new(data)).unwrap();
+        assert_eq!(info.duration, Some(5400.5));
+        assert!(info.video_track().is_some());
+    }
+
+    #[test]
+    fn parse_without_tracks_fails() {
+        let data = [
+            element(EBML_HEADER, &[]),
+            element(SEGMENT, &element(INFO, &uint(TIMESTAMP_SCALE, 1))),
+        ]
+        .concat();
+        assert!(parse(&mut Cursor::new(data)).is_err());
+    }
+}
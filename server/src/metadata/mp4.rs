@@ -0,0 +1,403 @@
+//! Parser of ISO base media (MP4, MOV) files, reading only the `moov` box and the boxes needed from it.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use anyhow::{bail, ensure, Context};
+
+use super::{language, ContainerInfo, TrackInfo, TrackKind};
+
+/// An upper bound for the `moov` box, to not read arbitrary amounts of memory for corrupted files.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+const TOP_LEVEL_BOX_TYPES: [&[u8; 4]; 7] = [
+    b"ftyp", b"moov", b"mdat", b"free", b"skip", b"wide", b"pnot",
+];
+
+pub(super) fn is_box_type(box_type: &[u8]) -> bool {
+    TOP_LEVEL_BOX_TYPES
+        .iter()
+        .any(|known| known[..] == *box_type)
+}
+
+pub(super) fn parse<R: Read + Seek>(reader: &mut R) -> anyhow::Result<ContainerInfo> {
+    let moov = read_moov(reader)?;
+    parse_moov(&moov)
+}
+
+/// Walks the top level boxes, seeking over everything else, as `moov` is often placed after `mdat`.
+fn read_moov<R: Read + Seek>(reader: &mut R) -> anyhow::Result<Vec<u8>> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    let mut position = reader.seek(SeekFrom::Start(0))?;
+
+    while position + 8 <= file_size {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        let mut size = u64::from(u32::from_be_bytes([
+            header[0], header[1], header[2], header[3],
+        ]));
+        let mut header_size = 8;
+        if size == 1 {
+            let mut large_size = [0; 8];
+            reader.read_exact(&mut large_size)?;
+            size = u64::from_be_bytes(large_size);
+            header_size = 16;
+        } else if size == 0 {
+            size = file_size - position;
+        }
+        ensure!(size >= header_size, "invalid box size at offset {position}");
+
+        if &header[4..] == b"moov" {
+            let body_size = size - header_size;
+            ensure!(body_size <= MAX_MOOV_SIZE, "moov box is too large");
+            let mut moov = vec![0; usize::try_from(body_size)?];
+            reader
+                .read_exact(&mut moov)
+                .context("moov box is truncated")?;
+            return Ok(moov);
+        }
+
+        position = position
+            .checked_add(size)
+            .context("box size overflows the file")?;
+        reader.seek(SeekFrom::Start(position))?;
+    }
+
+    bail!("no moov box found")
+}
+
+/// Iterator over the child boxes of a box body, yielding the type and body of each.
+struct Boxes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 8 {
+            return None;
+        }
+        let size = read_u32(self.data, 0)? as usize;
+        let box_type = self.data[4..8].try_into().ok()?;
+        let (header_size, size) = match size {
+            0 => (8, self.data.len()),
+            1 => (16, usize::try_from(read_u64(self.data, 8)?).ok()?),
+            size => (8, size),
+        };
+        if size < header_size || size > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+        let body = &self.data[header_size..size];
+        self.data = &self.data[size..];
+        Some((box_type, body))
+    }
+}
+
+fn boxes(data: &[u8]) -> Boxes<'_> {
+    Boxes { data }
+}
+
+fn find_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(t, _)| t == box_type)
+        .map(|(_, body)| body)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads the timescale and duration of a `mvhd` or `mdhd` box, which share their layout up to the duration.
+fn read_header_times(data: &[u8]) -> Option<(u32, u64)> {
+    match data.first()? {
+        0 => Some((read_u32(data, 12)?, u64::from(read_u32(data, 16)?))),
+        1 => Some((read_u32(data, 20)?, read_u64(data, 24)?)),
+        _ => None,
+    }
+}
+
+/// Reads the ISO 639-2 language code of a `mdhd` box, packed as three 5 bit characters after the duration.
+fn read_language(mdhd: &[u8]) -> Option<String> {
+    let offset = match mdhd.first()? {
+        0 => 20,
+        1 => 32,
+        _ => return None,
+    };
+    let packed = read_u16(mdhd, offset)?;
+    let code = [10, 5, 0]
+        .into_iter()
+        .map(|shift| char::from((packed >> shift & 0x1F) as u8 + 0x60))
+        .collect::<String>();
+    code.chars()
+        .all(|c| c.is_ascii_lowercase())
+        .then(|| language(&code))
+        .flatten()
+}
+
+fn seconds(timescale: u32, duration: u64) -> Option<f64> {
+    (timescale > 0).then(|| duration as f64 / f64::from(timescale))
+}
+
+fn parse_moov(moov: &[u8]) -> anyhow::Result<ContainerInfo> {
+    let mut info = ContainerInfo {
+        duration: find_box(moov, b"mvhd")
+            .and_then(read_header_times)
+            .and_then(|(timescale, duration)| seconds(timescale, duration)),
+        tracks: vec![],
+    };
+
+    for (_, trak) in boxes(moov).filter(|(box_type, _)| box_type == b"trak") {
+        info.tracks.push(parse_trak(trak));
+    }
+
+    ensure!(!info.tracks.is_empty(), "moov box has no tracks");
+    if info.duration.is_none() {
+        info.duration = info
+            .tracks
+            .iter()
+            .filter_map(|track| track.duration)
+            .reduce(f64::max);
+    }
+    Ok(info)
+}
+
+fn parse_trak(trak: &[u8]) -> TrackInfo {
+    let mut track = TrackInfo::default();
+
+    if let Some(tkhd) = find_box(trak, b"tkhd") {
+        // width and height are the last two 16.16 fixed point values of the box
+        let end = tkhd.len();
+        track.width = end
+            .checked_sub(8)
+            .and_then(|offset| read_u16(tkhd, offset))
+            .map(u32::from);
+        track.height = end
+            .checked_sub(4)
+            .and_then(|offset| read_u16(tkhd, offset))
+            .map(u32::from);
+    }
+
+    let Some(mdia) = find_box(trak, b"mdia") else {
+        return track;
+    };
+    let mdhd = find_box(mdia, b"mdhd");
+    let times = mdhd.and_then(read_header_times);
+    track.language = mdhd.and_then(read_language);
+    track.duration = times.and_then(|(timescale, duration)| seconds(timescale, duration));
+    track.kind = match find_box(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)) {
+        Some(b"vide") => TrackKind::Video,
+        Some(b"soun") => TrackKind::Audio,
+        Some(b"sbtl" | b"subt" | b"text" | b"clcp") => TrackKind::Subtitle,
+        _ => TrackKind::Other,
+    };
+
+    let Some(stbl) = find_box(mdia, b"minf").and_then(|minf| find_box(minf, b"stbl")) else {
+        return track;
+    };
+
+    // the first sample entry starts after the version, flags and entry count
+    if let Some((entry_type, entry)) =
+        find_box(stbl, b"stsd").and_then(|stsd| boxes(stsd.get(8..)?).next())
+    {
+        track.codec = Some(String::from_utf8_lossy(&entry_type).trim().to_owned());
+        if track.kind == TrackKind::Video {
+            // visual sample entries store the dimensions after the 8 byte sample entry and 16 bytes of reserved fields
+            if let (Some(width), Some(height)) = (read_u16(entry, 24), read_u16(entry, 26)) {
+                track.width = Some(u32::from(width));
+                track.height = Some(u32::from(height));
+            }
+        }
+        if track.kind == TrackKind::Audio {
+            // audio sample entries store the channel count after the 8 byte sample entry and 8 bytes of reserved fields
+            track.channels = read_u16(entry, 16)
+                .filter(|channels| *channels > 0)
+                .map(u32::from);
+        }
+    }
+
+    if track.kind == TrackKind::Video {
+        let sample_count = find_box(stbl, b"stts").and_then(|stts| {
+            let entries = read_u32(stts, 4)? as usize;
+            (0..entries).try_fold(0_u64, |count, entry| {
+                Some(count + u64::from(read_u32(stts, 8 + entry * 8)?))
+            })
+        });
+        track.framerate = sample_count
+            .zip(track.duration)
+            .filter(|(_, duration)| *duration > 0.0)
+            .map(|(count, duration)| count as f64 / duration);
+    }
+
+    track
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    pub fn mp4_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn header_times(timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0; 12];
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&duration.to_be_bytes());
+        body.extend_from_slice(&[0; 8]);
+        body
+    }
+
+    fn hdlr(handler: &[u8; 4]) -> Vec<u8> {
+        let mut body = vec![0; 8];
+        body.extend_from_slice(handler);
+        body.extend_from_slice(&[0; 13]);
+        mp4_box(b"hdlr", &body)
+    }
+
+    fn stsd(entry_type: &[u8; 4], entry: &[u8]) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        body.extend(mp4_box(entry_type, entry));
+        mp4_box(b"stsd", &body)
+    }
+
+    fn visual_entry(width: u16, height: u16) -> Vec<u8> {
+        let mut entry = vec![0; 24];
+        entry.extend_from_slice(&width.to_be_bytes());
+        entry.extend_from_slice(&height.to_be_bytes());
+        entry.extend_from_slice(&[0; 50]);
+        entry
+    }
+
+    fn audio_entry(channels: u16) -> Vec<u8> {
+        let mut entry = vec![0; 16];
+        entry.extend_from_slice(&channels.to_be_bytes());
+        entry.extend_from_slice(&[0; 10]);
+        entry
+    }
+
+    fn stts(sample_counts: &[u32]) -> Vec<u8> {
+        let mut body = vec![0; 4];
+        body.extend_from_slice(&(sample_counts.len() as u32).to_be_bytes());
+        for count in sample_counts {
+            body.extend_from_slice(&count.to_be_bytes());
+            body.extend_from_slice(&1000_u32.to_be_bytes());
+        }
+        mp4_box(b"stts", &body)
+    }
+
+    pub fn trak(
+        handler: &[u8; 4],
+        (entry_type, entry): (&[u8; 4], &[u8]),
+        (timescale, duration): (u32, u32),
+        samples: &[u32],
+    ) -> Vec<u8> {
+        let stbl = mp4_box(b"stbl", &[stsd(entry_type, entry), stts(samples)].concat());
+        let minf = mp4_box(b"minf", &stbl);
+        let mdia = mp4_box(
+            b"mdia",
+            &[
+                mp4_box(b"mdhd", &header_times(timescale, duration)),
+                hdlr(handler),
+                minf,
+            ]
+            .concat(),
+        );
+        mp4_box(b"trak", &mdia)
+    }
+
+    pub fn test_mp4() -> Vec<u8> {
+        let video = trak(
+            b"vide",
+            (b"avc1", &visual_entry(1280, 720)),
+            (25_000, 250_000),
+            &[200, 50],
+        );
+        let audio = trak(
+            b"soun",
+            (b"mp4a", &audio_entry(2)),
+            (48_000, 480_000),
+            &[470],
+        );
+        let moov = mp4_box(
+            b"moov",
+            &[mp4_box(b"mvhd", &header_times(1000, 10_000)), video, audio].concat(),
+        );
+        [
+            mp4_box(b"ftyp", b"isom\0\0\x02\0isomiso2avc1mp41"),
+            mp4_box(b"mdat", &[0; 1024]),
+            moov,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn parse_moov_after_mdat() {
+        let info = parse(&mut Cursor::new(test_mp4())).unwrap();
+        assert_eq!(info.duration, Some(10.0));
+        assert_eq!(info.tracks.len(), 2);
+
+        let video = info.video_track().unwrap();
+        assert_eq!(video.codec.as_deref(), Some("avc1"));
+        assert_eq!(video.width, Some(1280));
+        assert_eq!(video.height, Some(720));
+        assert_eq!(video.framerate, Some(25.0));
+        assert_eq!(info.tracks[1].kind, TrackKind::Audio);
+        assert_eq!(info.tracks[1].channels, Some(2));
+    }
+
+    #[test]
+    fn read_language_packed() {
+        let mut mdhd = header_times(1000, 1000);
+        // "hun" as 5 bit characters offset by 0x60
+        mdhd[20..22].copy_from_slice(&((8_u16 << 10) | (21 << 5) | 14).to_be_bytes());
+        assert_eq!(read_language(&mdhd).as_deref(), Some("hun"));
+        mdhd[20..22].copy_from_slice(&((21_u16 << 10) | (14 << 5) | 4).to_be_bytes());
+        assert_eq!(read_language(&mdhd), None);
+        assert_eq!(read_language(&header_times(1000, 1000)), None);
+    }
+
+    #[test]
+    fn parse_without_moov_fails() {
+        let data = [
+            mp4_box(b"ftyp", b"isom\0\0\x02\0"),
+            mp4_box(b"mdat", &[0; 16]),
+        ]
+        .concat();
+        assert!(parse(&mut Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn parse_truncated_moov_fails() {
+        let mut data = test_mp4();
+        data.truncate(data.len() - 10);
+        assert!(parse(&mut Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn boxes_stops_at_invalid_size() {
+        let mut data = mp4_box(b"free", &[1, 2, 3]);
+        data.extend_from_slice(&1000_u32.to_be_bytes());
+        data.extend_from_slice(b"skip");
+        let types = boxes(&data)
+            .map(|(box_type, _)| box_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![*b"free"]);
+    }
+}
@@ -2,7 +2,10 @@ use std::path::PathBuf;
 
 #[cfg(test)]
 use fake::Dummy;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
 use time::OffsetDateTime;
+use utoipa::ToSchema;
 
 pub type EntityId = i64;
 
@@ -60,6 +63,232 @@ impl Video {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(test, derive(Dummy))]
+#[sqlx(type_name = "transcode_job_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeJobKind {
+    /// Transcodes every segment of an HLS rendition ahead of playback.
+    HlsRendition,
+    /// Copies the streams of a video into an MP4 container, which browsers can play directly.
+    Remux,
+}
+
+// needed to insert many jobs at once with `UNNEST`
+impl PgHasArrayType for TranscodeJobKind {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_transcode_job_kind")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(test, derive(Dummy))]
+#[sqlx(type_name = "transcode_job_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeJobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TranscodeJobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+pub type Progress = f64;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct TranscodeJob {
+    pub id: EntityId,
+    pub video_id: EntityId,
+    pub kind: TranscodeJobKind,
+    pub rendition: Option<String>,
+    /// The only audio track kept by a remux job, every audio track is kept without it.
+    pub audio_index: Option<i32>,
+    pub status: TranscodeJobStatus,
+    /// Between 0 and 1.
+    pub progress: Progress,
+    pub error: Option<String>,
+    /// The id of the request that queued the job, so its logs can be correlated.
+    pub request_id: Option<String>,
+    pub started_at: Option<OffsetDateTime>,
+    pub finished_at: Option<OffsetDateTime>,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(test, derive(Dummy))]
+#[sqlx(type_name = "subtitle_format", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SubtitleFormat {
+    /// SubRip.
+    Srt,
+    /// Advanced SubStation Alpha, or its predecessor SubStation Alpha.
+    Ass,
+    /// WebVTT, which browsers play natively.
+    Vtt,
+    /// The timed text of MP4 files, only found embedded.
+    MovText,
+}
+
+impl PgHasArrayType for SubtitleFormat {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_subtitle_format")
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct Subtitle {
+    pub id: EntityId,
+    pub video_id: EntityId,
+    /// The side-car file next to the video, `None` for tracks embedded in the video file.
+    pub path: Option<String>,
+    /// The position among the subtitle tracks of the video file, `None` for side-car files.
+    pub stream_index: Option<i32>,
+    pub format: SubtitleFormat,
+    /// The language code as found in the file name or the container, e.g. `en` or `hun`.
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+pub type AudioChannels = i16;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct AudioTrack {
+    pub id: EntityId,
+    pub video_id: EntityId,
+    /// The position among the audio tracks of the video file.
+    pub stream_index: i32,
+    pub codec: String,
+    /// The language code as found in the container, e.g. `hun`.
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Zero if unknown.
+    pub channels: AudioChannels,
+    pub is_default: bool,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(test, derive(Dummy))]
+#[sqlx(type_name = "user_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    /// Manages the library, the users and the transcoding jobs.
+    Admin,
+    /// Watches every catalog.
+    Viewer,
+    /// Watches only the catalogs granted to them.
+    Guest,
+}
+
+// needed to insert many users at once with `UNNEST`
+impl PgHasArrayType for UserRole {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_user_role")
+    }
+}
+
+impl UserRole {
+    /// Whether the user only sees the catalogs granted to them.
+    pub fn is_restricted(self) -> bool {
+        self == Self::Guest
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct User {
+    pub id: EntityId,
+    pub username: String,
+    /// The Argon2 hash of the password in PHC string format.
+    pub password_hash: String,
+    pub role: UserRole,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct Session {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    /// The SHA-256 hash of the token sent in the session cookie.
+    pub token_hash: String,
+    pub expires_at: OffsetDateTime,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(test, derive(Dummy))]
+#[sqlx(type_name = "token_scope", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Allows only requests that change nothing, like listing catalogs or streaming.
+    Read,
+    /// Allows every request the role of the owner allows.
+    Write,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct ApiToken {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub name: String,
+    /// The SHA-256 hash of the token.
+    pub token_hash: String,
+    pub scope: TokenScope,
+    /// The token never expires without it.
+    pub expires_at: Option<OffsetDateTime>,
+    pub last_used_at: Option<OffsetDateTime>,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct WatchProgress {
+    pub user_id: EntityId,
+    pub video_id: EntityId,
+    /// Where the user stopped watching the video.
+    pub position: Seconds,
+    /// Once the user gets past the watched threshold of the video it stays watched, even when it is rewatched.
+    pub watched: bool,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[cfg_attr(test, derive(Dummy))]
+pub struct CatalogGrant {
+    pub user_id: EntityId,
+    pub catalog_id: EntityId,
+
+    pub created_at: OffsetDateTime,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,4 +336,11 @@ mod tests {
         video.path = path.clone();
         assert_eq!(video.path(), PathBuf::from(path));
     }
+
+    #[test]
+    fn only_guests_restricted_to_granted_catalogs() {
+        assert!(!UserRole::Admin.is_restricted());
+        assert!(!UserRole::Viewer.is_restricted());
+        assert!(UserRole::Guest.is_restricted());
+    }
 }
@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use crate::model::{Seconds, Video};
+
+/// The width of a preview frame, the height follows the aspect ratio of the video.
+const TILE_WIDTH: u32 = 160;
+
+/// The preview frames of a sheet are laid out in a grid of this many columns and rows, row by row.
+const GRID_SIZE: u32 = 10;
+
+/// How the preview frames of a video are split into sprite sheets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteLayout {
+    /// The seconds between two preview frames.
+    pub interval: Seconds,
+    pub duration: Seconds,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+impl SpriteLayout {
+    /// The layout of the previews of a video, `None` if its duration is unknown.
+    pub fn new(video: &Video, interval: Seconds) -> Option<Self> {
+        if video.duration <= 0 || interval <= 0 {
+            return None;
+        }
+        let tile_height = if video.width > 0 && video.height > 0 {
+            // rounded to an even number, as required by most encoders
+            (f64::from(TILE_WIDTH) * f64::from(video.height) / f64::from(video.width) / 2.0)
+                .round()
+                .max(1.0) as u32
+                * 2
+        } else {
+            TILE_WIDTH * 9 / 16
+        };
+        Some(Self {
+            interval,
+            duration: video.duration,
+            tile_width: TILE_WIDTH,
+            tile_height,
+        })
+    }
+
+    pub fn grid_size(&self) -> u32 {
+        GRID_SIZE
+    }
+
+    pub fn frame_count(&self) -> i64 {
+        (self.duration + self.interval - 1) / self.interval
+    }
+
+    pub fn sheet_count(&self) -> i64 {
+        let frames_per_sheet = i64::from(GRID_SIZE * GRID_SIZE);
+        (self.frame_count() + frames_per_sheet - 1) / frames_per_sheet
+    }
+
+    /// The start and the length in seconds of the part of the video a sheet covers, `None` if the sheet is out of range.
+    pub fn sheet_bounds(&self, sheet: i64) -> Option<(Seconds, Seconds)> {
+        if sheet < 0 || sheet >= self.sheet_count() {
+            return None;
+        }
+        let span = i64::from(GRID_SIZE * GRID_SIZE) * self.interval;
+        let start = sheet * span;
+        Some((start, span.min(self.duration - start)))
+    }
+}
+
+/// The WebVTT thumbnail track of a video, mapping the time range of every preview frame to its region on a sprite sheet.
+/// The sheets are referenced relative to the track, as `previews/{sheet}.jpg`.
+pub fn webvtt(layout: &SpriteLayout) -> String {
+    let frames_per_sheet = i64::from(GRID_SIZE * GRID_SIZE);
+    let mut track = String::from("WEBVTT\n");
+    for frame in 0..layout.frame_count() {
+        let start = frame * layout.interval;
+        let end = (start + layout.interval).min(layout.duration);
+        let sheet = frame / frames_per_sheet;
+        let position = (frame % frames_per_sheet) as u32;
+        let x = position % GRID_SIZE * layout.tile_width;
+        let y = position / GRID_SIZE * layout.tile_height;
+        let _ = write!(
+            track,
+            "\n{} --> {}\npreviews/{sheet}.jpg#xywh={x},{y},{},{}\n",
+            timestamp(start),
+            timestamp(end),
+            layout.tile_width,
+            layout.tile_height
+        );
+    }
+    track
+}
+
+fn timestamp(seconds: Seconds) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.000",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    fn video(duration: Seconds, width: i16, height: i16) -> Video {
+        Video {
+            duration,
+            width,
+            height,
+            ..Faker.fake()
+        }
+    }
+
+    #[test]
+    fn layout_unknown_duration_none() {
+        assert_eq!(SpriteLayout::new(&video(0, 1920, 1080), 10), None);
+        assert_eq!(SpriteLayout::new(&video(60, 1920, 1080), 0), None);
+    }
+
+    #[test]
+    fn layout_tile_follows_aspect_ratio() {
+        let layout = SpriteLayout::new(&video(60, 1920, 800), 10).unwrap();
+        assert_eq!((layout.tile_width, layout.tile_height), (160, 66));
+        let unknown = SpriteLayout::new(&video(60, 0, 0), 10).unwrap();
+        assert_eq!((unknown.tile_width, unknown.tile_height), (160, 90));
+    }
+
+    #[test]
+    fn layout_sheets_cover_duration() {
+        let layout = SpriteLayout::new(&video(2005, 1920, 1080), 10).unwrap();
+        assert_eq!(layout.frame_count(), 201);
+        assert_eq!(layout.sheet_count(), 3);
+        assert_eq!(layout.sheet_bounds(0), Some((0, 1000)));
+        assert_eq!(layout.sheet_bounds(2), Some((2000, 5)));
+        assert_eq!(layout.sheet_bounds(3), None);
+        assert_eq!(layout.sheet_bounds(-1), None);
+    }
+
+    #[test]
+    fn webvtt_maps_frames_to_regions() {
+        let layout = SpriteLayout::new(&video(25, 1920, 1080), 10).unwrap();
+        assert_eq!(
+            webvtt(&layout),
+            "WEBVTT\n\
+             \n00:00:00.000 --> 00:00:10.000\npreviews/0.jpg#xywh=0,0,160,90\n\
+             \n00:00:10.000 --> 00:00:20.000\npreviews/0.jpg#xywh=160,0,160,90\n\
+             \n00:00:20.000 --> 00:00:25.000\npreviews/0.jpg#xywh=320,0,160,90\n"
+        );
+    }
+
+    #[test]
+    fn webvtt_wraps_rows_and_sheets() {
+        let layout = SpriteLayout::new(&video(3700, 1920, 1080), 10).unwrap();
+        let track = webvtt(&layout);
+        assert!(track.contains("00:01:40.000 --> 00:01:50.000\npreviews/0.jpg#xywh=0,90,160,90\n"));
+        assert!(track.contains("00:16:40.000 --> 00:16:50.000\npreviews/1.jpg#xywh=0,0,160,90\n"));
+        assert!(
+            track.ends_with("01:01:30.000 --> 01:01:40.000\npreviews/3.jpg#xywh=1440,540,160,90\n")
+        );
+    }
+}
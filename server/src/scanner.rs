@@ -0,0 +1,1119 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    configuration::Configuration,
+    crud::{
+        audio_track::CreateAudioTrackRequest,
+        catalog::CreateCatalogRequest,
+        subtitle::CreateSubtitleRequest,
+        video::{CreateVideoRequest, UpdateVideoRequest},
+        Entity,
+    },
+    logging::{spawn_blocking_with_tracing, Logger},
+    media::is_video,
+    metadata::{EmbeddedAudioTrack, EmbeddedSubtitle, ProbedVideo, Prober, VideoMetadata},
+    model::{AudioTrack, Catalog, EntityId, Subtitle, Video},
+    subtitles::{is_subtitle, parse_side_car_name, SideCarName},
+};
+
+/// Synchronizes the catalogs and videos in the database with the files under the configured library roots.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    pool: PgPool,
+    roots: Arc<[PathBuf]>,
+    prober: Prober,
+    lock: Arc<Mutex<()>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, ToSchema)]
+pub struct Changes {
+    pub added: u64,
+    pub updated: u64,
+    pub removed: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, ToSchema)]
+pub struct ScanSummary {
+    pub catalogs: Changes,
+    pub videos: Changes,
+    pub audio_tracks: Changes,
+    pub subtitles: Changes,
+}
+
+#[derive(Debug)]
+struct ScannedDirectory {
+    path: String,
+    name: String,
+    files: Vec<ScannedFile>,
+}
+
+#[derive(Debug)]
+struct ScannedFile {
+    path: String,
+    name: String,
+    size: u64,
+    subtitles: Vec<ScannedSubtitle>,
+}
+
+/// A side-car subtitle file of a video.
+#[derive(Debug)]
+struct ScannedSubtitle {
+    path: String,
+    name: SideCarName,
+}
+
+/// A part of the file system that was scanned.
+#[derive(Debug, Clone, PartialEq)]
+enum Scope {
+    /// A directory and everything under it.
+    Tree(PathBuf),
+    /// A directory and the files directly in it.
+    Directory(PathBuf),
+}
+
+impl Scope {
+    fn contains(&self, path: &Path) -> bool {
+        match self {
+            Self::Tree(root) => path.starts_with(root),
+            Self::Directory(directory) => path == directory || path.parent() == Some(directory),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ScannedLibrary {
+    /// The scopes that could be read, rows are only removed inside these, so an unreachable root does not empty the library.
+    scopes: Vec<Scope>,
+    /// The paths inside the scopes that could not be read, the rows under them are kept as well.
+    unreadable: Vec<PathBuf>,
+    directories: Vec<ScannedDirectory>,
+}
+
+impl ScannedLibrary {
+    fn contains(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        self.scopes.iter().any(|scope| scope.contains(path))
+            && !self
+                .unreadable
+                .iter()
+                .any(|unreadable| path.starts_with(unreadable))
+    }
+}
+
+#[instrument(skip_all)]
+pub fn init(config: &Configuration, pool: PgPool, _: &Logger) -> Scanner {
+    let prober = Prober::new(config.media().ffprobe());
+    let scanner = Scanner::new(config.library().roots(), prober, pool);
+    info!(
+        "initialized library scanner with roots: {:?}",
+        scanner.roots
+    );
+
+    if config.library().scan_on_startup() {
+        let scanner = scanner.clone();
+        tokio::spawn(
+            async move { scanner.scan_logged().await }.instrument(info_span!("startup_scan")),
+        );
+    }
+
+    if let Some(interval) = config.library().scan_interval() {
+        let scanner = scanner.clone();
+        tokio::spawn(
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                // the first tick completes immediately, startup scanning is configured separately
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    scanner.scan_logged().await;
+                }
+            }
+            .instrument(info_span!("scheduled_scan")),
+        );
+        info!(
+            "scheduled library scan every {} seconds",
+            interval.as_secs()
+        );
+    }
+
+    scanner
+}
+
+impl Scanner {
+    pub fn new(roots: Vec<PathBuf>, prober: Prober, pool: PgPool) -> Self {
+        Self {
+            pool,
+            roots: roots.into(),
+            prober,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Scans the library, returns `None` if a scan is already running.
+    pub async fn try_scan(&self) -> Option<anyhow::Result<ScanSummary>> {
+        let _guard = self.lock.try_lock().ok()?;
+        Some(self.scan_unguarded().await)
+    }
+
+    async fn scan_logged(&self) {
+        match self.try_scan().await {
+            Some(Ok(_)) => {}
+            Some(Err(e)) => error!("library scan failed: {e:#}"),
+            None => info!("skipping library scan, another one is already running"),
+        }
+    }
+
+    /// Applies the changes reported by the [`crate::watcher`].
+    /// The rows under moved paths are moved along, then the touched paths are rescanned, without walking the whole library.
+    #[instrument(skip(self))]
+    pub async fn apply_changes(
+        &self,
+        moves: Vec<(PathBuf, PathBuf)>,
+        mut touched: Vec<PathBuf>,
+    ) -> anyhow::Result<ScanSummary> {
+        let _guard = self.lock.lock().await;
+        let mut summary = ScanSummary::default();
+
+        for (from, to) in moves {
+            self.move_path(&from, &to, &mut summary).await?;
+            touched.extend([from, to]);
+        }
+
+        // changes under unreadable or empty roots are dropped, an unmounted share must not empty the library
+        let readable_roots = self
+            .roots
+            .iter()
+            .filter(|root| fs::read_dir(root).is_ok_and(|mut entries| entries.next().is_some()))
+            .cloned()
+            .collect::<Vec<_>>();
+        touched.retain(|path| readable_roots.iter().any(|root| path.starts_with(root)));
+        if touched.is_empty() {
+            return Ok(summary);
+        }
+
+        let library = spawn_blocking_with_tracing(move || scan_paths(&touched))
+            .await
+            .context("library walking task failed")?;
+        let catalog_ids = self.sync_catalogs(&library, &mut summary).await?;
+        let probed = self
+            .sync_videos(&library, &catalog_ids, &mut summary)
+            .await?;
+        self.sync_audio_tracks(&probed, &mut summary).await?;
+        self.sync_subtitles(&library, &probed, &mut summary).await?;
+        self.remove_catalogs(&library, &catalog_ids, &mut summary)
+            .await?;
+
+        debug!("applied library changes: {summary:?}");
+        Ok(summary)
+    }
+
+    #[instrument(skip(self))]
+    async fn scan_unguarded(&self) -> anyhow::Result<ScanSummary> {
+        info!("scanning library...");
+        let roots = self.roots.clone();
+        let library = spawn_blocking_with_tracing(move || scan_roots(&roots))
+            .await
+            .context("library walking task failed")?;
+
+        let mut summary = ScanSummary::default();
+        let catalog_ids = self.sync_catalogs(&library, &mut summary).await?;
+        let probed = self
+            .sync_videos(&library, &catalog_ids, &mut summary)
+            .await?;
+        self.sync_audio_tracks(&probed, &mut summary).await?;
+        self.sync_subtitles(&library, &probed, &mut summary).await?;
+        self.remove_catalogs(&library, &catalog_ids, &mut summary)
+            .await?;
+
+        info!("scanned library: {summary:?}");
+        Ok(summary)
+    }
+
+    /// Moves the catalogs and videos at or under `from` to `to`, keeping their ids and descriptions.
+    async fn move_path(
+        &self,
+        from: &Path,
+        to: &Path,
+        summary: &mut ScanSummary,
+    ) -> anyhow::Result<()> {
+        let Some(to_str) = to.to_str() else {
+            debug!("not moving rows to non-unicode path '{}'", to.display());
+            return Ok(());
+        };
+        let moved_path = |path: &str| {
+            let rest = Path::new(path).strip_prefix(from).ok()?;
+            let moved = if rest.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rest)
+            };
+            moved.to_str().map(ToOwned::to_owned)
+        };
+
+        for catalog in Catalog::find_all(&self.pool, vec![], vec![], None).await? {
+            if catalog.path == to_str {
+                // a directory can only be renamed over an empty one
+                Catalog::delete(&self.pool, catalog.id).await?;
+            } else if let Some(path) = moved_path(&catalog.path) {
+                debug!("moving catalog '{}' to '{path}'", catalog.path);
+                Catalog::update_path(&self.pool, catalog.id, path).await?;
+                summary.catalogs.updated += 1;
+            }
+        }
+
+        let mut moved = vec![];
+        for video in Video::find_all(&self.pool, vec![], vec![], None).await? {
+            if video.path == to_str {
+                // the renamed file replaced this one
+                Video::delete(&self.pool, video.id).await?;
+                summary.videos.removed += 1;
+            } else if let Some(path) = moved_path(&video.path) {
+                moved.push((video, path));
+            }
+        }
+        if moved.is_empty() {
+            return Ok(());
+        }
+
+        // the videos may be moved into directories without a catalog
+        let directories = moved
+            .iter()
+            .filter_map(|(_, path)| {
+                let directory = Path::new(path).parent()?;
+                Some(ScannedDirectory {
+                    path: directory.to_str()?.to_owned(),
+                    name: file_name(directory)?.to_owned(),
+                    files: vec![],
+                })
+            })
+            .collect();
+        let library = ScannedLibrary {
+            directories,
+            ..ScannedLibrary::default()
+        };
+        let catalog_ids = self.sync_catalogs(&library, summary).await?;
+
+        for (video, path) in moved {
+            let Some(catalog_id) = Path::new(&path)
+                .parent()
+                .and_then(Path::to_str)
+                .and_then(|directory| catalog_ids.get(directory))
+            else {
+                continue;
+            };
+            debug!("moving video '{}' to '{path}'", video.path);
+            let request = UpdateVideoRequest {
+                id: video.id,
+                path,
+                display_name: video.display_name,
+                short_desc: video.short_desc,
+                long_desc: video.long_desc,
+                catalog_id: *catalog_id,
+                sequent_id: video.sequent_id,
+                size: video.size,
+                duration: video.duration,
+                bitrate: video.bitrate,
+                width: video.width,
+                height: video.height,
+                framerate: video.framerate,
+                expected_updated_at: Some(vec![video.updated_at]),
+            };
+            // a video edited meanwhile is moved by the next scan
+            if Video::update(&self.pool, request).await?.is_some() {
+                summary.videos.updated += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates the catalogs of newly found directories, returns the ids of every catalog by path.
+    async fn sync_catalogs(
+        &self,
+        library: &ScannedLibrary,
+        summary: &mut ScanSummary,
+    ) -> anyhow::Result<HashMap<String, EntityId>> {
+        let catalogs = Catalog::find_all(&self.pool, vec![], vec![], None).await?;
+        let mut catalog_ids = catalogs
+            .into_iter()
+            .map(|catalog| (catalog.path, catalog.id))
+            .collect::<HashMap<_, _>>();
+
+        let requests = library
+            .directories
+            .iter()
+            .filter(|directory| !catalog_ids.contains_key(&directory.path))
+            .map(|directory| (directory.path.as_str(), directory))
+            .collect::<HashMap<_, _>>()
+            .into_values()
+            .map(|directory| CreateCatalogRequest {
+                path: directory.path.clone(),
+                display_name: directory.name.clone(),
+                short_desc: String::new(),
+                long_desc: String::new(),
+            })
+            .collect::<Vec<_>>();
+
+        if !requests.is_empty() {
+            let created = Catalog::create_many(&self.pool, requests).await?;
+            summary.catalogs.added += created.len() as u64;
+            catalog_ids.extend(
+                created
+                    .into_iter()
+                    .map(|catalog| (catalog.path, catalog.id)),
+            );
+        }
+
+        Ok(catalog_ids)
+    }
+
+    /// Creates, updates and removes videos. New, changed and not yet probed files are probed for their metadata.
+    /// Returns the probed files by path.
+    async fn sync_videos(
+        &self,
+        library: &ScannedLibrary,
+        catalog_ids: &HashMap<String, EntityId>,
+        summary: &mut ScanSummary,
+    ) -> anyhow::Result<HashMap<String, ProbedVideo>> {
+        let videos = Video::find_all(&self.pool, vec![], vec![], None).await?;
+        let mut existing = videos
+            .into_iter()
+            .map(|video| (video.path.clone(), video))
+            .collect::<HashMap<_, _>>();
+
+        let unprobed = library
+            .directories
+            .iter()
+            .flat_map(|directory| &directory.files)
+            .filter(|file| {
+                existing
+                    .get(&file.path)
+                    .is_none_or(|video| video.size != file.size as i64 || video.duration == 0)
+            })
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        let prober = self.prober.clone();
+        let probed = spawn_blocking_with_tracing(move || probe_files(&prober, unprobed))
+            .await
+            .context("video probing task failed")?;
+
+        let mut requests = vec![];
+        for directory in &library.directories {
+            let catalog_id = catalog_ids[&directory.path];
+            for file in &directory.files {
+                let metadata = probed.get(&file.path).map(|probed| probed.metadata.clone());
+                match existing.remove(&file.path) {
+                    Some(video) => {
+                        let current = VideoMetadata {
+                            size: video.size,
+                            duration: video.duration,
+                            bitrate: video.bitrate,
+                            width: video.width,
+                            height: video.height,
+                            framerate: video.framerate,
+                        };
+                        let metadata = metadata.unwrap_or(VideoMetadata {
+                            size: file.size as i64,
+                            ..current.clone()
+                        });
+                        if metadata == current && video.catalog_id == catalog_id {
+                            continue;
+                        }
+                        debug!("updating changed video '{}'", file.path);
+                        let request = UpdateVideoRequest {
+                            id: video.id,
+                            path: video.path,
+                            display_name: video.display_name,
+                            short_desc: video.short_desc,
+                            long_desc: video.long_desc,
+                            catalog_id,
+                            sequent_id: video.sequent_id,
+                            size: metadata.size,
+                            duration: metadata.duration,
+                            bitrate: metadata.bitrate,
+                            width: metadata.width,
+                            height: metadata.height,
+                            framerate: metadata.framerate,
+                            expected_updated_at: Some(vec![video.updated_at]),
+                        };
+                        // a video edited meanwhile is updated by the next scan
+                        if Video::update(&self.pool, request).await?.is_some() {
+                            summary.videos.updated += 1;
+                        }
+                    }
+                    None => {
+                        let metadata = metadata.unwrap_or(VideoMetadata {
+                            size: file.size as i64,
+                            ..VideoMetadata::default()
+                        });
+                        requests.push(CreateVideoRequest {
+                            path: file.path.clone(),
+                            display_name: file.name.clone(),
+                            short_desc: String::new(),
+                            long_desc: String::new(),
+                            catalog_id,
+                            sequent_id: None,
+                            size: metadata.size,
+                            duration: metadata.duration,
+                            bitrate: metadata.bitrate,
+                            width: metadata.width,
+                            height: metadata.height,
+                            framerate: metadata.framerate,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !requests.is_empty() {
+            summary.videos.added += Video::create_many(&self.pool, requests).await?.len() as u64;
+        }
+
+        let removed = existing
+            .into_values()
+            .filter(|video| library.contains(&video.path))
+            .map(|video| video.id)
+            .collect::<Vec<_>>();
+        if !removed.is_empty() {
+            summary.videos.removed += Video::delete_many(&self.pool, removed).await?;
+        }
+
+        Ok(probed)
+    }
+
+    /// Replaces the audio tracks of the probed videos, where they changed.
+    async fn sync_audio_tracks(
+        &self,
+        probed: &HashMap<String, ProbedVideo>,
+        summary: &mut ScanSummary,
+    ) -> anyhow::Result<()> {
+        if probed.is_empty() {
+            return Ok(());
+        }
+        let video_ids = Video::find_all(&self.pool, vec![], vec![], None)
+            .await?
+            .into_iter()
+            .filter(|video| probed.contains_key(&video.path))
+            .map(|video| (video.path, video.id))
+            .collect::<HashMap<_, _>>();
+        let ids = video_ids.values().copied().collect::<Vec<_>>();
+        let mut existing = HashMap::<_, Vec<_>>::new();
+        for track in AudioTrack::find_all_by_videos(&self.pool, &ids).await? {
+            existing.entry(track.video_id).or_default().push(track);
+        }
+
+        for (path, video_id) in video_ids {
+            let tracks = probed[&path]
+                .audio_tracks
+                .iter()
+                .map(|track| audio_track_request(video_id, track))
+                .collect();
+            let current = existing.remove(&video_id).unwrap_or_default();
+            replace_rows(
+                &self.pool,
+                current,
+                tracks,
+                |track| track.id,
+                &mut summary.audio_tracks,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the side-car subtitles of the scanned videos and the embedded ones of the probed videos, where they changed.
+    async fn sync_subtitles(
+        &self,
+        library: &ScannedLibrary,
+        probed: &HashMap<String, ProbedVideo>,
+        summary: &mut ScanSummary,
+    ) -> anyhow::Result<()> {
+        let video_ids = Video::find_all(&self.pool, vec![], vec![], None)
+            .await?
+            .into_iter()
+            .map(|video| (video.path, video.id))
+            .collect::<HashMap<_, _>>();
+        let mut existing = HashMap::<_, Vec<_>>::new();
+        for subtitle in Subtitle::find_all(&self.pool, vec![], vec![], None).await? {
+            existing
+                .entry(subtitle.video_id)
+                .or_default()
+                .push(subtitle);
+        }
+
+        for file in library
+            .directories
+            .iter()
+            .flat_map(|directory| &directory.files)
+        {
+            let Some(&video_id) = video_ids.get(&file.path) else {
+                continue;
+            };
+            let (current_embedded, current_side_cars) = existing
+                .remove(&video_id)
+                .unwrap_or_default()
+                .into_iter()
+                .partition::<Vec<_>, _>(|subtitle| subtitle.stream_index.is_some());
+
+            let side_cars = file
+                .subtitles
+                .iter()
+                .map(|subtitle| side_car_request(video_id, subtitle))
+                .collect();
+            replace_rows(
+                &self.pool,
+                current_side_cars,
+                side_cars,
+                |subtitle| subtitle.id,
+                &mut summary.subtitles,
+            )
+            .await?;
+
+            // the embedded tracks are only known for the files probed during this scan
+            if let Some(probed) = probed.get(&file.path) {
+                let tracks = probed
+                    .subtitles
+                    .iter()
+                    .map(|track| embedded_subtitle_request(video_id, track))
+                    .collect();
+                replace_rows(
+                    &self.pool,
+                    current_embedded,
+                    tracks,
+                    |subtitle| subtitle.id,
+                    &mut summary.subtitles,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the catalogs whose directories disappeared, if no video references them anymore.
+    /// The catalogs of existing directories are kept even without videos, like the ones created through the API.
+    async fn remove_catalogs(
+        &self,
+        library: &ScannedLibrary,
+        catalog_ids: &HashMap<String, EntityId>,
+        summary: &mut ScanSummary,
+    ) -> anyhow::Result<()> {
+        let scanned_paths = library
+            .directories
+            .iter()
+            .map(|directory| directory.path.as_str())
+            .collect::<HashSet<_>>();
+        let disappeared = catalog_ids
+            .keys()
+            .filter(|path| library.contains(path) && !scanned_paths.contains(path.as_str()))
+            .filter(|path| fs::metadata(path).is_err_and(|e| e.kind() == io::ErrorKind::NotFound))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !disappeared.is_empty() {
+            summary.catalogs.removed += Catalog::delete_unused(&self.pool, &disappeared).await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn scan_roots(roots: &[PathBuf]) -> ScannedLibrary {
+    let mut library = ScannedLibrary::default();
+    for root in roots {
+        if let Err(e) = fs::read_dir(root) {
+            warn!("skipping unreadable library root '{}': {e}", root.display());
+            continue;
+        }
+        let found = library.directories.len();
+        walk_directory(root, true, &mut library);
+        // an unmounted share usually leaves an empty mount point behind
+        if library.directories.len() == found {
+            warn!(
+                "no videos found under library root '{}', keeping the rows under it",
+                root.display()
+            );
+            continue;
+        }
+        library.scopes.push(Scope::Tree(root.clone()));
+    }
+    library
+}
+
+/// Scans the directories of changed paths. Existing directories are walked, for anything else only the parent directory is read.
+fn scan_paths(paths: &[PathBuf]) -> ScannedLibrary {
+    let mut library = ScannedLibrary::default();
+    for path in paths {
+        if path.is_dir() {
+            library.scopes.push(Scope::Tree(path.clone()));
+            walk_directory(path, true, &mut library);
+            continue;
+        }
+        library.scopes.push(Scope::Tree(path.clone()));
+        if let Some(parent) = path.parent() {
+            library.scopes.push(Scope::Directory(parent.to_path_buf()));
+            if parent.is_dir() {
+                walk_directory(parent, false, &mut library);
+            }
+        }
+    }
+    // overlapping scopes may find the same directory more than once
+    library
+        .directories
+        .sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    library.directories.dedup_by(|a, b| a.path == b.path);
+    library
+}
+
+/// Collects every directory under `root` that contains video files, or only `root` itself if not `recursive`.
+/// Hidden entries and symlinked directories are skipped, the paths that cannot be read are marked unreadable.
+fn walk_directory(root: &Path, recursive: bool, library: &mut ScannedLibrary) {
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(directory) = pending.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(
+                    "skipping unreadable directory '{}': {e}",
+                    directory.display()
+                );
+                library.unreadable.push(directory);
+                continue;
+            }
+        };
+
+        let mut files = vec![];
+        let mut subtitles = vec![];
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!(
+                        "could not list directory '{}' completely: {e}",
+                        directory.display()
+                    );
+                    library.unreadable.push(directory.clone());
+                    break;
+                }
+            };
+            let path = entry.path();
+            if file_name(&path).is_none_or(|name| name.starts_with('.')) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                library.unreadable.push(path);
+                continue;
+            };
+            if file_type.is_dir() {
+                if recursive {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if is_subtitle(&path) {
+                match (path.to_str(), file_name(&path)) {
+                    (Some(path), Some(name)) => subtitles.push((path.to_owned(), name.to_owned())),
+                    _ => debug!("skipping non-unicode subtitle '{}'", path.display()),
+                }
+                continue;
+            }
+            if !is_video(&path) {
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(&path) else {
+                library.unreadable.push(path);
+                continue;
+            };
+            match (path.to_str(), file_stem(&path)) {
+                (Some(path), Some(name)) if metadata.is_file() => files.push(ScannedFile {
+                    path: path.to_owned(),
+                    name: name.to_owned(),
+                    size: metadata.len(),
+                    subtitles: vec![],
+                }),
+                _ => debug!("skipping non-unicode or special file '{}'", path.display()),
+            }
+        }
+
+        if files.is_empty() {
+            continue;
+        }
+        assign_subtitles(&mut files, subtitles);
+        match (directory.to_str(), file_name(&directory)) {
+            (Some(path), Some(name)) => library.directories.push(ScannedDirectory {
+                path: path.to_owned(),
+                name: name.to_owned(),
+                files,
+            }),
+            _ => warn!(
+                "skipping directory with non-unicode path '{}'",
+                directory.display()
+            ),
+        }
+    }
+}
+
+/// Assigns the side-car subtitle files to the video they are named after.
+/// If more video names match, like `movie` and `movie.extended`, the longest one wins.
+fn assign_subtitles(files: &mut [ScannedFile], subtitles: Vec<(String, String)>) {
+    for (path, file_name) in subtitles {
+        let Some((file, name)) = files
+            .iter_mut()
+            .filter_map(|file| {
+                let name = parse_side_car_name(&file.name, &file_name)?;
+                Some((file, name))
+            })
+            .max_by_key(|(file, _)| file.name.len())
+        else {
+            debug!("skipping subtitle '{path}' without a matching video");
+            continue;
+        };
+        file.subtitles.push(ScannedSubtitle { path, name });
+    }
+    for file in files {
+        file.subtitles.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    }
+}
+
+fn side_car_request(video_id: EntityId, subtitle: &ScannedSubtitle) -> CreateSubtitleRequest {
+    CreateSubtitleRequest {
+        video_id,
+        path: Some(subtitle.path.clone()),
+        stream_index: None,
+        format: subtitle.name.format,
+        language: subtitle.name.language.clone(),
+        title: None,
+        is_default: subtitle.name.is_default,
+        is_forced: subtitle.name.is_forced,
+    }
+}
+
+fn embedded_subtitle_request(
+    video_id: EntityId,
+    track: &EmbeddedSubtitle,
+) -> CreateSubtitleRequest {
+    CreateSubtitleRequest {
+        video_id,
+        path: None,
+        stream_index: Some(track.index),
+        format: track.format,
+        language: track.language.clone(),
+        title: track.title.clone(),
+        is_default: track.is_default,
+        is_forced: track.is_forced,
+    }
+}
+
+fn audio_track_request(video_id: EntityId, track: &EmbeddedAudioTrack) -> CreateAudioTrackRequest {
+    CreateAudioTrackRequest {
+        video_id,
+        stream_index: track.index,
+        codec: track.codec.clone(),
+        language: track.language.clone(),
+        title: track.title.clone(),
+        channels: track.channels,
+        is_default: track.is_default,
+    }
+}
+
+/// Removes the current rows which are not desired anymore and creates the missing ones, the unchanged ones keep their ids.
+async fn replace_rows<T>(
+    pool: &PgPool,
+    current: Vec<T>,
+    mut desired: Vec<T::CreateRequest>,
+    id_of: fn(&T) -> EntityId,
+    changes: &mut Changes,
+) -> anyhow::Result<()>
+where
+    T: Entity<T>,
+    T::CreateRequest: From<T> + PartialEq,
+{
+    let mut removed = vec![];
+    for row in current {
+        let id = id_of(&row);
+        let request = T::CreateRequest::from(row);
+        match desired.iter().position(|desired| *desired == request) {
+            Some(position) => {
+                desired.swap_remove(position);
+            }
+            None => removed.push(id),
+        }
+    }
+    if !removed.is_empty() {
+        changes.removed += T::delete_many(pool, removed).await?;
+    }
+    if !desired.is_empty() {
+        changes.added += T::create_many(pool, desired).await?.len() as u64;
+    }
+    Ok(())
+}
+
+/// Probes the given files, the ones that fail are left out and keep their size-only metadata.
+fn probe_files(prober: &Prober, paths: Vec<String>) -> HashMap<String, ProbedVideo> {
+    if !paths.is_empty() {
+        info!("probing {} video files...", paths.len());
+    }
+    paths
+        .into_iter()
+        .filter_map(|path| match prober.probe(Path::new(&path)) {
+            Ok(metadata) => Some((path, metadata)),
+            Err(e) => {
+                warn!("could not probe video '{path}': {e:#}");
+                None
+            }
+        })
+        .collect()
+}
+
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|name| name.to_str())
+}
+
+fn file_stem(path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|name| name.to_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SubtitleFormat;
+    use pretty_assertions::assert_eq;
+
+    fn test_library(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("binge-scanner-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (file, content) in [
+            ("movies/inception.mp4", "video"),
+            ("movies/poster.jpg", "image"),
+            ("movies/inception.en.srt", "subtitle"),
+            ("movies/inception.extended.mp4", "extended"),
+            ("movies/inception.extended.hu.forced.ass", "subtitle"),
+            ("movies/other.srt", "subtitle"),
+            ("series/lost/s01e01.mkv", "episode"),
+            ("series/lost/.s01e02.mkv", "hidden"),
+            ("empty/readme.txt", "text"),
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn scan_roots_collects_directories_with_videos() {
+        let root = test_library("roots");
+        let library = scan_roots(&[root.clone(), root.join("missing")]);
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut directories = library
+            .directories
+            .iter()
+            .map(|directory| {
+                let mut files = directory
+                    .files
+                    .iter()
+                    .map(|file| (file.name.as_str(), file.size))
+                    .collect::<Vec<_>>();
+                files.sort();
+                (directory.name.as_str(), files)
+            })
+            .collect::<Vec<_>>();
+        directories.sort();
+
+        assert_eq!(library.scopes, vec![Scope::Tree(root)]);
+        assert_eq!(
+            directories,
+            vec![
+                ("lost", vec![("s01e01", 7)]),
+                ("movies", vec![("inception", 5), ("inception.extended", 8)])
+            ]
+        );
+    }
+
+    #[test]
+    fn walk_directory_assigns_side_car_subtitles() {
+        let root = test_library("subtitles");
+        let mut library = ScannedLibrary::default();
+        walk_directory(&root.join("movies"), false, &mut library);
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut subtitles = library.directories[0]
+            .files
+            .iter()
+            .flat_map(|file| {
+                file.subtitles.iter().map(|subtitle| {
+                    (
+                        file.name.as_str(),
+                        file_name(Path::new(&subtitle.path)).unwrap(),
+                        subtitle.name.clone(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        subtitles.sort_by(|a, b| a.1.cmp(b.1));
+
+        assert_eq!(
+            subtitles,
+            vec![
+                (
+                    "inception",
+                    "inception.en.srt",
+                    SideCarName {
+                        format: SubtitleFormat::Srt,
+                        language: Some("en".to_owned()),
+                        is_default: false,
+                        is_forced: false,
+                    }
+                ),
+                (
+                    "inception.extended",
+                    "inception.extended.hu.forced.ass",
+                    SideCarName {
+                        format: SubtitleFormat::Ass,
+                        language: Some("hu".to_owned()),
+                        is_default: false,
+                        is_forced: true,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn scanned_library_contains_only_paths_under_roots() {
+        let library = ScannedLibrary {
+            scopes: vec![Scope::Tree(PathBuf::from("/media/movies"))],
+            ..ScannedLibrary::default()
+        };
+        assert!(library.contains("/media/movies/inception.mp4"));
+        assert!(!library.contains("/media/series/lost/s01e01.mkv"));
+        assert!(!library.contains("/media/movies-old/inception.mp4"));
+    }
+
+    #[test]
+    fn scanned_library_excludes_unreadable_paths() {
+        let library = ScannedLibrary {
+            scopes: vec![Scope::Tree(PathBuf::from("/media"))],
+            unreadable: vec![PathBuf::from("/media/series")],
+            ..ScannedLibrary::default()
+        };
+        assert!(library.contains("/media/movies/inception.mp4"));
+        assert!(!library.contains("/media/series/lost/s01e01.mkv"));
+        assert!(!library.contains("/media/series"));
+    }
+
+    #[test]
+    fn scan_roots_keeps_rows_under_empty_roots() {
+        let root = test_library("empty-roots");
+        let mount_point = root.join("mnt");
+        fs::create_dir(&mount_point).unwrap();
+        let library = scan_roots(&[root.join("movies"), mount_point.clone()]);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(library.scopes, vec![Scope::Tree(root.join("movies"))]);
+        assert!(!library.contains(mount_point.join("lost/s01e01.mkv").to_str().unwrap()));
+    }
+
+    #[test]
+    fn scan_roots_keeps_rows_under_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = test_library("unreadable");
+        let locked = root.join("series/lost");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // permissions do not restrict the superuser
+        let readable = fs::read_dir(&locked).is_ok();
+        let library = scan_roots(std::slice::from_ref(&root));
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        if readable {
+            return;
+        }
+
+        let directories = library
+            .directories
+            .iter()
+            .map(|directory| directory.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(directories, vec!["movies"]);
+        assert_eq!(library.unreadable, vec![locked.clone()]);
+        assert!(library.contains(root.join("movies/deleted.mp4").to_str().unwrap()));
+        assert!(!library.contains(locked.join("s01e01.mkv").to_str().unwrap()));
+    }
+
+    #[test]
+    fn directory_scope_contains_only_direct_children() {
+        let scope = Scope::Directory(PathBuf::from("/media/series"));
+        assert!(scope.contains(Path::new("/media/series")));
+        assert!(scope.contains(Path::new("/media/series/pilot.mkv")));
+        assert!(!scope.contains(Path::new("/media/series/lost/s01e01.mkv")));
+        assert!(!scope.contains(Path::new("/media")));
+    }
+
+    #[test]
+    fn scan_paths_reads_parent_of_files_and_walks_directories() {
+        let root = test_library("paths");
+        let library = scan_paths(&[
+            root.join("movies/inception.mp4"),
+            root.join("movies/deleted.mp4"),
+            root.join("series"),
+        ]);
+        fs::remove_dir_all(&root).unwrap();
+
+        let directories = library
+            .directories
+            .iter()
+            .map(|directory| directory.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(directories, vec!["movies", "lost"]);
+        assert!(library.contains(root.join("movies/other.mp4").to_str().unwrap()));
+        assert!(library.contains(root.join("series/lost/s01e01.mkv").to_str().unwrap()));
+        assert!(!library.contains(root.join("empty/readme.txt").to_str().unwrap()));
+    }
+
+    #[sqlx::test]
+    async fn scan_removes_only_catalogs_of_disappeared_directories(pool: PgPool) {
+        let root = test_library("disappeared");
+        let catalog = |path: PathBuf| CreateCatalogRequest {
+            path: path.to_str().unwrap().to_owned(),
+            display_name: "catalog".to_owned(),
+            short_desc: String::new(),
+            long_desc: String::new(),
+        };
+        // created through the API, without videos in its directory
+        let kept = Catalog::create(&pool, catalog(root.join("empty")))
+            .await
+            .unwrap();
+        let gone = Catalog::create(&pool, catalog(root.join("deleted")))
+            .await
+            .unwrap();
+        let scanner = Scanner::new(
+            vec![root.clone()],
+            Prober::new(PathBuf::from("ffprobe")),
+            pool.clone(),
+        );
+
+        let summary = scanner.try_scan().await.unwrap().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(summary.catalogs.removed, 1);
+        assert!(Catalog::find(&pool, kept.id).await.unwrap().is_some());
+        assert!(Catalog::find(&pool, gone.id).await.unwrap().is_none());
+    }
+}
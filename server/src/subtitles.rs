@@ -0,0 +1,415 @@
+use std::{fmt::Write, path::Path};
+
+use crate::model::{Subtitle, SubtitleFormat};
+
+/// The fields of an ASS `Dialogue` line, if the script does not declare them.
+const DEFAULT_ASS_FIELDS: [&str; 10] = [
+    "Layer", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text",
+];
+
+/// The properties of a side-car subtitle encoded in its file name, like `movie.en.forced.srt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SideCarName {
+    pub format: SubtitleFormat,
+    pub language: Option<String>,
+    pub is_default: bool,
+    pub is_forced: bool,
+}
+
+impl SubtitleFormat {
+    /// The format of a side-car subtitle file by its extension.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "srt" => Some(Self::Srt),
+            "ass" | "ssa" => Some(Self::Ass),
+            "vtt" => Some(Self::Vtt),
+            _ => None,
+        }
+    }
+
+    /// The format of an embedded track by its codec, as named by Matroska, MP4 or `ffprobe`.
+    /// Image based subtitles have no format, as they cannot be converted to WebVTT.
+    pub fn from_codec(codec: &str) -> Option<Self> {
+        match codec {
+            "S_TEXT/UTF8" | "subrip" | "srt" => Some(Self::Srt),
+            "S_TEXT/ASS" | "S_TEXT/SSA" | "S_ASS" | "S_SSA" | "ass" | "ssa" => Some(Self::Ass),
+            "S_TEXT/WEBVTT" | "D_WEBVTT/SUBTITLES" | "wvtt" | "webvtt" => Some(Self::Vtt),
+            "tx3g" | "mov_text" => Some(Self::MovText),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_subtitle(path: &Path) -> bool {
+    SubtitleFormat::from_extension(path).is_some()
+}
+
+/// Parses the name of a side-car subtitle file, `None` if it does not belong to the video with the given file stem.
+/// The parts between the stem and the extension are tags, `forced` and `default` are flags, a two or three letter one is the language.
+pub fn parse_side_car_name(video_stem: &str, file_name: &str) -> Option<SideCarName> {
+    let format = SubtitleFormat::from_extension(Path::new(file_name))?;
+    let (name, _) = file_name.rsplit_once('.')?;
+    let tags = if name == video_stem {
+        ""
+    } else {
+        name.strip_prefix(video_stem)?.strip_prefix('.')?
+    };
+
+    let mut side_car = SideCarName {
+        format,
+        language: None,
+        is_default: false,
+        is_forced: false,
+    };
+    for tag in tags.split('.').filter(|tag| !tag.is_empty()) {
+        match tag.to_lowercase().as_str() {
+            "forced" => side_car.is_forced = true,
+            "default" => side_car.is_default = true,
+            language
+                if side_car.language.is_none()
+                    && (2..=3).contains(&language.len())
+                    && language.chars().all(|c| c.is_ascii_alphabetic()) =>
+            {
+                side_car.language = Some(language.to_owned());
+            }
+            _ => {}
+        }
+    }
+    Some(side_car)
+}
+
+/// The name of a subtitle in the track selection of a player, its title or else its language.
+pub fn label(subtitle: &Subtitle) -> String {
+    if let Some(title) = &subtitle.title {
+        return title.clone();
+    }
+    let language = subtitle.language.as_deref().unwrap_or("unknown");
+    if subtitle.is_forced {
+        format!("{language} (forced)")
+    } else {
+        language.to_owned()
+    }
+}
+
+/// Decodes a subtitle file, which is expected to be UTF-8, but older files are often in a legacy single byte encoding.
+pub fn decode(bytes: &[u8]) -> String {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        // Latin-1 maps every byte to a character, so the text stays readable for most western encodings
+        Err(_) => bytes.iter().map(|byte| char::from(*byte)).collect(),
+    };
+    text.trim_start_matches('\u{feff}').replace("\r\n", "\n")
+}
+
+/// Converts a subtitle to WebVTT, `None` for formats which cannot be converted natively.
+pub fn to_webvtt(format: SubtitleFormat, text: &str) -> Option<String> {
+    match format {
+        SubtitleFormat::Srt => Some(srt_to_webvtt(text)),
+        SubtitleFormat::Ass => Some(ass_to_webvtt(text)),
+        SubtitleFormat::Vtt => Some(text.to_owned()),
+        SubtitleFormat::MovText => None,
+    }
+}
+
+fn srt_to_webvtt(text: &str) -> String {
+    let mut track = String::from("WEBVTT\n");
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        // coordinates may follow the end timestamp, they have no WebVTT equivalent
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (parse_timestamp(start.trim()), parse_timestamp(end)) else {
+            continue;
+        };
+        let cue = lines
+            .map(|line| strip_srt_markup(line.trim_end()))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        if cue.is_empty() {
+            continue;
+        }
+        let _ = write!(
+            track,
+            "\n{} --> {}\n{}\n",
+            format_timestamp(start),
+            format_timestamp(end),
+            cue.join("\n")
+        );
+    }
+    track
+}
+
+/// Removes the `<font>` tags and `{\an8}` style overrides found in SRT files, WebVTT only supports the basic tags.
+fn strip_srt_markup(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(position) = rest.find(['<', '{']) {
+        stripped.push_str(&rest[..position]);
+        let tail = &rest[position..];
+        let lowercase = tail.to_lowercase();
+        let skipped = if lowercase.starts_with("<font") || lowercase.starts_with("</font") {
+            tail.find('>').map(|end| end + 1)
+        } else if tail.starts_with("{\\") {
+            tail.find('}').map(|end| end + 1)
+        } else {
+            None
+        };
+        match skipped {
+            Some(skipped) => rest = &tail[skipped..],
+            None => {
+                stripped.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+fn ass_to_webvtt(text: &str) -> String {
+    let mut in_events = false;
+    let mut fields = DEFAULT_ASS_FIELDS
+        .map(|field| field.to_lowercase())
+        .to_vec();
+    let mut cues = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format
+                .split(',')
+                .map(|field| field.trim().to_lowercase())
+                .collect();
+            continue;
+        }
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+        // the text is the last field and may contain commas itself
+        let values = dialogue.splitn(fields.len(), ',').collect::<Vec<_>>();
+        let value_of = |name: &str| {
+            fields
+                .iter()
+                .position(|field| field == name)
+                .and_then(|position| values.get(position))
+                .map(|value| value.trim())
+        };
+        let (Some(start), Some(end), Some(text)) = (
+            value_of("start").and_then(parse_timestamp),
+            value_of("end").and_then(parse_timestamp),
+            value_of("text"),
+        ) else {
+            continue;
+        };
+        let text = strip_ass_markup(text);
+        if !text.trim().is_empty() {
+            cues.push((start, end, text));
+        }
+    }
+
+    // dialogue lines are not necessarily in order, but WebVTT cues must be
+    cues.sort_by_key(|(start, end, _)| (*start, *end));
+    let mut track = String::from("WEBVTT\n");
+    for (start, end, text) in cues {
+        let _ = write!(
+            track,
+            "\n{} --> {}\n{text}\n",
+            format_timestamp(start),
+            format_timestamp(end)
+        );
+    }
+    track
+}
+
+/// Removes the `{...}` override blocks of ASS dialogue and resolves its escapes.
+fn strip_ass_markup(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_override = false;
+    for c in text.chars() {
+        match c {
+            '{' => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if !in_override => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ")
+}
+
+/// Parses an `h:mm:ss,mmm` (SRT), `h:mm:ss.cc` (ASS) or `mm:ss.mmm` (WebVTT) timestamp into milliseconds.
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (clock, fraction) = timestamp.split_once([',', '.']).unwrap_or((timestamp, "0"));
+    let mut parts = clock.rsplit(':').map(str::parse::<u64>);
+    let seconds = parts.next()?.ok()?;
+    let minutes = parts.next()?.ok()?;
+    let hours = parts.next().transpose().ok()?.unwrap_or(0);
+    if parts.next().is_some() || fraction.is_empty() || fraction.len() > 3 {
+        return None;
+    }
+    // the fraction is scaled by its number of digits, `.5` is half a second
+    let millis = fraction.parse::<u64>().ok()? * 10_u64.pow(3 - fraction.len() as u32);
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+fn format_timestamp(millis: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn format_by_extension_and_codec() {
+        assert_eq!(
+            SubtitleFormat::from_extension(Path::new("/movies/a.en.SRT")),
+            Some(SubtitleFormat::Srt)
+        );
+        assert_eq!(
+            SubtitleFormat::from_extension(Path::new("a.ssa")),
+            Some(SubtitleFormat::Ass)
+        );
+        assert_eq!(SubtitleFormat::from_extension(Path::new("a.sub")), None);
+        assert_eq!(
+            SubtitleFormat::from_codec("S_TEXT/UTF8"),
+            Some(SubtitleFormat::Srt)
+        );
+        assert_eq!(
+            SubtitleFormat::from_codec("mov_text"),
+            Some(SubtitleFormat::MovText)
+        );
+        assert_eq!(SubtitleFormat::from_codec("hdmv_pgs_subtitle"), None);
+    }
+
+    #[test]
+    fn parse_side_car_name_tags() {
+        assert_eq!(
+            parse_side_car_name("Inception", "Inception.srt"),
+            Some(SideCarName {
+                format: SubtitleFormat::Srt,
+                language: None,
+                is_default: false,
+                is_forced: false,
+            })
+        );
+        assert_eq!(
+            parse_side_car_name("Inception", "Inception.HUN.forced.ass"),
+            Some(SideCarName {
+                format: SubtitleFormat::Ass,
+                language: Some("hun".to_owned()),
+                is_default: false,
+                is_forced: true,
+            })
+        );
+        assert_eq!(
+            parse_side_car_name("s01e01", "s01e01.en.sdh.default.vtt")
+                .map(|name| (name.language, name.is_default)),
+            Some((Some("en".to_owned()), true))
+        );
+    }
+
+    #[test]
+    fn parse_side_car_name_other_video() {
+        assert_eq!(parse_side_car_name("s01e01", "s01e02.en.srt"), None);
+        assert_eq!(parse_side_car_name("s01e01", "s01e012.srt"), None);
+        assert_eq!(parse_side_car_name("s01e01", "s01e01.txt"), None);
+    }
+
+    #[test]
+    fn label_title_or_language() {
+        let subtitle = Subtitle {
+            title: None,
+            language: Some("en".to_owned()),
+            is_forced: false,
+            ..Faker.fake()
+        };
+        assert_eq!(label(&subtitle), "en");
+        let forced = Subtitle {
+            language: None,
+            is_forced: true,
+            ..subtitle.clone()
+        };
+        assert_eq!(label(&forced), "unknown (forced)");
+        let titled = Subtitle {
+            title: Some("Commentary".to_owned()),
+            ..forced
+        };
+        assert_eq!(label(&titled), "Commentary");
+    }
+
+    #[test]
+    fn decode_legacy_encoding() {
+        assert_eq!(decode("\u{feff}árvíz\r\n".as_bytes()), "árvíz\n");
+        assert_eq!(decode(&[0x74, 0xFC, 0x7A]), "tüz");
+    }
+
+    #[test]
+    fn parse_timestamp_formats() {
+        assert_eq!(parse_timestamp("01:02:03,456"), Some(3_723_456));
+        assert_eq!(parse_timestamp("0:00:01.50"), Some(1500));
+        assert_eq!(parse_timestamp("02:03.004"), Some(123_004));
+        assert_eq!(parse_timestamp("1:02"), Some(62_000));
+        assert_eq!(parse_timestamp("12"), None);
+        assert_eq!(parse_timestamp("00:00:01,"), None);
+        assert_eq!(parse_timestamp("a:00:01,000"), None);
+    }
+
+    #[test]
+    fn srt_to_webvtt_cues() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500 X1:10 X2:20\n<i>Hello</i>\n<font color=\"red\">world</font>\n\n\
+                   2\n00:00:03,000 --> 00:00:04,000\n{\\an8}Top\n\n\
+                   3\nbroken\n";
+        assert_eq!(
+            srt_to_webvtt(srt),
+            "WEBVTT\n\
+             \n00:00:01.000 --> 00:00:02.500\n<i>Hello</i>\nworld\n\
+             \n00:00:03.000 --> 00:00:04.000\nTop\n"
+        );
+    }
+
+    #[test]
+    fn ass_to_webvtt_cues() {
+        let ass = "[Script Info]\nTitle: Test\n\n\
+                   [Events]\n\
+                   Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,{\\i1}Second{\\i0}, line\\Nbreak\n\
+                   Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,ignored\n\
+                   Dialogue: 0,0:00:01.25,0:00:02.00,Default,,0,0,0,,First\n";
+        assert_eq!(
+            ass_to_webvtt(ass),
+            "WEBVTT\n\
+             \n00:00:01.250 --> 00:00:02.000\nFirst\n\
+             \n00:00:05.000 --> 00:00:06.000\nSecond, line\nbreak\n"
+        );
+    }
+
+    #[test]
+    fn to_webvtt_by_format() {
+        assert_eq!(
+            to_webvtt(SubtitleFormat::Vtt, "WEBVTT\n").as_deref(),
+            Some("WEBVTT\n")
+        );
+        assert_eq!(to_webvtt(SubtitleFormat::MovText, ""), None);
+    }
+}
@@ -1,6 +1,9 @@
 use axum::Router;
 use tracing::{info, instrument};
-use utoipa::OpenApi;
+use utoipa::{
+    openapi::{Content, Ref, RefOr},
+    Modify, OpenApi,
+};
 use utoipa_swagger_ui::SwaggerUi;
 
 #[derive(OpenApi)]
@@ -12,10 +15,150 @@ use utoipa_swagger_ui::SwaggerUi;
             url = "https://github.com/szattila98/binge_at_home/blob/main/LICENSE"
         )
     ),
-    paths(crate::api::health_check::health_check)
+    paths(
+        crate::api::health_check::health_check,
+        crate::api::auth::login,
+        crate::api::auth::logout,
+        crate::api::auth::me,
+        crate::api::token::list_tokens,
+        crate::api::token::create_token,
+        crate::api::token::delete_token,
+        crate::api::catalog::list_catalogs,
+        crate::api::catalog::create_catalog,
+        crate::api::catalog::create_catalogs,
+        crate::api::catalog::delete_catalogs,
+        crate::api::catalog::find_catalog,
+        crate::api::catalog::update_catalog,
+        crate::api::catalog::delete_catalog,
+        crate::api::video::list_videos,
+        crate::api::video::list_catalog_videos,
+        crate::api::video::create_video,
+        crate::api::video::create_videos,
+        crate::api::video::delete_videos,
+        crate::api::video::find_video,
+        crate::api::video::update_video,
+        crate::api::video::delete_video,
+        crate::api::stream::stream_video,
+        crate::api::stream::stream_remuxed_video,
+        crate::api::hls::master_playlist,
+        crate::api::hls::rendition_playlist,
+        crate::api::hls::segment,
+        crate::api::artwork::video_thumbnail,
+        crate::api::artwork::preview_track,
+        crate::api::artwork::preview_sheet,
+        crate::api::artwork::catalog_poster,
+        crate::api::artwork::upload_catalog_poster,
+        crate::api::artwork::delete_catalog_poster,
+        crate::api::search::search,
+        crate::api::series::find_series,
+        crate::api::series::find_neighbours,
+        crate::api::series::find_up_next,
+        crate::api::watch_progress::find_progress,
+        crate::api::watch_progress::update_progress,
+        crate::api::watch_progress::delete_progress,
+        crate::api::watch_progress::continue_watching,
+        crate::api::subtitle::list_video_subtitles,
+        crate::api::subtitle::subtitle_track,
+        crate::api::job::list_jobs,
+        crate::api::job::create_job,
+        crate::api::job::find_job,
+        crate::api::job::delete_job,
+        crate::api::library::scan_library,
+        crate::api::user::list_users,
+        crate::api::user::create_user,
+        crate::api::user::find_user,
+        crate::api::user::update_user,
+        crate::api::user::delete_user,
+        crate::api::user::list_user_grants,
+        crate::api::user::replace_user_grants
+    ),
+    components(schemas(
+        crate::api::auth::LoginDto,
+        crate::api::auth::UserDto,
+        crate::api::token::ApiTokenDto,
+        crate::api::token::CreatedApiTokenDto,
+        crate::api::token::CreateApiTokenDto,
+        crate::model::TokenScope,
+        crate::model::UserRole,
+        crate::api::user::CreateUserDto,
+        crate::api::user::UpdateUserDto,
+        crate::api::user::CatalogGrantsDto,
+        crate::api::common::BulkDeleteDto,
+        crate::api::common::BulkDeleteResponse,
+        crate::api::common::CatalogListing,
+        crate::api::common::VideoListing,
+        crate::api::common::UserListing,
+        crate::api::common::JobListing,
+        crate::api::common::CatalogPage,
+        crate::api::common::VideoPage,
+        crate::api::common::UserPage,
+        crate::api::common::JobPage,
+        crate::api::common::Cursors,
+        crate::api::common::CatalogCursorPage,
+        crate::api::common::VideoCursorPage,
+        crate::api::common::UserCursorPage,
+        crate::api::common::JobCursorPage,
+        crate::api::catalog::CatalogDto,
+        crate::api::catalog::CreateCatalogDto,
+        crate::api::catalog::UpdateCatalogDto,
+        crate::api::video::VideoDto,
+        crate::api::video::VideoPayloadDto,
+        crate::api::video::CreateVideoDto,
+        crate::api::video::UpdateVideoDto,
+        crate::api::video::AudioTrackDto,
+        crate::api::stream::QueuedRemuxDto,
+        crate::api::search::SearchHitDto,
+        crate::crud::search::SearchHitKind,
+        crate::api::series::NeighboursDto,
+        crate::api::series::UpNextDto,
+        crate::api::watch_progress::WatchProgressDto,
+        crate::api::watch_progress::UpdateWatchProgressDto,
+        crate::api::watch_progress::ContinueWatchingDto,
+        crate::api::subtitle::SubtitleDto,
+        crate::model::SubtitleFormat,
+        crate::api::job::JobDto,
+        crate::api::job::CreateJobDto,
+        crate::model::TranscodeJobKind,
+        crate::model::TranscodeJobStatus,
+        crate::scanner::ScanSummary,
+        crate::scanner::Changes,
+        crate::error::ErrorResponse,
+        crate::error::ErrorBody,
+        crate::error::FieldError,
+        crate::error::ErrorCode
+    )),
+    modifiers(&ErrorResponses)
 )]
 struct ApiDoc;
 
+/// Documents the body of every error response, they all share the same one.
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let operations = openapi
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|path| path.operations.values_mut());
+        for operation in operations {
+            for (status, response) in &mut operation.responses.responses {
+                let RefOr::T(response) = response else {
+                    continue;
+                };
+                if (status.starts_with('4') || status.starts_with('5'))
+                    && response.content.is_empty()
+                {
+                    response.content.insert(
+                        "application/json".to_owned(),
+                        Content::new(Ref::from_schema_name("ErrorResponse")),
+                    );
+                }
+            }
+        }
+    }
+}
+
 #[instrument(skip_all)]
 pub fn add_swagger_ui(router: Router) -> Router {
     const SWAGGER_PATH: &str = "/swagger-ui";
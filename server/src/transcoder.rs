@@ -0,0 +1,771 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use anyhow::{bail, ensure, Context};
+use tokio::{fs, process::Command, sync::Semaphore};
+use tracing::{debug, info, instrument};
+
+use crate::{
+    artwork,
+    configuration::Configuration,
+    hls::{self, Rendition, AUDIO_BITRATE},
+    logging::{spawn_blocking_with_tracing, Logger},
+    model::{EntityId, Video},
+    previews::SpriteLayout,
+};
+
+/// Distinguishes the temporary files of concurrent transcodes of the same output.
+static TEMPORARY_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The number of bytes kept from the end of the standard error of `ffmpeg`, to explain why it failed.
+const STDERR_TAIL: usize = 4096;
+
+/// Shared between a long running transcode and its owner, to follow its progress and to stop it.
+#[derive(Debug, Clone, Default)]
+pub struct TranscodeControl {
+    /// The bits of an `f64` between 0 and 1.
+    progress: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TranscodeControl {
+    pub fn progress(&self) -> f64 {
+        f64::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    fn set_progress(&self, progress: f64) {
+        self.progress
+            .store(progress.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Produces transcoded media with a local `ffmpeg`, caching the results on the disk.
+#[derive(Debug, Clone)]
+pub struct Transcoder {
+    ffmpeg: PathBuf,
+    cache_dir: PathBuf,
+    permits: Arc<Semaphore>,
+}
+
+#[instrument(skip_all)]
+pub fn init(config: &Configuration, _: &Logger) -> Transcoder {
+    let media = config.media();
+    let transcoder = Transcoder::new(media.ffmpeg(), media.cache_dir(), media.live_transcodes());
+    info!(
+        "initialized transcoder with cache directory '{}'",
+        transcoder.cache_dir.display()
+    );
+    transcoder
+}
+
+impl Transcoder {
+    pub fn new(ffmpeg: PathBuf, cache_dir: PathBuf, concurrency: usize) -> Self {
+        Self {
+            ffmpeg,
+            cache_dir,
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+        }
+    }
+
+    /// The cache directory of a rendition, keyed by the size of the source too, so a replaced file is not served stale segments.
+    fn rendition_dir(&self, video: &Video, rendition: &Rendition) -> PathBuf {
+        self.cache_dir
+            .join("hls")
+            .join(format!("{}-{:x}", video.id, video.size))
+            .join(rendition.name)
+    }
+
+    /// The cached MP4 version of a video, with every audio track or only the given one, see [`Self::remux`].
+    pub fn remux_path(&self, video: &Video, audio: Option<i32>) -> PathBuf {
+        let name = match audio {
+            Some(audio) => format!("{}-{:x}-a{audio}.mp4", video.id, video.size),
+            None => format!("{}-{:x}.mp4", video.id, video.size),
+        };
+        self.cache_dir.join("remux").join(name)
+    }
+
+    /// The cache directory of the thumbnails of a video, keyed by the frame position too, so changing it produces new thumbnails.
+    fn thumbnail_dir(&self, video: &Video, percentage: u8) -> PathBuf {
+        self.cache_dir
+            .join("thumbnails")
+            .join(format!("{}-{:x}-{percentage}", video.id, video.size))
+    }
+
+    /// Returns the path of the thumbnail of a video, a frame at a percentage of its duration, optionally resized to a width.
+    /// The frame is extracted and resized on the first request.
+    #[instrument(skip(self, video), fields(video.id = video.id))]
+    pub async fn thumbnail(
+        &self,
+        video: &Video,
+        percentage: u8,
+        width: Option<u32>,
+    ) -> anyhow::Result<PathBuf> {
+        let dir = self.thumbnail_dir(video, percentage);
+        let frame = dir.join("full.jpg");
+        let timestamp = artwork::thumbnail_timestamp(video.duration, percentage);
+        self.cached_output(&frame, frame_args(&video.path, timestamp))
+            .await?;
+        let Some(width) = width else {
+            return Ok(frame);
+        };
+        let resized = dir.join(format!("{width}.jpg"));
+        self.cached_output(&resized, resize_args(&frame, width))
+            .await?;
+        Ok(resized)
+    }
+
+    /// Returns the path of a catalog poster resized to a width, the source is served as is without one.
+    /// The variants are keyed by the size and modification time of the source, so a replaced poster is resized again.
+    #[instrument(skip(self))]
+    pub async fn poster(
+        &self,
+        catalog_id: EntityId,
+        source: &Path,
+        width: Option<u32>,
+    ) -> anyhow::Result<PathBuf> {
+        let Some(width) = width else {
+            return Ok(source.to_owned());
+        };
+        let metadata = fs::metadata(source).await?;
+        let modified_secs = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        let resized = self
+            .cache_dir
+            .join("posters")
+            .join(format!(
+                "{catalog_id}-{:x}-{modified_secs:x}",
+                metadata.len()
+            ))
+            .join(format!("{width}.jpg"));
+        self.cached_output(&resized, resize_args(source, width))
+            .await?;
+        Ok(resized)
+    }
+
+    /// Returns the path of a sprite sheet of preview frames, generating it first if it is not cached yet.
+    #[instrument(skip(self, video), fields(video.id = video.id))]
+    pub async fn preview_sheet(
+        &self,
+        video: &Video,
+        layout: &SpriteLayout,
+        sheet: i64,
+    ) -> anyhow::Result<PathBuf> {
+        let (start, length) = layout
+            .sheet_bounds(sheet)
+            .context("sprite sheet is out of range")?;
+        let output = self
+            .cache_dir
+            .join("previews")
+            .join(format!("{}-{:x}-{}", video.id, video.size, layout.interval))
+            .join(format!("{sheet}.jpg"));
+        self.cached_output(&output, sprite_args(&video.path, layout, start, length))
+            .await?;
+        Ok(output)
+    }
+
+    /// Returns the path of an embedded subtitle track converted to WebVTT, extracting it first if it is not cached yet.
+    /// The index is the position of the track among the subtitle tracks of the video.
+    #[instrument(skip(self, video), fields(video.id = video.id))]
+    pub async fn subtitle(&self, video: &Video, index: i32) -> anyhow::Result<PathBuf> {
+        let output = self
+            .cache_dir
+            .join("subtitles")
+            .join(format!("{}-{:x}", video.id, video.size))
+            .join(format!("{index}.vtt"));
+        self.cached_output(&output, subtitle_args(&video.path, index))
+            .await?;
+        Ok(output)
+    }
+
+    /// Produces an image or a subtitle with `ffmpeg` unless it is cached already, sharing the permits of the on-demand segments.
+    async fn cached_output(&self, output: &Path, args: Vec<String>) -> anyhow::Result<()> {
+        if fs::try_exists(output).await? {
+            return Ok(());
+        }
+        let _permit = self.permits.acquire().await?;
+        if fs::try_exists(output).await? {
+            return Ok(());
+        }
+        self.run(&args, output).await
+    }
+
+    /// Transcodes every segment of a rendition which is not cached yet.
+    #[instrument(skip(self, video, control), fields(video.id = video.id))]
+    pub async fn hls_rendition(
+        &self,
+        video: &Video,
+        rendition: &Rendition,
+        control: &TranscodeControl,
+    ) -> anyhow::Result<()> {
+        let count = hls::segment_count(video.duration);
+        ensure!(count > 0, "the duration of the video is unknown");
+        for index in 0..count {
+            if control.is_cancelled() {
+                bail!("transcoding was cancelled");
+            }
+            self.hls_segment(video, rendition, index).await?;
+            control.set_progress((index + 1) as f64 / count as f64);
+        }
+        Ok(())
+    }
+
+    /// Copies the video and audio streams of a video into an MP4 container, without re-encoding them.
+    /// Only the given audio track is kept if there is one, so players without track selection play it.
+    #[instrument(skip(self, video, control), fields(video.id = video.id))]
+    pub async fn remux(
+        &self,
+        video: &Video,
+        audio: Option<i32>,
+        control: TranscodeControl,
+    ) -> anyhow::Result<PathBuf> {
+        let output = self.remux_path(video, audio);
+        if fs::try_exists(&output).await? {
+            control.set_progress(1.0);
+            return Ok(output);
+        }
+        let audio_map = audio.map_or_else(|| "0:a?".to_owned(), |audio| format!("0:a:{audio}"));
+        let args = [
+            "-v",
+            "error",
+            "-i",
+            &video.path,
+            "-map",
+            "0:v:0",
+            "-map",
+            &audio_map,
+            "-sn",
+            "-c",
+            "copy",
+            "-movflags",
+            "+faststart",
+            "-progress",
+            "pipe:1",
+            "-nostats",
+            "-f",
+            "mp4",
+        ]
+        .map(ToOwned::to_owned)
+        .to_vec();
+        let ffmpeg = self.ffmpeg.clone();
+        let duration = video.duration;
+        let blocking_output = output.clone();
+        spawn_blocking_with_tracing(move || {
+            run_with_progress(&ffmpeg, &args, &blocking_output, duration, &control)
+        })
+        .await
+        .context("remuxing task failed")??;
+        Ok(output)
+    }
+
+    /// Returns the path of an HLS segment, transcoding it first if it is not cached yet.
+    #[instrument(skip(self, video), fields(video.id = video.id))]
+    pub async fn hls_segment(
+        &self,
+        video: &Video,
+        rendition: &Rendition,
+        index: i64,
+    ) -> anyhow::Result<PathBuf> {
+        let (start, length) =
+            hls::segment_bounds(video.duration, index).context("segment is out of range")?;
+        let output = self
+            .rendition_dir(video, rendition)
+            .join(format!("{index}.ts"));
+        if fs::try_exists(&output).await? {
+            debug!("serving cached segment '{}'", output.display());
+            return Ok(output);
+        }
+
+        let _permit = self.permits.acquire().await?;
+        // another request may have produced it while waiting for the permit
+        if fs::try_exists(&output).await? {
+            return Ok(output);
+        }
+        let is_last = index == hls::segment_count(video.duration) - 1;
+        let mut args = vec![
+            "-v".to_owned(),
+            "error".to_owned(),
+            "-ss".to_owned(),
+            start.to_string(),
+            "-i".to_owned(),
+            video.path.clone(),
+        ];
+        if !is_last {
+            args.extend(["-t".to_owned(), length.to_string()]);
+        }
+        args.extend(segment_args(rendition, start));
+        self.run(&args, &output).await?;
+        Ok(output)
+    }
+
+    /// Runs `ffmpeg` with the given arguments, writing a temporary file which is renamed to `output` on success.
+    async fn run(&self, args: &[String], output: &Path) -> anyhow::Result<()> {
+        let parent = output.parent().context("output has no parent directory")?;
+        fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("could not create directory '{}'", parent.display()))?;
+        let temporary = temporary_path(output);
+
+        debug!("running ffmpeg with {args:?}");
+        let result = Command::new(&self.ffmpeg)
+            .args(args)
+            .arg("-y")
+            .arg(&temporary)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .with_context(|| format!("could not run '{}'", self.ffmpeg.display()));
+        let result = result.and_then(|result| {
+            ensure!(
+                result.status.success(),
+                "ffmpeg failed: {}",
+                String::from_utf8_lossy(&result.stderr).trim()
+            );
+            Ok(())
+        });
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temporary).await;
+            return Err(e);
+        }
+
+        fs::rename(&temporary, output).await?;
+        Ok(())
+    }
+}
+
+/// A unique path next to `output` to write it to, so it only appears once it is complete.
+pub fn temporary_path(output: &Path) -> PathBuf {
+    let counter = TEMPORARY_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    output.with_extension(format!("{counter}.part"))
+}
+
+/// Runs `ffmpeg` to completion on the current thread, following its progress on its standard output.
+/// The process is killed when the transcode is cancelled.
+fn run_with_progress(
+    ffmpeg: &Path,
+    args: &[String],
+    output: &Path,
+    duration: i64,
+    control: &TranscodeControl,
+) -> anyhow::Result<()> {
+    let parent = output.parent().context("output has no parent directory")?;
+    std::fs::create_dir_all(parent)
+        .with_context(|| format!("could not create directory '{}'", parent.display()))?;
+    let temporary = temporary_path(output);
+
+    debug!("running ffmpeg with {args:?}");
+    let mut child = process::Command::new(ffmpeg)
+        .args(args)
+        .arg("-y")
+        .arg(&temporary)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("could not run '{}'", ffmpeg.display()))?;
+
+    // drained while the progress is read, ffmpeg blocks once it fills the pipe otherwise
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| thread::spawn(move || read_tail(pipe, STDERR_TAIL)));
+
+    let result = (|| {
+        let stdout = child
+            .stdout
+            .take()
+            .context("ffmpeg has no standard output")?;
+        for line in BufReader::new(stdout).lines() {
+            if control.is_cancelled() {
+                child.kill()?;
+                bail!("transcoding was cancelled");
+            }
+            if let Some(progress) = parse_progress(&line?, duration) {
+                control.set_progress(progress);
+            }
+        }
+        Ok(child.wait()?)
+    })();
+    if result.is_err() {
+        let _ = child.wait();
+    }
+    let stderr = stderr
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    let result = result.and_then(|status| {
+        ensure!(status.success(), "ffmpeg failed: {}", stderr.trim());
+        Ok(())
+    });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temporary);
+        return Err(e);
+    }
+
+    std::fs::rename(&temporary, output)?;
+    control.set_progress(1.0);
+    Ok(())
+}
+
+/// Reads a pipe to its end, keeping only its last `limit` bytes.
+fn read_tail(mut pipe: impl Read, limit: usize) -> String {
+    let mut tail = Vec::with_capacity(limit);
+    let mut buffer = [0; 4096];
+    loop {
+        let read = match pipe.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        tail.extend_from_slice(&buffer[..read]);
+        if tail.len() > limit {
+            tail.drain(..tail.len() - limit);
+        }
+    }
+    String::from_utf8_lossy(&tail).into_owned()
+}
+
+/// Parses a line of the `-progress` output of `ffmpeg` into the processed fraction of the duration.
+fn parse_progress(line: &str, duration: i64) -> Option<f64> {
+    // despite its name, the value is in microseconds
+    let (key, value) = line.split_once('=')?;
+    if key != "out_time_us" && key != "out_time_ms" {
+        return None;
+    }
+    let micros = value.trim().parse::<i64>().ok()?;
+    (duration > 0).then(|| micros as f64 / 1_000_000.0 / duration as f64)
+}
+
+/// The arguments extracting a single JPEG frame at a timestamp, seeking on the input so it stays fast for long videos.
+fn frame_args(path: &str, timestamp: f64) -> Vec<String> {
+    [
+        "-v",
+        "error",
+        "-ss",
+        &format!("{timestamp:.3}"),
+        "-i",
+        path,
+        "-map",
+        "0:v:0",
+        "-frames:v",
+        "1",
+        "-q:v",
+        "2",
+    ]
+    .into_iter()
+    .chain(JPEG_OUTPUT_ARGS)
+    .map(ToOwned::to_owned)
+    .collect()
+}
+
+/// The arguments resizing an image to a width, keeping its aspect ratio, it is never upscaled.
+fn resize_args(source: &Path, width: u32) -> Vec<String> {
+    [
+        "-v",
+        "error",
+        "-i",
+        &source.to_string_lossy(),
+        "-vf",
+        &format!("scale='min(iw,{width})':-2"),
+        "-frames:v",
+        "1",
+        "-q:v",
+        "3",
+    ]
+    .into_iter()
+    .chain(JPEG_OUTPUT_ARGS)
+    .map(ToOwned::to_owned)
+    .collect()
+}
+
+/// The arguments tiling the preview frames of a part of a video into a sprite sheet.
+/// Only key frames are decoded, which makes it much faster at the cost of slightly shifted frames.
+fn sprite_args(path: &str, layout: &SpriteLayout, start: i64, length: i64) -> Vec<String> {
+    let grid_size = layout.grid_size();
+    [
+        "-v",
+        "error",
+        "-skip_frame",
+        "nokey",
+        "-ss",
+        &start.to_string(),
+        "-t",
+        &length.to_string(),
+        "-i",
+        path,
+        "-map",
+        "0:v:0",
+        "-vf",
+        &format!(
+            "fps=1/{},scale={}:{},tile={grid_size}x{grid_size}",
+            layout.interval, layout.tile_width, layout.tile_height
+        ),
+        "-frames:v",
+        "1",
+        "-q:v",
+        "5",
+    ]
+    .into_iter()
+    .chain(JPEG_OUTPUT_ARGS)
+    .map(ToOwned::to_owned)
+    .collect()
+}
+
+/// The arguments converting an embedded subtitle track to WebVTT.
+fn subtitle_args(path: &str, index: i32) -> Vec<String> {
+    [
+        "-v",
+        "error",
+        "-i",
+        path,
+        "-map",
+        &format!("0:s:{index}"),
+        "-c:s",
+        "webvtt",
+        "-f",
+        "webvtt",
+    ]
+    .into_iter()
+    .map(ToOwned::to_owned)
+    .collect()
+}
+
+/// The output format is explicit, as it cannot be guessed from the extension of the temporary file.
+const JPEG_OUTPUT_ARGS: [&str; 6] = ["-c:v", "mjpeg", "-update", "1", "-f", "image2"];
+
+/// The encoding arguments of a segment, the timestamps are shifted to the segment start, so segments play back continuously.
+fn segment_args(rendition: &Rendition, start: i64) -> Vec<String> {
+    let video_bitrate = rendition.video_bitrate;
+    [
+        "-map",
+        "0:v:0",
+        "-map",
+        "0:a:0?",
+        "-vf",
+        &format!("scale=-2:'min(ih,{})'", rendition.height),
+        "-c:v",
+        "libx264",
+        "-preset",
+        "veryfast",
+        "-b:v",
+        &video_bitrate.to_string(),
+        "-maxrate",
+        &video_bitrate.to_string(),
+        "-bufsize",
+        &(video_bitrate * 2).to_string(),
+        "-c:a",
+        "aac",
+        "-ac",
+        "2",
+        "-b:a",
+        &AUDIO_BITRATE.to_string(),
+        "-output_ts_offset",
+        &start.to_string(),
+        "-muxdelay",
+        "0",
+        "-f",
+        "mpegts",
+    ]
+    .map(ToOwned::to_owned)
+    .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, Faker};
+    use pretty_assertions::assert_eq;
+
+    fn rendition() -> Rendition {
+        Rendition {
+            name: "720p",
+            width: 1280,
+            height: 720,
+            video_bitrate: 2_800_000,
+        }
+    }
+
+    #[test]
+    fn rendition_dir_keyed_by_id_and_size() {
+        let transcoder = Transcoder::new("ffmpeg".into(), "/cache".into(), 1);
+        let video = Video {
+            id: 7,
+            size: 255,
+            ..Faker.fake()
+        };
+        assert_eq!(
+            transcoder.rendition_dir(&video, &rendition()),
+            PathBuf::from("/cache/hls/7-ff/720p")
+        );
+    }
+
+    #[test]
+    fn remux_path_keyed_by_audio_track() {
+        let transcoder = Transcoder::new("ffmpeg".into(), "/cache".into(), 1);
+        let video = Video {
+            id: 7,
+            size: 255,
+            ..Faker.fake()
+        };
+        assert_eq!(
+            transcoder.remux_path(&video, None),
+            PathBuf::from("/cache/remux/7-ff.mp4")
+        );
+        assert_eq!(
+            transcoder.remux_path(&video, Some(2)),
+            PathBuf::from("/cache/remux/7-ff-a2.mp4")
+        );
+    }
+
+    #[test]
+    fn segment_args_scale_and_offset() {
+        let args = segment_args(&rendition(), 42);
+        let value_of = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .map(|position| args[position + 1].as_str())
+        };
+        assert_eq!(value_of("-vf"), Some("scale=-2:'min(ih,720)'"));
+        assert_eq!(value_of("-b:v"), Some("2800000"));
+        assert_eq!(value_of("-bufsize"), Some("5600000"));
+        assert_eq!(value_of("-output_ts_offset"), Some("42"));
+        assert_eq!(args.last().map(String::as_str), Some("mpegts"));
+    }
+
+    #[test]
+    fn parse_progress_out_time() {
+        assert_eq!(parse_progress("out_time_us=5000000", 10), Some(0.5));
+        assert_eq!(parse_progress("out_time_ms=2500000", 10), Some(0.25));
+        assert_eq!(parse_progress("out_time_us=N/A", 10), None);
+        assert_eq!(parse_progress("frame=100", 10), None);
+        assert_eq!(parse_progress("out_time_us=5000000", 0), None);
+    }
+
+    #[test]
+    fn read_tail_keeps_end_of_pipe() {
+        let output = "a".repeat(10_000) + "the last line";
+        assert_eq!(read_tail(output.as_bytes(), 13), "the last line");
+        assert_eq!(read_tail("short".as_bytes(), 13), "short");
+    }
+
+    #[test]
+    fn run_with_progress_survives_verbose_stderr() {
+        use std::{os::unix::fs::PermissionsExt, sync::mpsc, time::Duration};
+
+        let dir = std::env::temp_dir().join(format!("binge-ffmpeg-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // more than a pipe buffer of errors before failing, like a corrupt input
+        let ffmpeg = dir.join("ffmpeg");
+        std::fs::write(
+            &ffmpeg,
+            "#!/bin/sh\nhead -c 1048576 /dev/zero | tr '\\0' x >&2\necho ' corrupt packet' >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let output = dir.join("out.mp4");
+        let script = ffmpeg.clone();
+        std::thread::spawn(move || {
+            let result = run_with_progress(&script, &[], &output, 10, &TranscodeControl::default());
+            let _ = sender.send(result);
+        });
+        let result = receiver.recv_timeout(Duration::from_secs(30));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let error = result
+            .expect("ffmpeg blocked on its standard error")
+            .unwrap_err();
+        let message = error.to_string();
+        assert!(message.ends_with("x corrupt packet"), "{message}");
+        assert!(message.len() < STDERR_TAIL + 100);
+    }
+
+    #[test]
+    fn control_progress_clamped() {
+        let control = TranscodeControl::default();
+        assert_eq!(control.progress(), 0.0);
+        control.set_progress(1.5);
+        assert_eq!(control.progress(), 1.0);
+        assert!(!control.is_cancelled());
+        control.clone().cancel();
+        assert!(control.is_cancelled());
+    }
+
+    #[test]
+    fn thumbnail_dir_keyed_by_id_size_and_position() {
+        let transcoder = Transcoder::new("ffmpeg".into(), "/cache".into(), 1);
+        let video = Video {
+            id: 3,
+            size: 16,
+            ..Faker.fake()
+        };
+        assert_eq!(
+            transcoder.thumbnail_dir(&video, 25),
+            PathBuf::from("/cache/thumbnails/3-10-25")
+        );
+    }
+
+    #[test]
+    fn image_args_seek_and_scale() {
+        let args = frame_args("/movies/a.mkv", 62.5);
+        assert_eq!(&args[2..6], ["-ss", "62.500", "-i", "/movies/a.mkv"]);
+        assert_eq!(args.last().map(String::as_str), Some("image2"));
+
+        let args = resize_args(Path::new("/cache/full.jpg"), 320);
+        assert!(args.contains(&"scale='min(iw,320)':-2".to_owned()));
+        assert_eq!(args.last().map(String::as_str), Some("image2"));
+    }
+
+    #[test]
+    fn sprite_args_tile_part_of_video() {
+        let video = Video {
+            duration: 1500,
+            width: 1920,
+            height: 1080,
+            ..Faker.fake()
+        };
+        let layout = SpriteLayout::new(&video, 10).unwrap();
+        let args = sprite_args("/movies/a.mkv", &layout, 1000, 500);
+        assert_eq!(&args[4..8], ["-ss", "1000", "-t", "500"]);
+        assert!(args.contains(&"fps=1/10,scale=160:90,tile=10x10".to_owned()));
+    }
+
+    #[test]
+    fn subtitle_args_map_track() {
+        let args = subtitle_args("/movies/a.mkv", 2);
+        assert_eq!(&args[4..6], ["-map", "0:s:2"]);
+        assert_eq!(args.last().map(String::as_str), Some("webvtt"));
+    }
+
+    #[tokio::test]
+    async fn hls_segment_out_of_range_fails() {
+        let transcoder = Transcoder::new("ffmpeg".into(), std::env::temp_dir(), 1);
+        let video = Video {
+            duration: 10,
+            ..Faker.fake()
+        };
+        assert!(transcoder
+            .hls_segment(&video, &rendition(), 2)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    mem,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
+
+use crate::{configuration::Configuration, logging::Logger, scanner::Scanner};
+
+/// How often the pending changes are checked for ones that settled.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Collects the file system events of the watched roots until the touched paths settle.
+#[derive(Debug)]
+struct PendingChanges {
+    roots: Vec<PathBuf>,
+    debounce: Duration,
+    moves: Vec<(PathBuf, PathBuf)>,
+    touched: HashMap<PathBuf, Instant>,
+    rescan: bool,
+}
+
+impl PendingChanges {
+    fn new(roots: Vec<PathBuf>, debounce: Duration) -> Self {
+        Self {
+            roots,
+            debounce,
+            moves: vec![],
+            touched: HashMap::new(),
+            rescan: false,
+        }
+    }
+
+    fn record(&mut self, event: Event, now: Instant) {
+        if event.need_rescan() {
+            self.rescan = true;
+            return;
+        }
+        match event.kind {
+            EventKind::Access(_) => {}
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let [from, to] = &event.paths[..] {
+                    match (self.is_hidden(from), self.is_hidden(to)) {
+                        (false, false) => self.moves.push((from.clone(), to.clone())),
+                        (true, false) => self.touch(to.clone(), now),
+                        (false, true) => self.touch(from.clone(), now),
+                        (true, true) => {}
+                    }
+                }
+            }
+            _ => {
+                for path in event.paths {
+                    if !self.is_hidden(&path) {
+                        self.touch(path, now);
+                    }
+                }
+            }
+        }
+    }
+
+    fn touch(&mut self, path: PathBuf, now: Instant) {
+        self.touched.insert(path, now);
+    }
+
+    /// Whether the path is hidden under its root, these are skipped by the scanner too.
+    fn is_hidden(&self, path: &Path) -> bool {
+        self.roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .is_none_or(|relative| {
+                relative.components().any(|component| {
+                    component
+                        .as_os_str()
+                        .to_str()
+                        .is_none_or(|name| name.starts_with('.'))
+                })
+            })
+    }
+
+    /// Takes every move and the paths that were not touched for the debounce duration.
+    fn take_settled(&mut self, now: Instant) -> (Vec<(PathBuf, PathBuf)>, Vec<PathBuf>) {
+        let settled = self
+            .touched
+            .iter()
+            .filter(|(_, touched_at)| now.duration_since(**touched_at) >= self.debounce)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        for path in &settled {
+            self.touched.remove(path);
+        }
+        (mem::take(&mut self.moves), settled)
+    }
+}
+
+/// Starts watching the configured library roots, applying their changes incrementally with the scanner.
+#[instrument(skip_all)]
+pub fn init(config: &Configuration, scanner: Scanner, _: &Logger) {
+    let library_roots = config.library().roots();
+    let roots = config
+        .library()
+        .watcher()
+        .roots()
+        .into_iter()
+        .filter(|root| {
+            let is_library_root = library_roots.contains(root);
+            if !is_library_root {
+                warn!(
+                    "not watching '{}', as it is not a library root",
+                    root.display()
+                );
+            }
+            is_library_root
+        })
+        .collect::<Vec<_>>();
+    if roots.is_empty() {
+        return;
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event| {
+        // the receiver is only dropped when the server stops
+        let _ = sender.send(event);
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("could not create library watcher: {e}");
+            return;
+        }
+    };
+
+    let mut watched = vec![];
+    for root in roots {
+        match watcher.watch(&root, RecursiveMode::Recursive) {
+            Ok(()) => watched.push(root),
+            Err(e) => warn!("could not watch library root '{}': {e}", root.display()),
+        }
+    }
+    if watched.is_empty() {
+        return;
+    }
+    info!("watching library roots: {watched:?}");
+
+    let pending = PendingChanges::new(watched, config.library().watcher().debounce());
+    tokio::spawn(
+        watch(watcher, receiver, pending, scanner).instrument(info_span!("library_watcher")),
+    );
+}
+
+async fn watch(
+    // dropping the watcher stops the watching
+    _watcher: RecommendedWatcher,
+    mut receiver: mpsc::UnboundedReceiver<notify::Result<Event>>,
+    mut pending: PendingChanges,
+    scanner: Scanner,
+) {
+    let mut ticker = tokio::time::interval(TICK);
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Some(Ok(event)) => {
+                    debug!("file system event: {event:?}");
+                    pending.record(event, Instant::now());
+                }
+                Some(Err(e)) => warn!("library watcher error: {e}"),
+                None => break,
+            },
+            _ = ticker.tick() => {
+                if mem::take(&mut pending.rescan) {
+                    info!("file system events were lost, scanning the whole library");
+                    if let Some(Err(e)) = scanner.try_scan().await {
+                        error!("library scan failed: {e:#}");
+                    }
+                }
+                let (moves, touched) = pending.take_settled(Instant::now());
+                if moves.is_empty() && touched.is_empty() {
+                    continue;
+                }
+                match scanner.apply_changes(moves, touched).await {
+                    Ok(summary) => info!("applied library changes: {summary:?}"),
+                    Err(e) => error!("could not apply library changes: {e:#}"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+    use pretty_assertions::assert_eq;
+
+    const DEBOUNCE: Duration = Duration::from_secs(10);
+
+    fn pending() -> PendingChanges {
+        PendingChanges::new(vec![PathBuf::from("/media")], DEBOUNCE)
+    }
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths
+            .iter()
+            .fold(Event::new(kind), |event, path| event.add_path(path.into()))
+    }
+
+    #[test]
+    fn touched_paths_settle_after_debounce() {
+        let mut pending = pending();
+        let start = Instant::now();
+        pending.record(
+            event(
+                EventKind::Create(CreateKind::File),
+                &["/media/movies/a.mkv"],
+            ),
+            start,
+        );
+        pending.record(
+            event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+                &["/media/movies/a.mkv"],
+            ),
+            start + Duration::from_secs(5),
+        );
+
+        assert_eq!(pending.take_settled(start + DEBOUNCE), (vec![], vec![]));
+        assert_eq!(
+            pending.take_settled(start + Duration::from_secs(15)),
+            (vec![], vec![PathBuf::from("/media/movies/a.mkv")])
+        );
+        assert!(pending.touched.is_empty());
+    }
+
+    #[test]
+    fn renames_are_moves_unless_hidden() {
+        let mut pending = pending();
+        let now = Instant::now();
+        let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        pending.record(event(rename, &["/media/a.mkv", "/media/b.mkv"]), now);
+        pending.record(event(rename, &["/media/.c.part", "/media/c.mkv"]), now);
+        pending.record(event(rename, &["/media/d.mkv", "/media/.trash/d.mkv"]), now);
+
+        let (moves, mut touched) = pending.take_settled(now + DEBOUNCE);
+        touched.sort();
+
+        assert_eq!(
+            moves,
+            vec![(PathBuf::from("/media/a.mkv"), PathBuf::from("/media/b.mkv"))]
+        );
+        assert_eq!(
+            touched,
+            vec![PathBuf::from("/media/c.mkv"), PathBuf::from("/media/d.mkv")]
+        );
+    }
+
+    #[test]
+    fn hidden_and_access_events_are_ignored() {
+        let mut pending = pending();
+        let now = Instant::now();
+        pending.record(
+            event(
+                EventKind::Remove(RemoveKind::File),
+                &["/media/.hidden/a.mkv"],
+            ),
+            now,
+        );
+        pending.record(
+            event(EventKind::Remove(RemoveKind::File), &["/elsewhere/a.mkv"]),
+            now,
+        );
+        pending.record(
+            event(
+                EventKind::Access(notify::event::AccessKind::Any),
+                &["/media/a.mkv"],
+            ),
+            now,
+        );
+        assert!(pending.touched.is_empty());
+    }
+
+    #[test]
+    fn rescan_flag_is_recorded() {
+        let mut pending = pending();
+        pending.record(
+            Event::new(EventKind::Other).set_flag(notify::event::Flag::Rescan),
+            Instant::now(),
+        );
+        assert!(pending.rescan);
+        assert!(pending.touched.is_empty());
+    }
+}